@@ -52,6 +52,11 @@ pub fn detect_sys_conf() -> SysConf {
     sys_conf.rt_period = get_sched_rt_period_us();
     sys_conf.rt_runtime = get_sched_rt_runtime_us();
 
+    if sys_conf.dl_overcommitted() {
+        eprintln!("WARNING: the SCHED_DEADLINE reservations ({:.2} cores) exceed the capacity available to real-time threads ({:.2} cores).",
+                  sys_conf.dl_reserved_utilization(), sys_conf.rt_capacity());
+    }
+
     if kernel_config_active("CONFIG_SCHED_DEBUG") {
         sys_conf.rt_runtime_is_global = sched_feat_active("RT_RUNTIME_SHARE");
         sys_conf.rt_runtime_is_greedy = sched_feat_active("RT_RUNTIME_GREED");
@@ -1,25 +1,79 @@
 use std::collections::HashSet;
-use std::iter::FromIterator;
 
 use rbftrace_core::sys_conf::{Pid, Cluster, MultiprocType, Cpu};
 
 use crate::system::{get_rt_pids, all_cpu_mask_vec, filter_ht_pinned_kthreads, filter_unmovable_pinned_kthreads, get_affinity};
 
+/* LIVE DETECTION */
+/* These functions probe the running system, then classify the probed affinities */
+
 pub fn get_multiproc_type() -> MultiprocType {
     let rt_pids = filter_ht_pinned_kthreads(&get_rt_pids());
     if empty_mask_present(&rt_pids) {
-        MultiprocType::ERROR
+        return MultiprocType::ERROR;
+    }
+
+    classify(&get_affinities(&rt_pids),
+             &get_affinities(&filter_unmovable_pinned_kthreads(&rt_pids)),
+             &all_cpu_mask_vec())
+}
+
+/* Global contraints are slightly relaxed: unmovable pinned kthreads are not considered */
+pub fn check_global(rt_pids: &[Pid]) -> bool {
+    is_global(&get_affinities(&filter_unmovable_pinned_kthreads(rt_pids)), &all_cpu_mask_vec())
+}
+
+pub fn check_partitioned(rt_pids: &[Pid]) -> bool {
+    is_partitioned(&get_affinities(rt_pids))
+}
+
+pub fn check_clustered(rt_pids: &[Pid], fixed_cluster_size: bool) -> Option<Vec<Cluster>> {
+    clusters_from_affinities(&get_affinities(&filter_unmovable_pinned_kthreads(rt_pids)), fixed_cluster_size)
+}
+
+pub fn empty_mask_present(rt_pids: &[Pid]) -> bool {
+    if let Some(pid) = empty_mask_pid(&get_affinities(rt_pids)) {
+        eprintln!("WARNING: Process {} has an empty affinity mask! Please assign an affinity mask. \
+        Did you disable hyperthreading without reassigning affinity masks afterwards?", pid);
+        return true;
     }
-    else if check_global(&rt_pids) {
+
+    false
+}
+
+fn get_affinities(pids: &[Pid]) -> Vec<(Pid, Vec<Cpu>)> {
+    pids.iter()
+        .map(|pid| (*pid, get_affinity(pid).unwrap()))
+        .collect()
+}
+
+/* OFFLINE CLASSIFICATION */
+/* These functions only look at the affinities they are given, so they can be used on a recorded system configuration */
+
+/// Classifies a system from the affinity masks of its real-time threads, with CPUs numbered from 0 to n_cores-1.
+/// Unlike the live detection, pinned kthreads can't be told apart from the other threads, so they are not filtered out.
+pub fn classify_from_affinities(affinities: &[(Pid, Vec<Cpu>)], n_cores: u32) -> MultiprocType {
+    if empty_mask_pid(affinities).is_some() {
+        return MultiprocType::ERROR;
+    }
+
+    let all_cpus: Vec<Cpu> = (0..n_cores).collect();
+
+    classify(affinities, affinities, &all_cpus)
+}
+
+/// `movable_affinities` excludes the unmovable pinned kthreads, which are ignored by the global and clustered checks
+fn classify(affinities: &[(Pid, Vec<Cpu>)], movable_affinities: &[(Pid, Vec<Cpu>)], all_cpus: &[Cpu]) -> MultiprocType {
+    if is_global(movable_affinities, all_cpus) {
         MultiprocType::GLOBAL
-    } 
-    else if check_partitioned(&rt_pids) {
+    }
+    else if is_partitioned(affinities) {
         MultiprocType::PARTITIONED
     }
-    else if check_clustered(&rt_pids, true).is_some() {
+    else if clusters_from_affinities(movable_affinities, true).is_some() {
         MultiprocType::CLUSTERED
     }
-    else if check_clustered(&rt_pids, false).is_some() {
+    else if clusters_from_affinities(movable_affinities, false).is_some() {
         MultiprocType::CLUSTEREDNF
     }
     else {
@@ -27,67 +81,104 @@ pub fn get_multiproc_type() -> MultiprocType {
     }
 }
 
-/* Global contraints are slightly relaxed: unmovable pinned kthreads are not considered */
-pub fn check_global(rt_pids: &[Pid]) -> bool {
-    let all_cpu_vec = all_cpu_mask_vec();
-    
-    for pid in filter_unmovable_pinned_kthreads(rt_pids) {
-        let affinity = get_affinity(&pid).unwrap();
-        if affinity != all_cpu_vec {
-            return false;
-        }
-    }
+pub fn is_global(affinities: &[(Pid, Vec<Cpu>)], all_cpus: &[Cpu]) -> bool {
+    let all_cpus: HashSet<&Cpu> = all_cpus.iter().collect();
 
-    true
+    affinities.iter()
+              .all(|(_, affinity)| affinity.iter().collect::<HashSet<_>>() == all_cpus)
 }
 
-pub fn check_partitioned(rt_pids: &[Pid]) -> bool {
-    for pid in rt_pids {
-        let affinity = get_affinity(pid).unwrap();
-
-        if affinity.len() != 1 {
-            return false;
-        }
-    }
-
-    true
+pub fn is_partitioned(affinities: &[(Pid, Vec<Cpu>)]) -> bool {
+    affinities.iter()
+              .all(|(_, affinity)| affinity.len() == 1)
 }
 
-pub fn check_clustered(rt_pids: &[Pid], fixed_cluster_size: bool) -> Option<Vec<Cluster>> {
+/// Returns the clusters if every couple of affinity masks is either disjoint or equal.
+/// If `fixed_cluster_size` is set, every cluster must also have the same number of CPUs.
+pub fn clusters_from_affinities(affinities: &[(Pid, Vec<Cpu>)], fixed_cluster_size: bool) -> Option<Vec<Cluster>> {
     let mut cluster_set: Vec<HashSet<Cpu>> = Vec::new();
     let mut ret: Vec<Cluster> = Vec::new();
-    let rt_pids_filtered = filter_unmovable_pinned_kthreads(rt_pids);
-    let cluster_size = get_affinity(&rt_pids_filtered[0]).unwrap().len();
-    for pid in rt_pids_filtered {
-        let affinity = get_affinity(&pid).unwrap();
+    let cluster_size = affinities.first().map_or(0, |(_, affinity)| affinity.len());
+    for (_, affinity) in affinities {
         if fixed_cluster_size && affinity.len() != cluster_size {
             return None;
         }
-        cluster_set.push(affinity.into_iter().collect());
+        let cluster: HashSet<Cpu> = affinity.iter().copied().collect();
+        if !cluster_set.contains(&cluster) {
+            cluster_set.push(cluster);
+        }
     }
-    cluster_set.dedup();
 
     /* All sets couples must be either disjointed or equal */
     for (cluster_idx, i) in cluster_set.iter().enumerate() {
         for j in &cluster_set {
-            if !i.is_disjoint(j) && !i.symmetric_difference(j).collect::<HashSet<_>>().is_empty() {
+            if !i.is_disjoint(j) && i != j {
                 return None;
             }
         }
-        ret.push(Cluster::new(cluster_idx as u32, Vec::from_iter(i.clone()), Vec::new()));
+        let mut cpus: Vec<Cpu> = i.iter().copied().collect();
+        cpus.sort_unstable();
+        ret.push(Cluster::new(cluster_idx as u32, cpus, Vec::new()));
     }
 
     Some(ret)
 }
 
-pub fn empty_mask_present(rt_pids: &[Pid]) -> bool {
-    for pid in rt_pids {
-        let affinity = get_affinity(pid);
-        if affinity.unwrap().is_empty() {
-            eprintln!("WARNING: Process {} has an empty affinity mask! Please assign an affinity mask. \
-            Did you disable hyperthreading without reassigning affinity masks afterwards?", pid);
-            return true;
-        } 
+pub fn empty_mask_pid(affinities: &[(Pid, Vec<Cpu>)]) -> Option<Pid> {
+    affinities.iter()
+              .find(|(_, affinity)| affinity.is_empty())
+              .map(|(pid, _)| *pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global() {
+        let affinities = [(1, vec![0, 1, 2, 3]), (2, vec![3, 2, 1, 0])];
+
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::GLOBAL);
+    }
+
+    #[test]
+    fn partitioned() {
+        let affinities = [(1, vec![0]), (2, vec![1]), (3, vec![1])];
+
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::PARTITIONED);
+    }
+
+    #[test]
+    fn clustered() {
+        let affinities = [(1, vec![0, 1]), (2, vec![2, 3]), (3, vec![0, 1])];
+
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::CLUSTERED);
+
+        let clusters = clusters_from_affinities(&affinities, true).unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].cpus, [0, 1]);
+        assert_eq!(clusters[1].cpus, [2, 3]);
+    }
+
+    #[test]
+    fn clustered_non_fixed() {
+        let affinities = [(1, vec![0]), (2, vec![1, 2, 3])];
+
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::CLUSTEREDNF);
+    }
+
+    #[test]
+    fn apa() {
+        let affinities = [(1, vec![0, 1]), (2, vec![1, 2])];
+
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::APA);
+    }
+
+    #[test]
+    fn empty_mask() {
+        let affinities = [(1, vec![0, 1]), (2, vec![])];
+
+        assert_eq!(empty_mask_pid(&affinities), Some(2));
+        assert_eq!(classify_from_affinities(&affinities, 4), MultiprocType::ERROR);
     }
-    false
 }
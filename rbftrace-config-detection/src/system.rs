@@ -1,9 +1,12 @@
 use std::mem::size_of;
 use nc;
 use std::fs::*;
+use std::path::Path;
 
 use rbftrace_core::sys_conf::*;
 use rbftrace_core::util::*;
+use rbftrace_core::model::{DeadlineTask, SystemModel};
+use rbftrace_core::time::Time;
 
 pub fn get_cpu_topology() -> Vec<Core> {
     let to_parse = run_cmd("lscpu -p=core,cpu | sed '1,4d' | sort".to_string());
@@ -50,6 +53,30 @@ pub fn get_rt_pids() -> Vec<Pid> {
     get_pids_with_policy(vec!(SchedPolicy::FIFO, SchedPolicy::RR), false)
 }
 
+/// Files listing the members of a cgroup, by order of preference: threads are preferred,
+/// since events are traced per thread (cgroup v2 threaded cgroups, then cgroup v1)
+const CGROUP_MEMBER_FILES: [&str; 3] = ["cgroup.threads", "tasks", "cgroup.procs"];
+
+/// Returns the (sorted) pids of the threads in the cgroup at `cgroup_dir`, e.g. /sys/fs/cgroup/mygroup.
+/// If the cgroup only lists processes (cgroup.procs), their pids are returned.
+pub fn get_cgroup_pids<P: AsRef<Path>>(cgroup_dir: P) -> std::io::Result<Vec<Pid>> {
+    let path = CGROUP_MEMBER_FILES.iter()
+        .map(|file| cgroup_dir.as_ref().join(file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "not a cgroup directory"))?;
+
+    let mut ret = Vec::new();
+    for line in read_to_string(path)?.lines() {
+        let pid = line.trim().parse::<Pid>()
+                      .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        ret.push(pid);
+    }
+    ret.sort_unstable();
+    ret.dedup();
+
+    Ok(ret)
+}
+
 pub fn get_pids_with_policy(policies: Vec<SchedPolicy>, print: bool) -> Vec<Pid> {
     let mut ret_pids = Vec::new();
     let all_pids = run_cmd("ps -A -L -o lwp=".to_string()); // Includes threads (-L and lwp)
@@ -319,16 +346,61 @@ pub fn get_sched_rt_runtime_us() -> i32 {
     return read_to_string("/proc/sys/kernel/sched_rt_runtime_us").unwrap().trim().parse::<i32>().unwrap();
 }
 
+/// Reads the attributes of the SCHED_DEADLINE threads once, to record their reservations and slack reclamation
 pub fn detect_dl_slack(sys_conf: &mut SysConf) {
-    let mut attrbuf = default_attr_t();
-    for pid in &sys_conf.dl_pids {
-        if let Err(e) = nc::sched_getattr(*pid as nc::pid_t, &mut attrbuf, size_of::<nc::sched_attr_t>() as u32, 0x0) {
+    for pid in sys_conf.dl_pids.clone() {
+        let mut attrbuf = default_attr_t();
+        if let Err(e) = nc::sched_getattr(pid as nc::pid_t, &mut attrbuf, size_of::<nc::sched_attr_t>() as u32, 0x0) {
             eprintln!("[DL] {} failed to get scheduling attributes, errno: {}", pid, e);
+            continue;
         };
-        if (attrbuf.sched_flags & nc::SCHED_FLAG_RECLAIM as u64) == nc::SCHED_FLAG_RECLAIM  as u64 {
-            sys_conf.dl_slack_rec_pids.push(*pid);
+        record_dl_attr(sys_conf, pid, &attrbuf);
+    }
+}
+
+/// Records the reservation of a SCHED_DEADLINE thread, and whether it reclaims slack
+pub fn record_dl_attr(sys_conf: &mut SysConf, pid: Pid, attr: &nc::sched_attr_t) {
+    if (attr.sched_flags & nc::SCHED_FLAG_RECLAIM as u64) == nc::SCHED_FLAG_RECLAIM  as u64 {
+        sys_conf.dl_slack_rec_pids.push(pid);
+    }
+    if let Some(task) = deadline_task_from_attr(attr) {
+        sys_conf.dl_reservations.insert(pid, task);
+    }
+}
+
+/// Reads the reservations of the SCHED_DEADLINE threads in `sys_conf.dl_pids`
+pub fn detect_deadline_tasks(sys_conf: &SysConf) -> SystemModel<DeadlineTask> {
+    let mut model = SystemModel::new(sys_conf.clone());
+    for pid in &sys_conf.dl_pids {
+        if let Some(task) = get_deadline_task(*pid) {
+            model.set_task_model(*pid, task);
         }
     }
+
+    model
+}
+
+pub fn get_deadline_task(pid: Pid) -> Option<DeadlineTask> {
+    let mut attrbuf = default_attr_t();
+    if let Err(e) = nc::sched_getattr(pid as nc::pid_t, &mut attrbuf, size_of::<nc::sched_attr_t>() as u32, 0x0) {
+        eprintln!("[DL] {} failed to get scheduling attributes, errno: {}", pid, e);
+        return None;
+    };
+
+    deadline_task_from_attr(&attrbuf)
+}
+
+/// None if the attributes are not those of a SCHED_DEADLINE thread
+pub fn deadline_task_from_attr(attr: &nc::sched_attr_t) -> Option<DeadlineTask> {
+    if attr.sched_policy != nc::SCHED_DEADLINE as u32 {
+        return None;
+    }
+
+    let deadline = Time::from_ns(attr.sched_deadline);
+    // A period of 0 means that the period is equal to the deadline
+    let period = if attr.sched_period == 0 { deadline } else { Time::from_ns(attr.sched_period) };
+
+    Some(DeadlineTask::new(Time::from_ns(attr.sched_runtime), deadline, period))
 }
 
 pub fn detect_max_runtimes(sys_conf: &mut SysConf) {
@@ -411,3 +483,91 @@ pub fn default_attr_t() -> nc::sched_attr_t {
         sched_util_max: 0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cgroup_pids() {
+        let dir = std::env::temp_dir().join(format!("rbftrace-cgroup-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        write(dir.join("cgroup.procs"), "42\n").unwrap();
+        write(dir.join("cgroup.threads"), "44\n42\n43\n").unwrap();
+
+        let threads = get_cgroup_pids(&dir);
+        remove_file(dir.join("cgroup.threads")).unwrap();
+        let procs = get_cgroup_pids(&dir);
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(threads.unwrap(), [42, 43, 44]);
+        assert_eq!(procs.unwrap(), [42]);
+        assert!(get_cgroup_pids(&dir).is_err());
+    }
+
+    #[test]
+    fn deadline_task() {
+        let attr = nc::sched_attr_t {
+            sched_policy: nc::SCHED_DEADLINE as u32,
+            sched_runtime: 2_000_000,
+            sched_deadline: 5_000_000,
+            sched_period: 10_000_000,
+            ..default_attr_t()
+        };
+
+        let task = deadline_task_from_attr(&attr).unwrap();
+
+        assert_eq!(task, DeadlineTask::new(Time::from_ms(2.), Time::from_ms(5.), Time::from_ms(10.)));
+        assert!((task.utilization() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn deadline_task_implicit_period() {
+        let attr = nc::sched_attr_t {
+            sched_policy: nc::SCHED_DEADLINE as u32,
+            sched_runtime: 2_000_000,
+            sched_deadline: 5_000_000,
+            ..default_attr_t()
+        };
+
+        assert_eq!(deadline_task_from_attr(&attr).unwrap().period, Time::from_ms(5.));
+    }
+
+    #[test]
+    fn dl_reservations() {
+        let mut sys_conf = SysConf { n_cores: 1, dl_pids: vec![1, 2], rt_period: 1_000_000, rt_runtime: 950_000, ..Default::default() };
+        let attr = |runtime, period, sched_flags| nc::sched_attr_t {
+            sched_policy: nc::SCHED_DEADLINE as u32,
+            sched_flags,
+            sched_runtime: runtime,
+            sched_deadline: period,
+            sched_period: period,
+            ..default_attr_t()
+        };
+
+        record_dl_attr(&mut sys_conf, 1, &attr(3_000_000, 10_000_000, 0));
+        record_dl_attr(&mut sys_conf, 2, &attr(5_000_000, 20_000_000, nc::SCHED_FLAG_RECLAIM as u64));
+
+        assert_eq!(sys_conf.dl_slack_rec_pids, [2]);
+        assert_eq!(sys_conf.dl_reservations[&1], DeadlineTask::new(Time::from_ms(3.), Time::from_ms(10.), Time::from_ms(10.)));
+        assert!((sys_conf.dl_reserved_utilization() - 0.55).abs() < 1e-9);
+        assert!(!sys_conf.dl_overcommitted());
+
+        // Exceeds the 95% left by the RT throttling
+        record_dl_attr(&mut sys_conf, 2, &attr(9_000_000, 20_000_000, 0));
+        assert!((sys_conf.dl_reserved_utilization() - 0.75).abs() < 1e-9);
+        record_dl_attr(&mut sys_conf, 1, &attr(6_000_000, 10_000_000, 0));
+        assert!(sys_conf.dl_overcommitted());
+    }
+
+    #[test]
+    fn not_a_deadline_task() {
+        let attr = nc::sched_attr_t {
+            sched_policy: nc::SCHED_FIFO as u32,
+            sched_priority: 10,
+            ..default_attr_t()
+        };
+
+        assert_eq!(deadline_task_from_attr(&attr), None);
+    }
+}
@@ -0,0 +1,306 @@
+//! System-level analyses combining extracted models with the system configuration.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    model::{SystemModel, PeriodicTask},
+    sys_conf::{SysConf, RuntimeLimit, Pid, Cpu},
+    time::Time,
+    trace::Trace,
+};
+
+/// Returns the total utilization of each cluster in `sys_conf.rt_threads_info_clusters`, as `(cluster id, utilization)`.
+/// Only threads with an extracted model contribute.
+/// A thread whose affinity spans several clusters is listed in each of them: its utilization
+/// is split among those clusters proportionally to the number of its CPUs in each cluster.
+pub fn cluster_utilizations(sys_conf: &SysConf, model: &SystemModel<PeriodicTask>) -> Vec<(u32, f64)> {
+    let clusters = &sys_conf.rt_threads_info_clusters;
+    let mut ret = Vec::with_capacity(clusters.len());
+
+    for cluster in clusters {
+        let mut utilization = 0.0;
+
+        for thread in &cluster.threads {
+            if let Some(task) = model.get_model(thread.pid) {
+                // CPUs of the affinity mask that belong to some cluster
+                let total_cpus = thread.affinity.iter()
+                                       .filter(|cpu| clusters.iter().any(|c| c.cpus.contains(cpu)))
+                                       .count();
+                let cluster_cpus = thread.affinity.iter()
+                                         .filter(|cpu| cluster.cpus.contains(cpu))
+                                         .count();
+
+                if total_cpus > 0 {
+                    utilization += task.utilization() * cluster_cpus as f64 / total_cpus as f64;
+                }
+            }
+        }
+
+        ret.push((cluster.id, utilization));
+    }
+
+    ret
+}
+
+/// Returns the load of each CPU, given the affinities in `sys_conf.rt_threads_info` and the extracted utilizations.
+/// A partitioned task (single-CPU affinity) loads its CPU with its whole utilization. A task allowed on several CPUs
+/// is assumed to be balanced by the scheduler: its utilization is split evenly among the CPUs of its affinity.
+/// Tasks missing from `sys_conf` or with an empty affinity can run anywhere, and are split among all `n_cores` CPUs.
+/// Every CPU of an affinity mask or of `0..n_cores` has an entry, even if idle.
+pub fn per_cpu_utilization(sys_conf: &SysConf, model: &SystemModel<PeriodicTask>) -> BTreeMap<Cpu, f64> {
+    let all_cpus: Vec<Cpu> = (0..sys_conf.n_cores as Cpu).collect();
+    let mut ret: BTreeMap<Cpu, f64> = all_cpus.iter().map(|cpu| (*cpu, 0.0)).collect();
+
+    for pid in model.pids() {
+        let cpus = match sys_conf.affinity_of(*pid) {
+            Some(affinity) if !affinity.is_empty() => affinity,
+            _ => &all_cpus,
+        };
+        if cpus.is_empty() {
+            continue;
+        }
+
+        let share = model.get_model(*pid).unwrap().utilization() / cpus.len() as f64;
+        for cpu in cpus {
+            *ret.entry(*cpu).or_insert(0.0) += share;
+        }
+    }
+
+    ret
+}
+
+/// Returns the CPUs whose load (see `per_cpu_utilization`) exceeds the real-time budget of a core
+/// (see `SysConf::rt_budget_fraction`), as `(cpu, load)`: the RT throttling will delay their tasks.
+/// With RT_RUNTIME_SHARE, a core can use the whole period, but the total load is bounded by `SysConf::rt_capacity`:
+/// if the total exceeds it, all the loaded CPUs are returned.
+pub fn rt_throttling_risks(sys_conf: &SysConf, model: &SystemModel<PeriodicTask>) -> Vec<(Cpu, f64)> {
+    let loads = per_cpu_utilization(sys_conf, model);
+    let budget = sys_conf.rt_budget_fraction();
+    let total: f64 = loads.values().sum();
+    let over_capacity = sys_conf.rt_runtime_is_global && total > sys_conf.rt_capacity();
+
+    loads.into_iter()
+         .filter(|(_, load)| *load > budget || (over_capacity && *load > 0.0))
+         .collect()
+}
+
+/// Returns the tasks whose extracted WCET exceeds `fraction` of their consecutive runtime limit (RLIMIT_RTTIME),
+/// as `(pid, wcet, limit in microseconds)`. Such tasks risk being killed in the middle of a job.
+pub fn runtime_limit_risks(model: &SystemModel<PeriodicTask>, limits: &[RuntimeLimit], fraction: f64) -> Vec<(Pid, Time, u64)> {
+    limits.iter()
+          .filter_map(|limit| model.get_model(limit.pid).map(|task| (limit, task.wcet)))
+          .filter(|(limit, wcet)| wcet.to_us() > limit.max_runtime as f64 * fraction)
+          .map(|(limit, wcet)| (limit.pid, wcet, limit.max_runtime))
+          .collect()
+}
+
+/// Returns the tasks of the trace that ran on a CPU outside of their affinity in `sys_conf.rt_threads_info`,
+/// as `(pid, CPUs the task ran on)`, see `Trace::cpu_residency`. Their affinity changed during the capture,
+/// so an analysis that assumes the detected partitioning doesn't hold for them.
+/// Tasks missing from `sys_conf` or with an empty affinity can run anywhere and are never reported.
+pub fn affinity_changes(sys_conf: &SysConf, trace: &Trace) -> Vec<(Pid, Vec<Cpu>)> {
+    let pids: BTreeSet<Pid> = trace.events().map(|e| e.pid).collect();
+    let mut ret = Vec::new();
+
+    for pid in &pids {
+        let affinity = match sys_conf.affinity_of(*pid) {
+            Some(affinity) if !affinity.is_empty() => affinity,
+            _ => continue,
+        };
+
+        let mut observed: Vec<Cpu> = trace.cpu_residency(*pid).iter().map(|(cpu, _, _)| *cpu).collect();
+        observed.sort_unstable();
+        observed.dedup();
+        if observed.iter().any(|cpu| !affinity.contains(cpu)) {
+            ret.push((*pid, observed));
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sys_conf::{Cluster, MultiprocType, ThreadInfo, Pid, Cpu},
+        time::Time,
+    };
+
+    #[test]
+    fn partitioned_two_clusters() {
+        let sys_conf = sys_conf(vec![
+            Cluster::new(0, vec![0], vec![thread(1, vec![0]), thread(2, vec![0])]),
+            Cluster::new(1, vec![1], vec![thread(3, vec![1])]),
+        ]);
+
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, task(10., 2.));  // 0.2
+        model.set_task_model(2, task(10., 3.));  // 0.3
+        model.set_task_model(3, task(20., 5.));  // 0.25
+
+        let utilizations = cluster_utilizations(&sys_conf, &model);
+
+        assert_eq!(utilizations.len(), 2);
+        assert_eq!(utilizations[0].0, 0);
+        assert!((utilizations[0].1 - 0.5).abs() < 1e-9);
+        assert_eq!(utilizations[1].0, 1);
+        assert!((utilizations[1].1 - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spanning_thread_is_split() {
+        let spanning = thread(4, vec![0, 1]);
+        let sys_conf = sys_conf(vec![
+            Cluster::new(0, vec![0], vec![thread(1, vec![0]), spanning.clone()]),
+            Cluster::new(1, vec![1], vec![spanning]),
+        ]);
+
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, task(10., 2.)); // 0.2
+        model.set_task_model(4, task(10., 4.)); // 0.4, half on each cluster
+
+        let utilizations = cluster_utilizations(&sys_conf, &model);
+
+        assert!((utilizations[0].1 - 0.4).abs() < 1e-9);
+        assert!((utilizations[1].1 - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn runtime_limits() {
+        let mut model = SystemModel::new(SysConf::default());
+        model.set_task_model(1, task(10., 0.9)); // 90% of the limit
+        model.set_task_model(2, task(10., 0.5)); // 50% of the limit
+        model.set_task_model(3, task(10., 5.)); // No limit
+        let limits = [
+            RuntimeLimit { pid: 1, max_runtime: 1000 },
+            RuntimeLimit { pid: 2, max_runtime: 1000 },
+            RuntimeLimit { pid: 4, max_runtime: 1000 }, // No model
+        ];
+
+        assert_eq!(runtime_limit_risks(&model, &limits, 0.8), [(1, Time::from_ms(0.9), 1000)]);
+        assert!(runtime_limit_risks(&model, &limits, 1.0).is_empty());
+    }
+
+    #[test]
+    fn per_cpu_partitioned() {
+        let sys_conf = threads_conf(2, &[thread(1, vec![0]), thread(2, vec![0]), thread(3, vec![1])]);
+
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, task(10., 2.));  // 0.2
+        model.set_task_model(2, task(10., 3.));  // 0.3
+        model.set_task_model(3, task(20., 5.));  // 0.25
+
+        let utilizations = per_cpu_utilization(&sys_conf, &model);
+
+        assert_eq!(utilizations.keys().copied().collect::<Vec<_>>(), [0, 1]);
+        assert!((utilizations[&0] - 0.5).abs() < 1e-9);
+        assert!((utilizations[&1] - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn per_cpu_global() {
+        // Task 3 is missing from the configuration, so it can run anywhere
+        let sys_conf = threads_conf(4, &[thread(1, vec![0, 1, 2, 3]), thread(2, vec![2, 3])]);
+
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, task(10., 4.));  // 0.4, 0.1 per CPU
+        model.set_task_model(2, task(10., 2.));  // 0.2, 0.1 on CPUs 2 and 3
+        model.set_task_model(3, task(10., 8.));  // 0.8, 0.2 per CPU
+
+        let utilizations = per_cpu_utilization(&sys_conf, &model);
+
+        for (cpu, expected) in [(0, 0.3), (1, 0.3), (2, 0.4), (3, 0.4)] {
+            assert!((utilizations[&cpu] - expected).abs() < 1e-9);
+        }
+        let total: f64 = utilizations.values().sum();
+        assert!((total - 1.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rt_throttling() {
+        let mut sys_conf = threads_conf(2, &[thread(1, vec![0]), thread(2, vec![0]), thread(3, vec![1])]);
+        sys_conf.rt_period = 1_000_000;
+        sys_conf.rt_runtime = 950_000;
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, task(10., 5.));  // 0.5
+        model.set_task_model(2, task(10., 4.6)); // 0.46
+        model.set_task_model(3, task(10., 5.));  // 0.5
+
+        let risks = rt_throttling_risks(&sys_conf, &model);
+        assert_eq!(risks.len(), 1);
+        assert_eq!(risks[0].0, 0);
+        assert!((risks[0].1 - 0.96).abs() < 1e-9);
+
+        // CPU 0 borrows from CPU 1
+        sys_conf.rt_runtime_is_global = true;
+        assert!(rt_throttling_risks(&sys_conf, &model).is_empty());
+        // Total of 1.96 over a capacity of 1.9
+        model.set_task_model(3, task(10., 10.));
+        assert_eq!(rt_throttling_risks(&sys_conf, &model).iter().map(|(cpu, _)| *cpu).collect::<Vec<_>>(), [0, 1]);
+
+        sys_conf.rt_runtime = -1;
+        assert!(rt_throttling_risks(&sys_conf, &model).is_empty());
+    }
+
+    #[test]
+    fn migrations() {
+        use crate::trace::TraceBuilder;
+
+        // 1 is pinned to CPU 0 but migrates to 2 and back, 2 migrates within its affinity, 3 is not configured
+        let sys_conf = threads_conf(4, &[thread(1, vec![0]), thread(2, vec![0, 1])]);
+        let cpus = |pid| match pid {
+            1 => [0, 2, 2, 0],
+            2 => [1, 0, 1, 1],
+            _ => [3, 1, 2, 0],
+        };
+        let mut trace = TraceBuilder::new()
+            .periodic(1, Time::zero(), Time::from_ms(10.), Time::from_ms(2.), 4)
+            .periodic(2, Time::zero(), Time::from_ms(10.), Time::from_ms(2.), 4)
+            .periodic(3, Time::zero(), Time::from_ms(10.), Time::from_ms(2.), 4)
+            .build();
+        // The i-th job of each task runs on the i-th CPU of its list
+        for event in trace.events_mut().filter(|e| !e.is_activation()) {
+            event.cpu = Some(cpus(event.pid)[(event.instant.to_ns() / Time::from_ms(10.).to_ns()) as usize]);
+        }
+
+        assert_eq!(trace.cpu_residency(1), vec![(0, Time::from_ms(0.), Time::from_ms(2.)),
+                                                (2, Time::from_ms(10.), Time::from_ms(12.)),
+                                                (2, Time::from_ms(20.), Time::from_ms(22.)),
+                                                (0, Time::from_ms(30.), Time::from_ms(32.))]);
+        assert_eq!(affinity_changes(&sys_conf, &trace), vec![(1, vec![0, 2])]);
+        assert!(affinity_changes(&SysConf::default(), &trace).is_empty());
+    }
+
+    /* Support */
+
+    fn threads_conf(n_cores: u32, threads: &[ThreadInfo]) -> SysConf {
+        let mut sys_conf = SysConf { n_cores, ..Default::default() };
+        for thread in threads {
+            sys_conf.rt_threads_info.insert(thread.pid, thread.clone());
+        }
+
+        sys_conf
+    }
+
+    fn sys_conf(clusters: Vec<Cluster>) -> SysConf {
+        SysConf {
+            multiproc: MultiprocType::PARTITIONED,
+            n_cores: 2,
+            rt_threads_info_clusters: clusters,
+            ..Default::default()
+        }
+    }
+
+    fn thread(pid: Pid, affinity: Vec<Cpu>) -> ThreadInfo {
+        ThreadInfo {
+            pid,
+            affinity,
+            ..Default::default()
+        }
+    }
+
+    fn task(period_ms: f64, wcet_ms: f64) -> PeriodicTask {
+        PeriodicTask::new(Time::from_ms(period_ms), Time::zero(), Time::zero(), Time::from_ms(wcet_ms))
+    }
+}
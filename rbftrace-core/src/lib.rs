@@ -4,4 +4,5 @@ pub mod model;
 pub mod trace;
 pub mod sys_conf;
 pub mod rbf;
-pub mod math;
\ No newline at end of file
+pub mod math;
+pub mod analysis;
\ No newline at end of file
@@ -62,6 +62,24 @@ where T: PartialEq + PartialOrd + Clone + Copy
         }
     }
 
+    /// Whether `x` is in the closed interval, false if empty
+    pub fn contains(&self, x: T) -> bool {
+        match self {
+            Interval::NotEmpty(a, b) => *a <= x && x <= *b,
+            _ => false
+        }
+    }
+
+    /// The point of the interval closest to `x`, None if empty
+    pub fn clamp(&self, x: T) -> Option<T> {
+        match self {
+            Interval::NotEmpty(a, _) if x < *a => Some(*a),
+            Interval::NotEmpty(_, b) if x > *b => Some(*b),
+            Interval::NotEmpty(_, _) => Some(x),
+            _ => None
+        }
+    }
+
     pub fn overlaps_with(&self, other: &Self) -> bool {
         let intersection = self.intersection(other);
 
@@ -148,4 +166,28 @@ mod tests {
         assert!(!a.union(&b).is_interval());
         assert_eq!(c.union(&d), b);
     }
+
+    #[test]
+    fn test_contains_and_clamp() {
+        let a = Interval::closed(2, 4);
+
+        // Inside
+        assert!(a.contains(3));
+        assert_eq!(a.clamp(3), Some(3));
+        // On the boundary
+        assert!(a.contains(2));
+        assert!(a.contains(4));
+        assert_eq!(a.clamp(2), Some(2));
+        assert_eq!(a.clamp(4), Some(4));
+        // Outside
+        assert!(!a.contains(1));
+        assert!(!a.contains(5));
+        assert_eq!(a.clamp(1), Some(2));
+        assert_eq!(a.clamp(5), Some(4));
+
+        assert!(Interval::closed(1, 1).contains(1));
+        assert!(!Interval::<i32>::empty().contains(1));
+        assert_eq!(Interval::<i32>::empty().clamp(1), None);
+        assert_eq!(Interval::<i32>::NotAnInterval.clamp(1), None);
+    }
 }
\ No newline at end of file
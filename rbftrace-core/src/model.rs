@@ -1,16 +1,52 @@
 use crate::sys_conf::SysConf;
 use crate::time::{Time};
 use std::collections::{BTreeMap};
+use std::io::Write;
 use crate::sys_conf::{Pid};
 
+use crate::rbf::Point;
+
 use serde::{Serialize, Deserialize};
 
+/// Malformed input detected while building a model
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    /// The points of an RBF curve must have strictly increasing deltas and costs
+    NonMonotonicCurve{prev: Point, point: Point},
+    /// An arrival precedes the previous one
+    NonMonotonicArrival{prev: Time, instant: Time},
+    /// A job arrives before the previous job of the same task completed
+    OverlappingJobs{completed_at: Time, arrived_at: Time},
+    /// A self-suspending job must have one suspension less than its execution segments,
+    /// and as many segments as the other jobs of a segmented model
+    InconsistentSegments{arrived_at: Time, executions: usize, suspensions: usize},
+}
+
+impl std::fmt::Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::NonMonotonicCurve{prev, point} =>
+                write!(f, "non-monotonic curve: ({}, {}) follows ({}, {})", point.delta, point.cost, prev.delta, prev.cost),
+            ModelError::NonMonotonicArrival{prev, instant} =>
+                write!(f, "non-monotonic arrivals: {} follows {}", instant, prev),
+            ModelError::OverlappingJobs{completed_at, arrived_at} =>
+                write!(f, "overlapping jobs: a job arrived at {} before the previous one completed at {}", arrived_at, completed_at),
+            ModelError::InconsistentSegments{arrived_at, executions, suspensions} =>
+                write!(f, "inconsistent segments: the job arrived at {} has {} executions and {} suspensions", arrived_at, executions, suspensions),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Job {
     pub execution_time: Time,
     pub arrived_at: Time,
     pub completed_at: Time,
     pub preemption_time: Time,
+    /// Time spent self-suspended between the arrival and the completion of the job
+    pub suspension_time: Time,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone, Copy)]
@@ -21,6 +57,14 @@ pub struct PeriodicTask {
     pub wcet: Time
 }
 
+/// Reservation of a SCHED_DEADLINE task, as set with sched_setattr
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Clone, Copy)]
+pub struct DeadlineTask {
+    pub runtime: Time,
+    pub deadline: Time,
+    pub period: Time,
+}
+
 /* It is possible to employ both the dynamic self-suspension model
 and the segmented self-suspension model simultaneously in one task set. The hybrid
 self-suspension models can be adopted with different trade-offs between flexibility and accuracy. */
@@ -45,6 +89,48 @@ impl PeriodicTask {
         }
     }
 
+    pub fn with_period(self, period: Time) -> Self {
+        Self { period, ..self }
+    }
+
+    pub fn with_wcet(self, wcet: Time) -> Self {
+        Self { wcet, ..self }
+    }
+
+    pub fn with_jitter(self, jitter: Time) -> Self {
+        Self { jitter, ..self }
+    }
+
+    pub fn with_offset(self, offset: Time) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Multiplies the utilization by `factor` by scaling the WCET (rounded to the nearest ns).
+    /// The period is left unchanged.
+    pub fn scale_utilization(self, factor: f64) -> Self {
+        assert!(factor >= 0.0, "Utilization factor must be >= 0");
+        let wcet = Time::from_ns((self.wcet.to_ns() as f64 * factor).round() as u64);
+
+        Self { wcet, ..self }
+    }
+
+    /// Rescales a WCET measured with the CPU at `from_hz` to the reference frequency `to_hz`, see `Time::scale_frequency`.
+    /// Makes WCETs measured on systems with frequency scaling (DVFS) comparable.
+    pub fn normalize_wcet(self, from_hz: u64, to_hz: u64) -> Self {
+        let wcet = self.wcet.scale_frequency(from_hz, to_hz);
+
+        Self { wcet, ..self }
+    }
+
+    /// Fraction of processor time requested by the task (WCET / period)
+    pub fn utilization(&self) -> f64 {
+        if self.period.is_zero() {
+            return 0.0;
+        }
+
+        self.wcet.to_ns() as f64 / self.period.to_ns() as f64
+    }
+
     pub fn pretty_print(&self) {
         if self.jitter.is_zero() {
             println!("PJITTER");
@@ -62,10 +148,63 @@ impl PeriodicTask {
     }
 }
 
+impl DeadlineTask {
+    pub fn new(runtime: Time, deadline: Time, period: Time) -> Self {
+        Self {
+            runtime,
+            deadline,
+            period,
+        }
+    }
+
+    /// Fraction of processor time reserved for the task (runtime / period)
+    pub fn utilization(&self) -> f64 {
+        if self.period.is_zero() {
+            return 0.0;
+        }
+
+        self.runtime.to_ns() as f64 / self.period.to_ns() as f64
+    }
+}
+
 impl PeriodicSelfSuspendingTask {
     pub fn computation_segments(&self) -> usize {
         self.wcet.len()
     }
+
+    pub fn suspension_segments(&self) -> usize {
+        self.ss.len()
+    }
+
+    /// WCET of the i-th computation segment, None if out of bounds
+    pub fn segment_wcet(&self, i: usize) -> Option<Time> {
+        self.wcet.get(i).copied()
+    }
+
+    /// Worst-case length of the i-th suspension, None if out of bounds
+    pub fn segment_ss(&self, i: usize) -> Option<Time> {
+        self.ss.get(i).copied()
+    }
+
+    /// Checks that the totals agree with the segments of a segmented model.
+    /// Each segment is the worst case among all jobs, so the totals (worst case of a whole job)
+    /// are at least the largest segment and at most the sum of the segments.
+    /// Non-segmented models have no segments, and are always consistent.
+    pub fn is_consistent(&self) -> bool {
+        if !self.segmented || self.wcet.is_empty() {
+            return self.ss.is_empty();
+        }
+
+        let within = |total: Time, segments: &[Time]| {
+            let max = segments.iter().copied().max().unwrap_or_default();
+            let sum = segments.iter().fold(Time::zero(), |acc, t| acc + *t);
+            max <= total && total <= sum
+        };
+
+        self.ss.len() == self.wcet.len() - 1
+            && within(self.total_wcet, &self.wcet)
+            && within(self.total_wcss, &self.ss)
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -97,4 +236,154 @@ impl<T> SystemModel<T> {
     pub fn pids(&self) -> impl Iterator<Item=&Pid> {
         self.models.keys()
     }
-}
\ No newline at end of file
+
+    /// Transforms the model of each task, e.g. to keep only one of the models of a `CompositeModel`.
+    /// The tasks for which `f` returns None are dropped, the system configuration is kept.
+    pub fn map<U>(self, f: impl Fn(T) -> Option<U>) -> SystemModel<U> {
+        SystemModel {
+            sys_conf: self.sys_conf,
+            models: self.models
+                .into_iter()
+                .filter_map(|(pid, model)| f(model).map(|model| (pid, model)))
+                .collect(),
+        }
+    }
+}
+
+impl SystemModel<PeriodicTask> {
+    /// Writes the task set as CSV, e.g. for external schedulability analyzers: a `pid,period,wcet,deadline,priority` header,
+    /// then one row per task in pid order. Times are in ns, the deadline is implicit (equal to the period),
+    /// and the priority is taken from `sys_conf` (empty if the pid is unknown).
+    pub fn to_taskset_csv<W: Write>(&self, sys_conf: &SysConf, mut w: W) -> std::io::Result<()> {
+        writeln!(w, "pid,period,wcet,deadline,priority")?;
+        for (pid, task) in self.models.iter() {
+            let prio = sys_conf.priority_of(*pid).map_or(String::new(), |p| p.to_string());
+            writeln!(w, "{},{},{},{},{}", pid, task.period.to_ns(), task.wcet.to_ns(), task.period.to_ns(), prio)?;
+        }
+
+        Ok(())
+    }
+
+    /// True if, in increasing order, each period divides the next one, i.e. all the periods are integer multiples
+    /// of the smaller ones. The periods must divide exactly (to the ns): extract them with a coarse enough resolution.
+    /// An empty model is harmonic, a model with a zero period is not.
+    pub fn is_harmonic(&self) -> bool {
+        let mut periods: Vec<Time> = self.models.values().map(|task| task.period).collect();
+        periods.sort_unstable();
+        periods.dedup();
+
+        periods.first().is_none_or(|period| !period.is_zero())
+            && periods.windows(2).all(|w| (w[1] % w[0]).is_zero())
+    }
+
+    /// The smallest period, of which all the others are multiples, if the model is harmonic (see `is_harmonic`).
+    /// None for an empty or non-harmonic model.
+    pub fn harmonic_base(&self) -> Option<Time> {
+        if !self.is_harmonic() {
+            return None;
+        }
+
+        self.models.values().map(|task| task.period).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys_conf::ThreadInfo;
+
+    #[test]
+    fn builder() {
+        let task = PeriodicTask::default()
+            .with_period(Time::from_ms(10.))
+            .with_wcet(Time::from_ms(2.))
+            .with_jitter(Time::from_ms(1.))
+            .with_offset(Time::from_ms(3.));
+
+        assert_eq!(task, PeriodicTask::new(Time::from_ms(10.), Time::from_ms(1.), Time::from_ms(3.), Time::from_ms(2.)));
+    }
+
+    #[test]
+    fn scale_utilization() {
+        let task = PeriodicTask::default()
+            .with_period(Time::from_ms(10.))
+            .with_wcet(Time::from_ms(2.))
+            .scale_utilization(2.5);
+
+        assert_eq!(task.wcet, Time::from_ms(5.));
+        assert_eq!(task.period, Time::from_ms(10.));
+        assert!((task.utilization() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn taskset_csv() {
+        let mut sys_conf = SysConf::default();
+        sys_conf.rt_threads_info.insert(1, ThreadInfo { pid: 1, prio: 90, ..Default::default() });
+        sys_conf.rt_threads_info.insert(2, ThreadInfo { pid: 2, prio: 50, ..Default::default() });
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(2, PeriodicTask::default().with_period(Time::from_ms(20.)).with_wcet(Time::from_ms(5.)));
+        model.set_task_model(1, PeriodicTask::default().with_period(Time::from_ms(10.)).with_wcet(Time::from_ms(2.)));
+        // Not in the configuration
+        model.set_task_model(3, PeriodicTask::default().with_period(Time::from_us(500.)).with_wcet(Time::from_us(1.)));
+
+        let mut csv = Vec::new();
+        model.to_taskset_csv(&sys_conf, &mut csv).unwrap();
+
+        assert_eq!(String::from_utf8(csv).unwrap(), "pid,period,wcet,deadline,priority\n\
+                                                     1,10000000,2000000,10000000,90\n\
+                                                     2,20000000,5000000,20000000,50\n\
+                                                     3,500000,1000,500000,\n");
+    }
+
+    #[test]
+    fn harmonic() {
+        let model = |periods_ms: &[f64]| {
+            let mut model = SystemModel::new(SysConf::default());
+            for (pid, period) in periods_ms.iter().enumerate() {
+                model.set_task_model(pid as Pid, PeriodicTask::default().with_period(Time::from_ms(*period)));
+            }
+            model
+        };
+
+        let harmonic = model(&[20., 5., 10., 10.]);
+        assert!(harmonic.is_harmonic());
+        assert_eq!(harmonic.harmonic_base(), Some(Time::from_ms(5.)));
+
+        for periods in [&[7., 10.][..], &[5., 10., 15.], &[0., 10.]] {
+            assert!(!model(periods).is_harmonic());
+            assert_eq!(model(periods).harmonic_base(), None);
+        }
+
+        assert!(model(&[]).is_harmonic());
+        assert_eq!(model(&[]).harmonic_base(), None);
+        assert_eq!(model(&[7.]).harmonic_base(), Some(Time::from_ms(7.)));
+    }
+
+    #[test]
+    fn normalize_wcet() {
+        let task = PeriodicTask::default()
+            .with_period(Time::from_ms(10.))
+            .with_wcet(Time::from_ms(2.))
+            .normalize_wcet(2_000_000_000, 1_000_000_000);
+
+        assert_eq!(task.wcet, Time::from_ms(4.));
+        assert_eq!(task.period, Time::from_ms(10.));
+    }
+
+    #[test]
+    fn inconsistent_self_suspending() {
+        let task = PeriodicSelfSuspendingTask {
+            period: Time::from_ms(10.),
+            total_wcet: Time::from_ms(5.),
+            total_wcss: Time::from_ms(1.),
+            wcet: vec![Time::from_ms(1.), Time::from_ms(2.)],
+            ss: vec![Time::from_ms(1.)],
+            segmented: true,
+        };
+        // total_wcet exceeds the sum of the segments
+        assert!(!task.is_consistent());
+        assert!(PeriodicSelfSuspendingTask { total_wcet: Time::from_ms(3.), ..task.clone() }.is_consistent());
+        // Missing suspension
+        assert!(!PeriodicSelfSuspendingTask { total_wcet: Time::from_ms(3.), ss: vec![], ..task }.is_consistent());
+    }
+}
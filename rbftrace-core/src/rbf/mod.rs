@@ -1,12 +1,15 @@
-use std::{collections::VecDeque, cmp::Ordering};
+use std::{collections::VecDeque, cmp::Ordering, fmt::Display};
 use serde::{Serialize, Deserialize};
 
-use crate::{time::*, sys_conf::{Pid, Priority}};
+use crate::{time::*, sys_conf::{Pid, Priority}, model::ModelError};
 
 mod sparse_map;
 use sparse_map::{SparseMap};
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[cfg(feature = "plotting")]
+mod svg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Point {
     pub delta: Duration,
     pub cost: Cost, 
@@ -21,6 +24,26 @@ impl Point {
     }
 }
 
+/// Ordered by delta, then by cost
+impl Ord for Point {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.delta.cmp(&other.delta)
+            .then(self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for Point {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{} : {}]", self.delta, self.cost)
+    }
+}
+
 /* The "curve" map maps distance to total cost. 
 It answers the question: What is the minimum distance to observe AT MOST a total cost of c?
 The distance is *exclusive*, meaning that:
@@ -37,14 +60,26 @@ pub struct RbfCurve {
 }
 
 impl RbfCurve {
+    /// Panics if `instant` precedes the last arrival, see `try_add_arrival`
     pub fn add_arrival(&mut self, instant: Time, cost: Cost) {
-        
+        if let Err(e) = self.try_add_arrival(instant, cost) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `add_arrival`, but returns an error instead of panicking if `instant` precedes the last arrival.
+    /// The curve is left unchanged in that case.
+    pub fn try_add_arrival(&mut self, instant: Time, cost: Cost) -> Result<(), ModelError> {
         let arrival: (Time, Cost) = (instant, cost);
         let t = instant;
         let mut curr_observed_tot_cost = Time::zero();
 
         // sanity check: the arrival times must be monotonic
-        assert!(t >= self.last_arrivals_window.back().unwrap_or(&arrival).0);
+        if let Some((prev, _)) = self.last_arrivals_window.back() {
+            if t < *prev {
+                return Err(ModelError::NonMonotonicArrival{prev: *prev, instant: t});
+            }
+        }
         // add to treat the observed_gap = 0 case
         self.last_arrivals_window.push_back(arrival);
         // look at all arrival times in the sliding window, in order
@@ -54,7 +89,8 @@ impl RbfCurve {
             // of the (i + 1)-th preceding job.
             // So if i=0, we are looking at two adjacent jobs.
             let observed_gap = t - arr.0 + Time::from_ns(1);
-            curr_observed_tot_cost += arr.1;
+            // Saturate instead of wrapping around on pathological costs
+            curr_observed_tot_cost = curr_observed_tot_cost.saturating_add(arr.1);
 
             // we have not yet seen a distance of length "observed_gap" -> first sample
             // add new distance only if the observed total cost is bigger than the one observed in the nearest preceding gap
@@ -71,6 +107,40 @@ impl RbfCurve {
 
         // update WCET
         self.wcet = self.wcet.max(cost); // TODO could just return the cost for key 0
+
+        Ok(())
+    }
+
+    /// Like `add_arrival`, but an arrival that precedes the last one is moved to the instant of the last one.
+    /// Its cost is accounted, but its distances to the preceding arrivals are stretched, so the curve can
+    /// underestimate the demand of the sorted trace. Returns true if the arrival was clamped.
+    pub fn add_arrival_clamped(&mut self, instant: Time, cost: Cost) -> bool {
+        let last = self.last_arrivals_window.back().map(|(prev, _)| *prev);
+        let clamped = last.filter(|prev| instant < *prev);
+        self.add_arrival(clamped.unwrap_or(instant), cost);
+
+        clamped.is_some()
+    }
+
+    /// Approximate number of bytes held by the curve, including the window of arrivals and the points
+    pub fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_size()
+    }
+
+    /// Approximate number of bytes allocated on the heap for the window of arrivals and the points,
+    /// i.e. `memory_footprint` of a curve embedded in another struct
+    pub fn heap_size(&self) -> usize {
+        self.last_arrivals_window.capacity() * std::mem::size_of::<(Time, Cost)>()
+            + self.curve.heap_size()
+    }
+
+    /// Forgets all the arrivals, going back to the initial curve {(0, 0)} as `new` would, e.g. on a mode change.
+    /// The pid, the priority and the allocated memory are kept.
+    pub fn clear(&mut self) {
+        self.last_arrivals_window.clear();
+        self.curve.clear();
+        self.curve.add(Point::new(Time::zero(), Time::zero()));
+        self.wcet = Time::zero();
     }
 
     pub fn add_arrivals(&mut self, arrivals: &[(Time, Cost)]) {
@@ -84,6 +154,73 @@ impl RbfCurve {
         self.curve.get(delta)
     }
 
+    /// Returns the cost of the smallest stored delta that is >= `delta`, i.e. the next step of the curve,
+    /// or None if `delta` is past the last step.
+    /// Deltas follow the same exclusive convention as `get`: the cost at delta d is the one observed
+    /// in half-open windows [t, t + d), so a delta of 1 covers a single arrival.
+    /// For deltas that are stored in the curve, `get` and `get_upper` agree.
+    pub fn get_upper(&self, delta: Duration) -> Option<Cost> {
+        self.curve.get_upper(delta)
+    }
+
+    /// Drops the points with a delta greater than `horizon`, e.g. the hyperperiod, to bound the size of the curve.
+    /// `get` beyond the horizon then saturates at the last retained cost.
+    /// Arrivals added afterwards can add points beyond the horizon again.
+    pub fn trim_to_horizon(&mut self, horizon: Duration) {
+        self.curve.truncate(horizon);
+    }
+
+    /// Average cost requested per unit of time (ns/ns) between the deltas `d1` and `d2`.
+    /// Returns 0 if `d1 == d2`.
+    pub fn slope(&self, d1: Duration, d2: Duration) -> f64 {
+        let (d1, d2) = if d1 <= d2 { (d1, d2) } else { (d2, d1) };
+        if d1 == d2 {
+            return 0.0;
+        }
+
+        (self.get(d2) - self.get(d1)).to_ns() as f64 / (d2 - d1).to_ns() as f64
+    }
+
+    /// Area under the step function over [0, horizon), in ns * ns: each step contributes its cost times
+    /// the width until the next step (or the horizon). Past the last step, the cost saturates as in `get`.
+    pub fn integral(&self, horizon: Duration) -> u128 {
+        let points = self.as_points();
+        let mut ret = 0;
+
+        for (i, point) in points.iter().enumerate() {
+            if point.delta >= horizon {
+                break;
+            }
+            let end = points.get(i + 1).map_or(horizon, |next| next.delta.min(horizon));
+            ret += point.cost.to_ns() as u128 * (end - point.delta).to_ns() as u128;
+        }
+
+        ret
+    }
+
+    /// Steepest slope between two consecutive steps of the curve, i.e. of the
+    /// piecewise-linear curve through the steps. Returns 0 for an empty curve.
+    pub fn max_slope(&self) -> f64 {
+        self.as_points()
+            .windows(2)
+            .map(|w| (w[1].cost - w[0].cost).to_ns() as f64 / (w[1].delta - w[0].delta).to_ns() as f64)
+            .fold(0.0, f64::max)
+    }
+
+    /// Long-run rate of the curve (ns/ns): slope between the two steps with the largest deltas.
+    /// Only meaningful once the window is full, before that the last steps may not reflect the steady state.
+    /// Returns 0 if the curve has less than two steps.
+    pub fn asymptotic_rate(&self) -> f64 {
+        match self.as_points().as_slice() {
+            [.., prev, last] => (last.cost - prev.cost).to_ns() as f64 / (last.delta - prev.delta).to_ns() as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Adds `other` to this curve, e.g. to get the total demand of a set of tasks.
+    /// The result is an aggregate: the WCET is the sum of the WCETs, the window size is the largest one,
+    /// pid and priority are reset to 0 (the caller can relabel it), and the arrival history is dropped,
+    /// so arrivals should not be added to it afterwards.
     pub fn sum(&mut self, other: &RbfCurve) {
         // Cloning the first curve because we would need to mutate it while iterating
         let curve_1_clone = self.curve.clone();
@@ -100,8 +237,8 @@ impl RbfCurve {
                 Ordering::Equal => {
                     self.curve.insert(Point::new(p_1.delta, p_1.cost + p_2.cost));
 
-                    last_cost_1 = p_1.cost + p_2.cost;
-                    last_cost_2 = p_1.cost + p_2.cost;
+                    last_cost_1 = p_1.cost;
+                    last_cost_2 = p_2.cost;
                     point_1 = curve_1.next();
                     point_2 = curve_2.next();
                 },
@@ -135,19 +272,109 @@ impl RbfCurve {
         for p_2 in curve_2 {
             self.curve.insert(Point::new(p_2.delta, p_2.cost + last_cost_1));
         }
+
+        self.wcet += other.wcet;
+        self.window_size = self.window_size.max(other.window_size);
+        self.pid = 0;
+        self.prio = 0;
+        self.last_arrivals_window.clear();
+    }
+
+    /// Sum of all the curves, with the same aggregate semantics as `sum`. The curves are summed pairwise,
+    /// like a balanced tree, so that the intermediate sums stay small. An empty slice gives an empty curve.
+    pub fn sum_all(curves: &[RbfCurve]) -> RbfCurve {
+        match curves {
+            [] => RbfCurve::new(0, 1),
+            [curve] => {
+                let mut ret = RbfCurve::new(0, curve.window_size);
+                ret.sum(curve);
+                ret
+            },
+            _ => {
+                let (left, right) = curves.split_at(curves.len() / 2);
+                let mut ret = Self::sum_all(left);
+                ret.sum(&Self::sum_all(right));
+                ret
+            },
+        }
+    }
+
+    /// Compares two curves up to a tolerance on the cost, ignoring pid and priority.
+    /// Both curves are step functions, so it is enough to compare them at the deltas of their steps.
+    pub fn approx_eq(&self, other: &RbfCurve, cost_tol: Cost) -> bool {
+        self.curve.into_iter()
+            .chain(&other.curve)
+            .all(|point| {
+                let cost_1 = self.get(point.delta);
+                let cost_2 = other.get(point.delta);
+
+                cost_1.max(cost_2) - cost_1.min(cost_2) <= cost_tol
+            })
+    }
+
+    /// The steps of the curve, ordered by delta
+    pub fn as_points(&self) -> Vec<Point> {
+        self.curve.into_iter().collect()
+    }
+
+    /// Rebuilds a curve from its steps, e.g. as returned by `as_points`.
+    /// The point at delta 0 is implicit. The curve has no arrival history, so adding arrivals
+    /// to it only accounts for the distances between the new arrivals.
+    pub fn from_points(pid: Pid, window_size: usize, points: &[Point]) -> Result<Self, ModelError> {
+        for w in points.windows(2) {
+            if w[0].delta >= w[1].delta || w[0].cost >= w[1].cost {
+                return Err(ModelError::NonMonotonicCurve{prev: w[0], point: w[1]});
+            }
+        }
+
+        let mut ret = RbfCurve::new(pid, window_size);
+        for point in points {
+            ret.curve.insert(*point);
+        }
+        // The largest cost of a single job
+        ret.wcet = ret.get(Time::from_ns(1));
+
+        Ok(ret)
     }
 
     pub fn print_curve(&self) {
         for point in &self.curve {
-            print!("[{} : {}] ", point.delta, point.cost);
+            print!("{} ", point);
         }
         
         println!();
     }
 
 
+    /// Builds the curve of a whole arrival sequence at once. The arrivals must be sorted by instant.
+    /// Since the largest observable distance is known upfront, the buckets of the curve are sized
+    /// to hold it and are never doubled while the arrivals are added.
+    pub fn from_sorted_arrivals(pid: Pid, window_size: usize, arrivals: &[(Time, Cost)]) -> Self {
+        assert!(arrivals.windows(2).all(|w| w[0].0 <= w[1].0), "Arrivals must be sorted");
+
+        // Each arrival is compared with the `window_size` preceding ones at most
+        let span = (window_size + 1).min(arrivals.len()).max(1);
+        let max_delta = arrivals.windows(span)
+            .map(|w| w[w.len() - 1].0 - w[0].0 + Time::from_ns(1))
+            .max()
+            .unwrap_or(Time::zero());
+        // Smallest bucket size such that `window_size` buckets hold `max_delta`
+        let bucket_size = Time::from_ns(max_delta.to_ns() / window_size.max(1) as u64 + 1);
+        let mut ret = RbfCurve::with_bucket_size(pid, window_size, bucket_size);
+        ret.add_arrivals(arrivals);
+
+        ret
+    }
+
     pub fn new(pid: Pid, window_size: usize) -> Self {
-        let mut curve = SparseMap::new(window_size);
+        Self::with_bucket_size(pid, window_size, Time::from_ns(1))
+    }
+
+    /// Like `new`, with a hint on the initial bucket size of the curve, see `SparseMap::with_bucket_size`.
+    /// The buckets are never doubled if `window_size` buckets span the largest distance between the arrivals of the window,
+    /// which is slightly more than `window_size` periods: a little more than the expected period of the task is a good hint.
+    pub fn with_bucket_size(pid: Pid, window_size: usize, bucket_size: Duration) -> Self {
+        let mut curve = SparseMap::with_bucket_size(window_size, bucket_size);
         curve.add(Point::new(Time::zero(), Time::zero()));
         RbfCurve { 
             last_arrivals_window: VecDeque::with_capacity(window_size+1),
@@ -195,6 +422,80 @@ mod tests {
         assert_eq!(extracted_curve, ground_truth);
     }
 
+    #[test]
+    fn periodic_asymptotic_rate() {
+        let (cost, period) = (5, 5);
+        let rbf = RbfCurve::from([(Time::from_ns(0), Time::from_ns(cost)), 
+                                  (Time::from_ns(5), Time::from_ns(cost)), 
+                                  (Time::from_ns(10), Time::from_ns(cost)), 
+                                  (Time::from_ns(15), Time::from_ns(cost)), 
+                                  (Time::from_ns(20), Time::from_ns(cost))]);
+
+        assert!((rbf.asymptotic_rate() - cost as f64 / period as f64).abs() < 1e-9);
+        assert_eq!(RbfCurve::from([]).asymptotic_rate(), 0.);
+    }
+
+    #[test]
+    fn bucket_size_hint() {
+        let mut rbf = RbfCurve::new(1, 50);
+        let mut hinted = RbfCurve::with_bucket_size(1, 50, Time::from_ms(11.));
+        for i in 0..200 {
+            rbf.add_arrival(Time::from_ms(10. * i as f64), Time::from_ms(1.));
+            hinted.add_arrival(Time::from_ms(10. * i as f64), Time::from_ms(1.));
+        }
+
+        assert_eq!(hinted.curve.bucket_size, Time::from_ms(11.).to_ns());
+        assert_eq!(hinted, rbf);
+    }
+
+    #[test]
+    fn point_ordering() {
+        let mut points = vec![p(6, 10), p(1, 5), p(6, 8), p(0, 0)];
+        points.sort();
+
+        assert_eq!(points, [p(0, 0), p(1, 5), p(6, 8), p(6, 10)]);
+        assert_eq!(p(6, 10).to_string(), "[6 : 10]");
+    }
+
+    #[test]
+    fn integral() {
+        let rbf = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                  (Time::from_ns(5), Time::from_ns(5)),
+                                  (Time::from_ns(10), Time::from_ns(5)),
+                                  (Time::from_ns(15), Time::from_ns(5)),
+                                  (Time::from_ns(20), Time::from_ns(5))]);
+
+        assert_eq!(rbf.integral(Time::zero()), 0);
+        // 5 * 5 + 10 * 5
+        assert_eq!(rbf.integral(Time::from_ns(11)), 75);
+        // 5 * 5 + 10 * 5 + 15 * 5 + 20 * 5 + 25 * 2, the last step extends up to the horizon
+        assert_eq!(rbf.integral(Time::from_ns(23)), 300);
+    }
+
+    #[test]
+    fn periodic_get_upper() {
+        let rbf = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)), 
+                                  (Time::from_ns(5), Time::from_ns(5)), 
+                                  (Time::from_ns(10), Time::from_ns(5)), 
+                                  (Time::from_ns(15), Time::from_ns(5)), 
+                                  (Time::from_ns(20), Time::from_ns(5))]);
+        // [p(0, 0), p(1, 5), p(6, 10), p(11, 15), p(16, 20), p(21, 25)]
+
+        // On a step
+        assert_eq!(rbf.get(Time::from_ns(6)), Time::from_ns(10));
+        assert_eq!(rbf.get_upper(Time::from_ns(6)), Some(Time::from_ns(10)));
+
+        // Between steps
+        assert_eq!(rbf.get(Time::from_ns(3)), Time::from_ns(5));
+        assert_eq!(rbf.get_upper(Time::from_ns(3)), Some(Time::from_ns(10)));
+        assert_eq!(rbf.get(Time::from_ns(20)), Time::from_ns(20));
+        assert_eq!(rbf.get_upper(Time::from_ns(20)), Some(Time::from_ns(25)));
+
+        // Past the last step
+        assert_eq!(rbf.get(Time::from_ns(22)), Time::from_ns(25));
+        assert_eq!(rbf.get_upper(Time::from_ns(22)), None);
+    }
+
     #[test]
     fn periodic_var_cost() {
         let rbf = RbfCurve::from([(Time::from_ns(0 ), Time::from_ns(1 )), 
@@ -223,6 +524,79 @@ mod tests {
         assert_eq!(extracted_curve, ground_truth);
     }
 
+    #[test]
+    fn trim_to_horizon() {
+        let arrivals: Vec<(Time, Cost)> = (0..10).map(|i| (Time::from_ns(i * 10), Time::from_ns(2))).collect();
+        let mut rbf = RbfCurve::from(arrivals);
+        assert_eq!(rbf.as_points().len(), 11);
+
+        rbf.trim_to_horizon(Time::from_ns(31));
+
+        assert_eq!(rbf.as_points(), [p(0, 0), p(1, 2), p(11, 4), p(21, 6), p(31, 8)]);
+        assert_eq!(rbf.curve.count, 5);
+        // Saturates at the last retained cost
+        assert_eq!(rbf.get(Time::from_ns(30)), Time::from_ns(6));
+        assert_eq!(rbf.get(Time::from_ns(31)), Time::from_ns(8));
+        assert_eq!(rbf.get(Time::from_ns(91)), Time::from_ns(8));
+        assert_eq!(rbf.get(Time::from_ns(10_000)), Time::from_ns(8));
+    }
+
+    #[test]
+    fn bursty_slope() {
+        let rbf = RbfCurve::from([(Time::from_ns(0 ), Time::from_ns(10)), 
+                                  (Time::from_ns(1 ), Time::from_ns(10)), 
+                                  (Time::from_ns(2 ), Time::from_ns(10)), 
+                                  (Time::from_ns(20), Time::from_ns(10)), 
+                                  (Time::from_ns(21), Time::from_ns(10)), 
+                                  (Time::from_ns(22), Time::from_ns(10))]);
+
+        let in_burst = rbf.slope(Time::from_ns(1), Time::from_ns(3));
+        let long_run = rbf.slope(Time::from_ns(1), Time::from_ns(23));
+
+        assert!((in_burst - 10.).abs() < 1e-9);
+        assert!((long_run - 50. / 22.).abs() < 1e-9);
+        assert!(in_burst > long_run);
+        assert_eq!(rbf.slope(Time::from_ns(23), Time::from_ns(1)), long_run);
+        assert_eq!(rbf.slope(Time::from_ns(5), Time::from_ns(5)), 0.);
+        assert!((rbf.max_slope() - 10.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn from_sorted_arrivals() {
+        let arrivals = [(Time::from_ns(0 ), Time::from_ns(10)), 
+                        (Time::from_ns(1 ), Time::from_ns(10)), 
+                        (Time::from_ns(2 ), Time::from_ns(10)), 
+                        (Time::from_ns(20), Time::from_ns(10)), 
+                        (Time::from_ns(21), Time::from_ns(10)), 
+                        (Time::from_ns(22), Time::from_ns(10))];
+
+        let mut incremental = RbfCurve::new(1, 1000);
+        incremental.add_arrivals(&arrivals);
+        let batch = RbfCurve::from_sorted_arrivals(1, 1000, &arrivals);
+
+        assert_eq!(batch.as_points(), incremental.as_points());
+        assert_eq!(batch.wcet, incremental.wcet);
+        assert_eq!(batch.curve.bucket_size, 1);
+
+        // The buckets are sized once for the whole sequence
+        let arrivals: Vec<_> = (0..100u64).map(|i| (Time::from_ns(i * 1000), Time::from_ns(10))).collect();
+        let mut incremental = RbfCurve::new(1, 16);
+        incremental.add_arrivals(&arrivals);
+        let batch = RbfCurve::from_sorted_arrivals(1, 16, &arrivals);
+
+        assert_eq!(batch.as_points(), incremental.as_points());
+        // 16 periods and 1ns
+        assert_eq!(batch.curve.bucket_size, 16_001 / 16 + 1);
+        assert!(incremental.curve.bucket_size > batch.curve.bucket_size);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_unsorted_arrivals() {
+        RbfCurve::from_sorted_arrivals(1, 1000, &[(Time::from_ns(5), Time::from_ns(1)),
+                                                  (Time::from_ns(2), Time::from_ns(1))]);
+    }
+
     #[test]
     fn far_spikes() {
         let rbf = RbfCurve::from([(Time::from_ns(4) , Time::from_ns(90)), 
@@ -234,6 +608,50 @@ mod tests {
         assert_eq!(extracted_curve, ground_truth);
     }
 
+    #[test]
+    fn sum_metadata() {
+        let mut rbf1 = RbfCurve::new(1, 10);
+        rbf1.add_arrivals(&[(Time::from_ns(0), Time::from_ns(5)), (Time::from_ns(10), Time::from_ns(3))]);
+        rbf1.prio = 20;
+        let mut rbf2 = RbfCurve::new(2, 100);
+        rbf2.add_arrivals(&[(Time::from_ns(2), Time::from_ns(7))]);
+        rbf2.prio = 10;
+
+        rbf1.sum(&rbf2);
+
+        assert_eq!(rbf1.wcet, Time::from_ns(12));
+        assert_eq!(rbf1.window_size, 100);
+        assert_eq!(rbf1.pid, 0);
+        assert_eq!(rbf1.prio, 0);
+        assert!(rbf1.last_arrivals_window.is_empty());
+    }
+
+    #[test]
+    fn sum_all() {
+        let curves = [
+            RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)), (Time::from_ns(10), Time::from_ns(5)), (Time::from_ns(20), Time::from_ns(5))]),
+            RbfCurve::from([(Time::from_ns(3), Time::from_ns(2)), (Time::from_ns(4), Time::from_ns(2))]),
+            RbfCurve::from([(Time::from_ns(1), Time::from_ns(7)), (Time::from_ns(50), Time::from_ns(9))]),
+        ];
+        let mut sequential = curves[0].clone();
+        sequential.sum(&curves[1]);
+        sequential.sum(&curves[2]);
+
+        let summed = RbfCurve::sum_all(&curves);
+        assert_eq!(summed.as_points(), sequential.as_points());
+        assert_eq!(summed.wcet, sequential.wcet);
+        assert_eq!(summed.pid, 0);
+        // Pointwise sum
+        for delta in (0..60).map(Time::from_ns) {
+            assert_eq!(summed.get(delta), curves.iter().map(|c| c.get(delta)).fold(Time::zero(), |a, b| a + b));
+        }
+
+        let single = RbfCurve::sum_all(&curves[..1]);
+        assert_eq!(single.as_points(), curves[0].as_points());
+        assert_eq!(single.wcet, curves[0].wcet);
+        assert_eq!(RbfCurve::sum_all(&[]).as_points(), [p(0, 0)]);
+    }
+
     #[test]
     fn sum_empty() {
         let mut rbf1 = RbfCurve::new(1, 1000);
@@ -369,8 +787,165 @@ mod tests {
 
         assert_eq!(
             rbf1_vec, 
-            [p(0, 0), p(5, 15), p(20, 25), p(30, 26), p(31, 27)]
+            [p(0, 0), p(5, 15), p(20, 20), p(30, 21), p(31, 22)]
+        );
+    }
+
+    #[test]
+    fn sum_shared_step() {
+        let mut rbf1 = RbfCurve::new(1, 1000);
+        let mut rbf2 = RbfCurve::new(1, 1000);
+        let curve1 = [p(0, 0), p(5, 5), p(10, 6)];
+        let curve2 = [p(0, 0), p(5, 5), p(12, 7)];
+        for p in curve1 {
+            rbf1.curve.insert(p);
+        }
+        for p in curve2 {
+            rbf2.curve.insert(p);
+        }
+        let expected: Vec<Cost> = (0..20).map(|delta| rbf1.get(Time::from_ns(delta)) + rbf2.get(Time::from_ns(delta))).collect();
+
+        rbf1.sum(&rbf2);
+        let rbf1_vec: Vec<Point> = rbf1.curve.into_iter().collect();
+
+        // After the shared step at 5, each curve continues from its own cost
+        assert_eq!(
+            rbf1_vec,
+            [p(0, 0), p(5, 10), p(10, 11), p(12, 13)]
         );
+        assert_eq!((0..20).map(|delta| rbf1.get(Time::from_ns(delta))).collect::<Vec<Cost>>(), expected);
+    }
+
+    #[test]
+    fn approx_eq_within_tolerance() {
+        let rbf1 = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                   (Time::from_ns(5), Time::from_ns(5)),
+                                   (Time::from_ns(10), Time::from_ns(5))]);
+        let rbf2 = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                   (Time::from_ns(5), Time::from_ns(6)),
+                                   (Time::from_ns(10), Time::from_ns(5))]);
+
+        assert_ne!(rbf1, rbf2);
+        assert!(rbf1.approx_eq(&rbf2, Time::from_ns(1)));
+        assert!(rbf2.approx_eq(&rbf1, Time::from_ns(1)));
+        assert!(!rbf1.approx_eq(&rbf2, Time::zero()));
+    }
+
+    #[test]
+    fn approx_eq_large_difference() {
+        let rbf1 = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                   (Time::from_ns(5), Time::from_ns(5))]);
+        let rbf2 = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                   (Time::from_ns(5), Time::from_ns(500))]);
+
+        assert!(!rbf1.approx_eq(&rbf2, Time::from_ns(1)));
+        assert!(rbf1.approx_eq(&rbf1.clone(), Time::zero()));
+    }
+
+    #[test]
+    fn cost_saturates() {
+        let huge = Time::from_ns(u64::MAX / 2 + 1);
+        let rbf = RbfCurve::from([(Time::from_ns(0), huge),
+                                  (Time::from_ns(5), huge),
+                                  (Time::from_ns(10), huge)]);
+
+        assert_eq!(rbf.get(Time::from_ns(1)), huge);
+        assert_eq!(rbf.get(Time::from_ns(6)), Time::MAX);
+        assert_eq!(rbf.get(Time::from_ns(11)), Time::MAX);
+    }
+
+    #[test]
+    fn non_monotonic_arrival() {
+        let mut rbf = RbfCurve::new(1, 1000);
+        rbf.add_arrival(Time::from_ns(10), Time::from_ns(5));
+        let before = rbf.as_points();
+
+        let res = rbf.try_add_arrival(Time::from_ns(5), Time::from_ns(5));
+
+        assert_eq!(res, Err(ModelError::NonMonotonicArrival{prev: Time::from_ns(10), instant: Time::from_ns(5)}));
+        assert_eq!(rbf.as_points(), before);
+        assert!(rbf.try_add_arrival(Time::from_ns(10), Time::from_ns(5)).is_ok());
+    }
+
+    #[test]
+    fn clear() {
+        let arrivals: Vec<(Time, Cost)> = (0..50).map(|i| (Time::from_ms(10. * i as f64), Time::from_ms(2.))).collect();
+        let mut rbf = RbfCurve::new(1, 20);
+        rbf.prio = 5;
+        rbf.add_arrivals(&arrivals);
+        let window_capacity = rbf.last_arrivals_window.capacity();
+        let allocated: usize = rbf.curve.buckets.iter().map(|b| b.capacity()).sum();
+
+        rbf.clear();
+        let mut fresh = RbfCurve::new(1, 20);
+        fresh.prio = 5;
+        assert_eq!(rbf, fresh);
+        assert_eq!(rbf.as_points(), [p(0, 0)]);
+        assert_eq!(rbf.last_arrivals_window.capacity(), window_capacity);
+        assert_eq!(rbf.curve.buckets.iter().map(|b| b.capacity()).sum::<usize>(), allocated);
+
+        rbf.add_arrivals(&arrivals);
+        fresh.add_arrivals(&arrivals);
+        assert_eq!(rbf, fresh);
+        assert_eq!(rbf.as_points(), fresh.as_points());
+    }
+
+    #[test]
+    fn lenient_arrivals() {
+        // A stray arrival at 25 in the middle of a periodic sequence
+        let arrivals = [(0, 2), (10, 2), (20, 2), (30, 2), (25, 2), (40, 2), (50, 2)];
+        let mut expected = RbfCurve::new(1, 1000);
+        expected.add_arrivals(&[0, 10, 20, 30, 40, 50].map(|t| (Time::from_ns(t), Time::from_ns(2))));
+
+        // Skipped
+        let mut rbf = RbfCurve::new(1, 1000);
+        let skipped: Vec<_> = arrivals.iter()
+            .filter(|(t, c)| rbf.try_add_arrival(Time::from_ns(*t), Time::from_ns(*c)).is_err())
+            .collect();
+        assert_eq!(skipped, vec![&(25, 2)]);
+        assert_eq!(rbf, expected);
+
+        // Clamped to 30: two jobs in a delta of 1
+        let mut rbf = RbfCurve::new(1, 1000);
+        let clamped: Vec<_> = arrivals.iter()
+            .filter(|(t, c)| rbf.add_arrival_clamped(Time::from_ns(*t), Time::from_ns(*c)))
+            .collect();
+        assert_eq!(clamped, vec![&(25, 2)]);
+        assert_eq!(rbf.get(Time::from_ns(1)), Time::from_ns(4));
+
+        // Not an upper bound: clamping 60 to 100 stretches its distance to 0
+        let mut rbf = RbfCurve::new(1, 1000);
+        for (t, c) in [(0, 5), (50, 1), (100, 1), (60, 1)] {
+            rbf.add_arrival_clamped(Time::from_ns(t), Time::from_ns(c));
+        }
+        let mut sorted = RbfCurve::new(1, 1000);
+        sorted.add_arrivals(&[(0, 5), (50, 1), (60, 1), (100, 1)].map(|(t, c)| (Time::from_ns(t), Time::from_ns(c))));
+        assert_eq!(sorted.get(Time::from_ns(61)), Time::from_ns(7));
+        assert_eq!(rbf.get(Time::from_ns(61)), Time::from_ns(6));
+    }
+
+    #[test]
+    fn points_round_trip() {
+        let rbf = RbfCurve::from([(Time::from_ns(0 ), Time::from_ns(1 )), 
+                                  (Time::from_ns(5 ), Time::from_ns(6 )), 
+                                  (Time::from_ns(10), Time::from_ns(5 )), 
+                                  (Time::from_ns(15), Time::from_ns(50)), 
+                                  (Time::from_ns(20), Time::from_ns(5 ))]);
+
+        let points = rbf.as_points();
+        let rebuilt = RbfCurve::from_points(1, 1000, &points).unwrap();
+
+        assert_eq!(rebuilt.as_points(), points);
+        assert!(rebuilt.approx_eq(&rbf, Time::zero()));
+        assert_eq!(rebuilt.wcet, rbf.wcet);
+    }
+
+    #[test]
+    fn from_non_monotonic_points() {
+        let res = RbfCurve::from_points(1, 1000, &[p(0, 0), p(5, 10), p(10, 10)]);
+
+        assert_eq!(res, Err(ModelError::NonMonotonicCurve{prev: p(5, 10), point: p(10, 10)}));
+        assert!(RbfCurve::from_points(1, 1000, &[p(0, 0), p(10, 10), p(5, 20)]).is_err());
     }
 
     /* Support */
@@ -1,14 +1,19 @@
-use linked_list::*;
+use std::collections::BTreeSet;
+
 use crate::time::*;
 
 use super::Point;
 
+/// Points sorted by delta, split into `capacity` buckets of `bucket_size` ns.
+/// The indices of the non-empty buckets are kept in `nonempty`, so that lookups are logarithmic:
+/// a binary search within a bucket, and a range query to find the closest non-empty bucket.
 #[derive(Debug, Clone)]
 pub struct SparseMap {
-    pub buckets : Vec<LinkedList<Point>>,
+    pub buckets : Vec<Vec<Point>>,
     pub capacity : usize,
     pub bucket_size: u64,
     pub count : u64,
+    nonempty : BTreeSet<usize>,
 }
 
 impl SparseMap {
@@ -24,93 +29,117 @@ impl SparseMap {
         while p.delta.to_ns() >= self.bucket_size * (self.capacity as u64) {
             self.double_buckets();
         }
-        
-        let bi = self.bucket_index_of(p.delta);
-        let mut b = &mut (self.buckets[bi]);
-        let mut c = b.cursor();
-
-        let mut found = false;
-        c.prev(); // Go to end
-        let mut cost;
-        loop {
-            cost = c.peek_prev();
-            match cost {
-                None => break, //no cost found
-                Some(point) if point.delta == p.delta => {  // same cost found
-                    point.cost = p.cost;
-                    found = true;
-                    c.prev(); // go back by one so that next() is the new point
-                    break; 
-                }
-                Some(point) if point.delta < p.delta => { // position found
-                    c.insert(p);
-                    self.count += 1;
-                    found = true;
-                    break;
-                }
-                Some(_) => {}
-            };
-            c.prev();
-        }
 
-        if !found { // either list is empty or p should be in first position
-            c.reset();
-            c.insert(p);
+        let bi = self.bucket_index_of(p.delta);
+        let b = &mut self.buckets[bi];
+        let pos = b.partition_point(|el| el.delta < p.delta);
+        if pos < b.len() && b[pos].delta == p.delta { // same delta found
+            b[pos].cost = p.cost;
+        } else {
+            b.insert(pos, p);
             self.count += 1;
         }
+        self.nonempty.insert(bi);
 
-        // Ensuring monotonicity: removing all non increasing elements in the same bucket
+        // Ensuring monotonicity: removing all non increasing elements following the new one.
         // Monotonicity can be broken only by a continuous sequence of elements starting from the newly
-        // inserted element. So, we start by checking the current bucket. 
+        // inserted element. So, we start by checking the current bucket.
         // If the new element remains as last in the current bucket, we move to the following ones.
         if keep_monotonicity {
-            match c.peek_next() {
-                None => { panic! (); },
-                Some (el) => { assert! (el.delta == p.delta && el.cost == p.cost); }
-            }
-            c.next(); // cursor is not at the following element
-            let mut sequence_interrupted = false; 
-            let mut cbi = bi; 
+            let mut from = pos + 1;
+            let mut cbi = bi;
             loop {
-                loop {
-                    match c.peek_next() {
-                        None => { break; },
-                        Some (el) => { 
-                            if el.cost <= p.cost { 
-                                c.remove(); 
-                            } 
-                            else {
-                                sequence_interrupted = true;
-                                break;
-                            }
-                        }
-                    }
+                let b = &mut self.buckets[cbi];
+                let to = from + b[from..].iter().take_while(|el| el.cost <= p.cost).count();
+                let sequence_interrupted = to < b.len();
+
+                b.drain(from..to);
+                self.count -= (to - from) as u64;
+                if b.is_empty() {
+                    self.nonempty.remove(&cbi);
                 }
-    
-                // Move to the next bucket if the non increasing sequence didn't end
+
+                // Move to the next non-empty bucket if the non increasing sequence didn't end
                 if sequence_interrupted { break; }
-                cbi += 1;
-                if cbi >= self.capacity { break; }
-                b = &mut (self.buckets[cbi]);
-                c = b.cursor();
+                match self.nonempty.range(cbi+1..).next() {
+                    Some(next) => cbi = *next,
+                    None => break,
+                }
+                from = 0;
             }
         }
     }
 
+    /// Past the last bucket, returns the cost of the last point
     pub fn get(&self, delta: Duration) -> Cost {
+        if self.capacity == 0 { return Time::zero(); }
+
+        // biggest bucket index that could contain the cost
+        let bi = self.bucket_index_of(delta).min(self.capacity - 1);
+        let b = &self.buckets[bi];
+        let pos = b.partition_point(|el| el.delta <= delta);
+        if pos > 0 { return b[pos - 1].cost; } // found
+
+        // otherwise, the last point of the closest non-empty bucket before
+        self.nonempty.range(..bi)
+                     .next_back()
+                     .and_then(|prev| self.buckets[*prev].last())
+                     .map_or(Time::zero(), |el| el.cost)
+    }
+
+    // Returns the cost of the smallest stored delta that is >= delta, None if there is no such delta
+    pub fn get_upper(&self, delta: Duration) -> Option<Cost> {
         let max = self.bucket_size * (self.capacity as u64);
-        if max <= delta.to_ns() { return Time::zero(); }
+        if max <= delta.to_ns() { return None; }
 
-        let mut bi = self.bucket_index_of(delta); // start with biggest bucket index that could contain the cost
-        loop {
-            let b = &self.buckets[bi];
-            for el in b.iter().rev() {
-                if el.delta <= delta { return el.cost; } // found
-            }
+        // smallest bucket index that could contain the cost
+        let bi = self.bucket_index_of(delta);
+        let b = &self.buckets[bi];
+        let pos = b.partition_point(|el| el.delta < delta);
+        if pos < b.len() { return Some(b[pos].cost); } // found
+
+        // otherwise, the first point of the closest non-empty bucket after
+        self.nonempty.range(bi+1..)
+                     .next()
+                     .and_then(|next| self.buckets[*next].first())
+                     .map(|el| el.cost)
+    }
+
+    /// Removes the points with a delta greater than `max_delta`
+    pub fn truncate(&mut self, max_delta: Duration) {
+        let first = self.bucket_index_of(max_delta);
+        if first >= self.capacity { return; }
 
-            if bi == 0 { return Time::zero(); } // not found
-            bi -= 1;
+        let bucket = &mut self.buckets[first];
+        let kept = bucket.partition_point(|el| el.delta <= max_delta);
+        self.count -= (bucket.len() - kept) as u64;
+        bucket.truncate(kept);
+        if bucket.is_empty() {
+            self.nonempty.remove(&first);
         }
+
+        for bucket in &mut self.buckets[first+1..] {
+            self.count -= bucket.len() as u64;
+            bucket.clear();
+        }
+        self.nonempty.split_off(&(first + 1));
+    }
+
+    /// Approximate number of bytes allocated on the heap for the buckets and their points
+    pub fn heap_size(&self) -> usize {
+        let points: usize = self.buckets.iter().map(|b| b.capacity()).sum();
+
+        self.buckets.capacity() * std::mem::size_of::<Vec<Point>>()
+            + points * std::mem::size_of::<Point>()
+            + self.nonempty.len() * std::mem::size_of::<usize>()
+    }
+
+    /// Removes all the points, keeping the allocated buckets and their current size
+    pub fn clear(&mut self) {
+        for bi in std::mem::take(&mut self.nonempty) {
+            self.buckets[bi].clear();
+        }
+        self.count = 0;
     }
 
     pub fn bucket_index_of(&self, delta : Duration) -> usize { 
@@ -118,30 +147,38 @@ impl SparseMap {
     }
 
     // Used when a new element cannot fit
+    // Buckets 2i and 2i+1 are merged (in order) into bucket i.
+    // With an odd capacity, the last bucket has no pair and is moved alone.
     fn double_buckets(&mut self) {
         self.bucket_size *=2;
-        for i in (0..self.capacity/2).step_by(1) {
-            let mut l = LinkedList::new();
-            l.append(&mut self.buckets[i*2]);
-            if i*2 < self.capacity { l.append(&mut self.buckets[i*2+1]); }
-            
-            self.buckets[i].append(&mut l);
+        for i in 0..self.capacity.div_ceil(2) {
+            // Bucket i has already been merged into bucket i/2 (or is bucket 0)
+            let mut l = std::mem::take(&mut self.buckets[i*2]);
+            if i*2 + 1 < self.capacity { l.append(&mut self.buckets[i*2+1]); }
+
+            self.buckets[i] = l;
         }
+
+        self.nonempty = (0..self.capacity).filter(|i| !self.buckets[*i].is_empty()).collect();
     }
 
     pub fn new(capacity: usize) -> Self {
-        let mut map = SparseMap {
+        Self::with_bucket_size(capacity, Time::from_ns(1))
+    }
+
+    /// Like `new`, but starting from buckets of `bucket_size` instead of 1ns.
+    /// With a bucket size close to the expected distance between points (e.g. the period of the task for an RBF),
+    /// the buckets do not have to be doubled while the map fills up.
+    pub fn with_bucket_size(capacity: usize, bucket_size: Duration) -> Self {
+        assert!(bucket_size > Time::zero(), "The bucket size must be positive");
+
+        SparseMap {
             capacity,
-            buckets : Vec::<LinkedList<Point>>::with_capacity(capacity),
-            bucket_size : 1,
+            buckets : vec![Vec::new(); capacity],
+            bucket_size : bucket_size.to_ns(),
             count : 0,
-        };
-
-        for _ in 0..capacity {
-            map.buckets.push(<LinkedList<Point>>::new());
+            nonempty : BTreeSet::new(),
         }
-
-        map
     }
 }
 
@@ -161,7 +198,7 @@ impl<'a> IntoIterator for &'a SparseMap {
 pub struct SparseMapIterator<'a> {
     map: &'a SparseMap,
     bucket_idx: usize,
-    list_iter: Option<linked_list::Iter<'a, Point>>,
+    list_iter: Option<std::slice::Iter<'a, Point>>,
 }
 
 impl<'a> Iterator for SparseMapIterator<'a> {
@@ -205,4 +242,115 @@ impl PartialEq for SparseMap {
     }
 }
 
-impl Eq for SparseMap {}
\ No newline at end of file
+impl Eq for SparseMap {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_size_hint() {
+        // 100 points every 10ms
+        let points: Vec<Point> = (0..100).map(|i| Point::new(Time::from_ms(10. * i as f64), Time::from_ns(i + 1))).collect();
+
+        let mut map = SparseMap::new(100);
+        let mut hinted = SparseMap::with_bucket_size(100, Time::from_ms(10.));
+        for point in &points {
+            map.add(*point);
+            hinted.add(*point);
+        }
+
+        assert!(map.bucket_size > 1);
+        assert_eq!(hinted.bucket_size, Time::from_ms(10.).to_ns()); // Never doubled
+        assert_eq!(hinted, map);
+        for point in &points {
+            assert_eq!(hinted.get(point.delta), point.cost);
+        }
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = SparseMap::new(100);
+        for i in 0..100 {
+            map.add(Point::new(Time::from_ms(10. * i as f64), Time::from_ns(i + 1)));
+        }
+        let bucket_size = map.bucket_size;
+        let allocated: usize = map.buckets.iter().map(|b| b.capacity()).sum();
+
+        map.clear();
+
+        assert_eq!(map.count, 0);
+        assert_eq!(map.into_iter().count(), 0);
+        assert_eq!(map.get(Time::from_ms(500.)), Time::zero());
+        assert_eq!(map.get_upper(Time::zero()), None);
+        assert_eq!(map.bucket_size, bucket_size);
+        assert_eq!(map.buckets.iter().map(|b| b.capacity()).sum::<usize>(), allocated);
+
+        map.add(Point::new(Time::from_ms(5.), Time::from_ns(1)));
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), [Point::new(Time::from_ms(5.), Time::from_ns(1))]);
+    }
+
+    #[test]
+    fn double_buckets_odd_capacity() {
+        let mut map = SparseMap::new(3);
+        let points = [p(0, 0), p(1, 10), p(2, 20), p(5, 30)];
+
+        for point in &points[..3] {
+            map.add(*point);
+        }
+        // Does not fit in 3 buckets of size 1
+        map.add(points[3]);
+
+        assert_eq!(map.bucket_size, 2);
+        for point in &points {
+            assert_eq!(map.get(point.delta), point.cost);
+        }
+        assert_eq!(map.into_iter().collect::<Vec<Point>>(), points);
+    }
+
+    #[test]
+    fn double_buckets_even_capacity() {
+        let mut map = SparseMap::new(4);
+        let points = [p(0, 0), p(1, 10), p(2, 20), p(3, 30), p(13, 40)];
+
+        for point in &points {
+            map.add(*point);
+        }
+
+        assert_eq!(map.bucket_size, 4);
+        for point in &points {
+            assert_eq!(map.get(point.delta), point.cost);
+        }
+        assert_eq!(map.into_iter().collect::<Vec<Point>>(), points);
+    }
+
+    #[test]
+    fn get_matches_linear_scan() {
+        // Sparse deltas, so that many buckets are empty
+        let mut map = SparseMap::new(1000);
+        for i in 0..10_000u64 {
+            map.add(p(i * i % 7_919 + i * 50_000, i * 3));
+        }
+        let points: Vec<Point> = map.into_iter().collect();
+        assert_eq!(map.count as usize, points.len());
+
+        let queries: Vec<Duration> = (0..1_000u64).map(|i| Time::from_ns(i * 500_111)).collect();
+        let costs: Vec<Cost> = queries.iter().map(|delta| map.get(*delta)).collect();
+        let expected: Vec<Cost> = queries.iter()
+            .map(|delta| points.iter().rev().find(|el| el.delta <= *delta).map_or(Time::zero(), |el| el.cost))
+            .collect();
+
+        assert_eq!(costs, expected);
+
+        for delta in &queries {
+            let upper = points.iter().find(|el| el.delta >= *delta).map(|el| el.cost);
+            assert_eq!(map.get_upper(*delta), upper);
+        }
+    }
+
+    /* Support */
+
+    fn p(delta_ns: u64, cost_ns: u64) -> Point {
+        Point::new(Time::from_ns(delta_ns), Time::from_ns(cost_ns))
+    }
+}
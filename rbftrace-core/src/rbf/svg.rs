@@ -0,0 +1,70 @@
+//! SVG rendering of the RBF curves, without any dependency.
+
+use std::fmt::Write;
+
+use crate::time::Time;
+
+use super::{RbfCurve, Point};
+
+/// Space left around the plot for the axes and their labels, in pixels
+const MARGIN: f64 = 40.;
+
+impl RbfCurve {
+    /// Renders the curve as a standalone SVG image of `width` x `height` pixels: the steps are drawn as a polyline,
+    /// from delta 0 to the last point, with the axes labeled with the largest delta and cost (in ns).
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let points: Vec<Point> = self.curve.into_iter().collect();
+        let max_delta = points.last().map_or(Time::zero(), |p| p.delta).to_ns().max(1) as f64;
+        let max_cost = points.last().map_or(Time::zero(), |p| p.cost).to_ns().max(1) as f64;
+
+        let (width, height) = (width as f64, height as f64);
+        let plot_width = (width - 2. * MARGIN).max(1.);
+        let plot_height = (height - 2. * MARGIN).max(1.);
+        let x = |delta: Time| MARGIN + delta.to_ns() as f64 / max_delta * plot_width;
+        let y = |cost: Time| height - MARGIN - cost.to_ns() as f64 / max_cost * plot_height;
+
+        // Each point after the first one adds a horizontal and a vertical segment
+        let mut vertices = Vec::with_capacity(2 * points.len());
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                vertices.push(format!("{:.1},{:.1}", x(point.delta), y(points[i-1].cost)));
+            }
+            vertices.push(format!("{:.1},{:.1}", x(point.delta), y(point.cost)));
+        }
+
+        let (origin_x, origin_y) = (MARGIN, height - MARGIN);
+        let mut svg = String::new();
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#, w = width, h = height).unwrap();
+        writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="gray"/>"#, origin_x, origin_y, width - MARGIN, origin_y).unwrap();
+        writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="gray"/>"#, origin_x, origin_y, origin_x, MARGIN).unwrap();
+        writeln!(svg, r#"<text x="{:.1}" y="{:.1}" font-size="10" text-anchor="end">{}</text>"#, width - MARGIN, height - MARGIN / 2., max_delta).unwrap();
+        writeln!(svg, r#"<text x="{:.1}" y="{:.1}" font-size="10" text-anchor="end">{}</text>"#, MARGIN - 4., MARGIN, max_cost).unwrap();
+        writeln!(svg, r#"<polyline fill="none" stroke="black" points="{}"/>"#, vertices.join(" ")).unwrap();
+        writeln!(svg, "</svg>").unwrap();
+
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rbf::RbfCurve, time::Time};
+
+    #[test]
+    fn periodic() {
+        let rbf = RbfCurve::from([(Time::from_ns(0), Time::from_ns(5)),
+                                  (Time::from_ns(5), Time::from_ns(5)),
+                                  (Time::from_ns(10), Time::from_ns(5)),
+                                  (Time::from_ns(15), Time::from_ns(5)),
+                                  (Time::from_ns(20), Time::from_ns(5))]);
+        let svg = rbf.to_svg(400, 300);
+
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        let points = svg.split(r#"points=""#).nth(1).unwrap().split('"').next().unwrap();
+        // 6 points: the first one, then a horizontal and a vertical segment for each step
+        let vertices: Vec<&str> = points.split(' ').collect();
+        assert_eq!(vertices.len(), 11);
+        assert_eq!(vertices[0], "40.0,260.0"); // Origin
+        assert_eq!(*vertices.last().unwrap(), "360.0,40.0"); // Last point, in the top right corner
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
+use crate::model::DeadlineTask;
+
 pub type Pid = u32;
 pub type Cpu = u32;
 pub type Priority = u32;
@@ -40,6 +42,9 @@ pub struct SysConf {
     pub dl_pids : Vec<Pid>,
     /// SCHED_DEADLINE threads with slack reclamation
     pub dl_slack_rec_pids : Vec<Pid>,
+    /// Reservations of the SCHED_DEADLINE threads, read once at detection
+    #[serde(default)]
+    pub dl_reservations: HashMap<Pid, DeadlineTask>,
     /// Processes that must be analyzed, which is a subset of (rt_pids U dl_pids)
     pub target_pids : Vec<Pid>,                  
     pub kthread_pids : Vec<Pid>,                
@@ -66,6 +71,11 @@ pub struct SysConf {
     pub rt_runtime_is_global : bool,
     /// The rt_runtime limit can be avoided if there are no threads to starve (RT_RUNTIME_GREED)
     pub rt_runtime_is_greedy : bool,
+
+    /* Frequency scaling */
+    /// CPU frequency (Hz) to which execution times are normalized, None if not set
+    #[serde(default)]
+    pub ref_cpu_freq_hz: Option<u64>,
 }
 
 impl Default for SysConf {
@@ -78,6 +88,7 @@ impl Default for SysConf {
             rr_pids : Vec::default(),
             dl_pids : Vec::default(),
             dl_slack_rec_pids : Vec::default(),
+            dl_reservations : HashMap::default(),
             target_pids : Vec::default(),
             kthread_pids : Vec::default(),
             rt_threads_info : HashMap::default(),
@@ -88,8 +99,116 @@ impl Default for SysConf {
             rt_runtime_is_global : false,
             rt_runtime_is_greedy : false,
             procs_max_runtimes: Vec::default(),
+            ref_cpu_freq_hz: None,
+        }
+    }
+}
+
+/* Lookups in rt_threads_info, None for threads that are not real-time or were not detected */
+impl SysConf {
+    pub fn affinity_of(&self, pid: Pid) -> Option<&[Cpu]> {
+        self.rt_threads_info.get(&pid).map(|info| info.affinity.as_slice())
+    }
+
+    pub fn priority_of(&self, pid: Pid) -> Option<Priority> {
+        self.rt_threads_info.get(&pid).map(|info| info.prio)
+    }
+
+    pub fn policy_of(&self, pid: Pid) -> Option<SchedPolicy> {
+        self.rt_threads_info.get(&pid).map(|info| info.policy)
+    }
+
+    /// Whether the pid must be analyzed, see `target_pids`
+    pub fn is_target(&self, pid: Pid) -> bool {
+        self.target_pids.contains(&pid)
+    }
+
+    /// Sum of the `runtime / period` of the reservations of `dl_pids`, i.e. the processor time reserved by SCHED_DEADLINE.
+    /// The pids whose reservation is unknown are not accounted.
+    pub fn dl_reserved_utilization(&self) -> f64 {
+        self.dl_pids.iter()
+            .filter_map(|pid| self.dl_reservations.get(pid))
+            .map(|task| task.utilization())
+            .sum()
+    }
+
+    /// Processor time available to real-time threads, in number of cores: all the cores,
+    /// minus what the RT throttling (`rt_runtime` / `rt_period`) keeps for the other threads
+    pub fn rt_capacity(&self) -> f64 {
+        self.n_cores as f64 * self.rt_runtime_ratio()
+    }
+
+    /// Fraction of each `rt_period` that real-time threads can use on a single core before being throttled.
+    /// With RT_RUNTIME_SHARE (`rt_runtime_is_global`), a core borrows the unused runtime of the other cores up to
+    /// the whole period, so the budget of a core is 1, and only the total over all the cores is bounded (see `rt_capacity`).
+    pub fn rt_budget_fraction(&self) -> f64 {
+        if self.rt_runtime_is_global && self.n_cores > 1 {
+            1.0
+        } else {
+            self.rt_runtime_ratio()
+        }
+    }
+
+    fn rt_runtime_ratio(&self) -> f64 {
+        if self.rt_period > 0 && self.rt_runtime >= 0 {
+            self.rt_runtime as f64 / self.rt_period as f64
+        } else {
+            1.0 // A negative rt_runtime disables the throttling
         }
     }
+
+    /// (measured, reference) frequencies to scale the costs measured with the CPU at `measured_hz` to `ref_cpu_freq_hz`,
+    /// see `Time::scale_frequency`. None if no reference frequency is set.
+    pub fn frequency_scaling(&self, measured_hz: u64) -> Option<(u64, u64)> {
+        self.ref_cpu_freq_hz.map(|ref_hz| (measured_hz, ref_hz))
+    }
+
+    /// Whether the SCHED_DEADLINE reservations exceed the available capacity, see `rt_capacity`
+    pub fn dl_overcommitted(&self) -> bool {
+        self.dl_reserved_utilization() > self.rt_capacity()
+    }
+
+    /// Like `==`, but ignoring the order of lists whose order is not meaningful, e.g. when comparing two detections
+    /// that enumerate pids in a different order: the pid lists, affinities, runtime limits, clusters and their CPUs.
+    /// The threads of a cluster are ordered by decreasing priority, so that order must match,
+    /// but threads with the same priority can appear in any order.
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        let pid_lists = |c: &SysConf| [
+            sorted(&c.rt_pids), sorted(&c.fifo_pids), sorted(&c.rr_pids), sorted(&c.dl_pids),
+            sorted(&c.dl_slack_rec_pids), sorted(&c.target_pids), sorted(&c.kthread_pids),
+        ];
+        let runtime_limits = |c: &SysConf| sorted(&c.procs_max_runtimes.iter().map(|l| (l.pid, l.max_runtime)).collect::<Vec<_>>());
+        let clusters = |c: &SysConf| {
+            let mut clusters: Vec<Cluster> = c.rt_threads_info_clusters.iter().map(Cluster::normalized).collect();
+            clusters.sort_by_key(|cluster| cluster.id);
+            clusters
+        };
+        let reservations_eq = self.dl_reservations == other.dl_reservations;
+        let threads_eq = self.rt_threads_info.len() == other.rt_threads_info.len()
+            && self.rt_threads_info.iter().all(|(pid, info)| {
+                other.rt_threads_info.get(pid).is_some_and(|o| info.normalized() == o.normalized())
+            });
+
+        self.multiproc == other.multiproc
+            && self.n_cores == other.n_cores
+            && pid_lists(self) == pid_lists(other)
+            && reservations_eq
+            && threads_eq
+            && clusters(self) == clusters(other)
+            && self.max_runtimes == other.max_runtimes
+            && runtime_limits(self) == runtime_limits(other)
+            && self.rt_period == other.rt_period
+            && self.rt_runtime == other.rt_runtime
+            && self.rt_runtime_is_global == other.rt_runtime_is_global
+            && self.rt_runtime_is_greedy == other.rt_runtime_is_greedy
+            && self.ref_cpu_freq_hz == other.ref_cpu_freq_hz
+    }
+}
+
+fn sorted<T: Ord + Clone>(list: &[T]) -> Vec<T> {
+    let mut ret = list.to_vec();
+    ret.sort_unstable();
+    ret
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -102,6 +221,13 @@ pub struct ThreadInfo {
     pub is_kthread: bool
 }
 
+impl ThreadInfo {
+    /// With a sorted affinity, see `SysConf::semantically_eq`
+    fn normalized(&self) -> Self {
+        ThreadInfo { affinity: sorted(&self.affinity), ..self.clone() }
+    }
+}
+
 impl Default for ThreadInfo {
     fn default() -> Self { 
         ThreadInfo {
@@ -131,6 +257,16 @@ impl Cluster {
             threads,
         }
     }
+
+    /// With sorted CPUs, and threads of the same priority sorted by pid, see `SysConf::semantically_eq`
+    fn normalized(&self) -> Self {
+        let mut threads: Vec<ThreadInfo> = self.threads.iter().map(ThreadInfo::normalized).collect();
+        for same_prio in threads.chunk_by_mut(|a, b| a.prio == b.prio) {
+            same_prio.sort_unstable_by_key(|thread| thread.pid);
+        }
+
+        Cluster { id: self.id, cpus: sorted(&self.cpus), threads }
+    }
 }
 
 /* Hard consecutive runtime limit imposed on a process. */
@@ -146,3 +282,123 @@ pub struct Core {
     pub id: Cpu,
     pub logical_cpu_ids: Vec<Cpu>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::Time;
+
+    #[test]
+    fn accessors() {
+        let mut sys_conf = SysConf {
+            target_pids: vec![1],
+            ..Default::default()
+        };
+        sys_conf.rt_threads_info.insert(1, ThreadInfo { pid: 1, prio: 50, policy: SchedPolicy::FIFO, affinity: vec![0, 1], is_target: true, ..Default::default() });
+        sys_conf.rt_threads_info.insert(2, ThreadInfo { pid: 2, prio: 10, policy: SchedPolicy::RR, affinity: vec![2], ..Default::default() });
+
+        assert_eq!(sys_conf.affinity_of(1), Some(&[0, 1][..]));
+        assert_eq!(sys_conf.affinity_of(2), Some(&[2][..]));
+        assert_eq!(sys_conf.priority_of(1), Some(50));
+        assert_eq!(sys_conf.policy_of(2), Some(SchedPolicy::RR));
+        assert!(sys_conf.is_target(1));
+        assert!(!sys_conf.is_target(2));
+
+        assert_eq!(sys_conf.frequency_scaling(2_000_000_000), None);
+        sys_conf.ref_cpu_freq_hz = Some(1_000_000_000);
+        assert_eq!(sys_conf.frequency_scaling(2_000_000_000), Some((2_000_000_000, 1_000_000_000)));
+
+        // Unknown pid
+        assert_eq!(sys_conf.affinity_of(3), None);
+        assert_eq!(sys_conf.priority_of(3), None);
+        assert_eq!(sys_conf.policy_of(3), None);
+        assert!(!sys_conf.is_target(3));
+    }
+
+    #[test]
+    fn dl_reserved_utilization() {
+        let mut sys_conf = SysConf {
+            n_cores: 2,
+            dl_pids: vec![1, 2, 3],
+            rt_period: 1_000_000,
+            rt_runtime: 950_000,
+            ..Default::default()
+        };
+        sys_conf.dl_reservations.insert(1, DeadlineTask::new(Time::from_ms(2.), Time::from_ms(10.), Time::from_ms(10.)));
+        sys_conf.dl_reservations.insert(2, DeadlineTask::new(Time::from_ms(16.), Time::from_ms(20.), Time::from_ms(20.)));
+        // Not a SCHED_DEADLINE pid anymore
+        sys_conf.dl_reservations.insert(4, DeadlineTask::new(Time::from_ms(1.), Time::from_ms(1.), Time::from_ms(1.)));
+
+        assert!((sys_conf.dl_reserved_utilization() - 1.0).abs() < 1e-9);
+        assert!((sys_conf.rt_capacity() - 1.9).abs() < 1e-9);
+        assert!(!sys_conf.dl_overcommitted());
+
+        sys_conf.n_cores = 1;
+        assert!(sys_conf.dl_overcommitted());
+        sys_conf.rt_runtime = -1;
+        assert!(!sys_conf.dl_overcommitted());
+
+        assert_eq!(SysConf::default().dl_reserved_utilization(), 0.0);
+    }
+
+    #[test]
+    fn rt_budget_fraction() {
+        let mut sys_conf = SysConf { n_cores: 4, rt_period: 1_000_000, rt_runtime: 950_000, ..Default::default() };
+        assert!((sys_conf.rt_budget_fraction() - 0.95).abs() < 1e-9);
+        assert!((sys_conf.rt_capacity() - 3.8).abs() < 1e-9);
+
+        // Borrowing from the other cores
+        sys_conf.rt_runtime_is_global = true;
+        assert_eq!(sys_conf.rt_budget_fraction(), 1.0);
+        assert!((sys_conf.rt_capacity() - 3.8).abs() < 1e-9);
+        // Nothing to borrow from
+        sys_conf.n_cores = 1;
+        assert!((sys_conf.rt_budget_fraction() - 0.95).abs() < 1e-9);
+
+        sys_conf.rt_runtime = -1;
+        assert_eq!(sys_conf.rt_budget_fraction(), 1.0);
+        assert_eq!(sys_conf.rt_capacity(), 1.0);
+    }
+
+    #[test]
+    fn semantically_eq() {
+        let thread = |pid, prio, affinity: Vec<Cpu>| ThreadInfo { pid, prio, affinity, ..Default::default() };
+        let mut sys_conf = SysConf {
+            rt_pids: vec![1, 2, 3],
+            target_pids: vec![1, 3],
+            procs_max_runtimes: vec![RuntimeLimit { pid: 1, max_runtime: 10 }, RuntimeLimit { pid: 2, max_runtime: 20 }],
+            rt_threads_info_clusters: vec![
+                Cluster::new(0, vec![0, 1], vec![thread(1, 50, vec![0, 1]), thread(2, 10, vec![0]), thread(3, 10, vec![1])]),
+                Cluster::new(1, vec![2], vec![]),
+            ],
+            ..Default::default()
+        };
+        sys_conf.rt_threads_info.insert(1, thread(1, 50, vec![0, 1]));
+
+        let mut reordered = SysConf {
+            rt_pids: vec![3, 1, 2],
+            target_pids: vec![3, 1],
+            procs_max_runtimes: vec![RuntimeLimit { pid: 2, max_runtime: 20 }, RuntimeLimit { pid: 1, max_runtime: 10 }],
+            rt_threads_info_clusters: vec![
+                Cluster::new(1, vec![2], vec![]),
+                // Threads 2 and 3 have the same priority
+                Cluster::new(0, vec![1, 0], vec![thread(1, 50, vec![1, 0]), thread(3, 10, vec![1]), thread(2, 10, vec![0])]),
+            ],
+            ..Default::default()
+        };
+        reordered.rt_threads_info.insert(1, thread(1, 50, vec![1, 0]));
+
+        assert_ne!(sys_conf, reordered);
+        assert!(sys_conf.semantically_eq(&reordered));
+        assert!(reordered.semantically_eq(&sys_conf));
+
+        // The priority order of the threads is meaningful
+        let mut misordered = reordered.clone();
+        misordered.rt_threads_info_clusters[1].threads.swap(0, 1);
+        assert!(!sys_conf.semantically_eq(&misordered));
+
+        let mut missing = reordered;
+        missing.target_pids.pop();
+        assert!(!sys_conf.semantically_eq(&missing));
+    }
+}
@@ -1,7 +1,7 @@
-use std::{ops::{Add, AddAssign, Sub, SubAssign, Div, Rem, RemAssign, DivAssign, Mul, MulAssign}, fmt::Display, str::FromStr, num::ParseIntError};
+use std::{ops::{Add, AddAssign, Sub, SubAssign, Div, Rem, RemAssign, DivAssign, Mul, MulAssign}, fmt::Display, str::FromStr};
 
 use duplicate::duplicate;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer, Deserializer, de::{self, Visitor}};
 
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Serialize, Deserialize, Debug, Default)]
 #[serde(from="u64")]
@@ -19,10 +19,49 @@ pub type Mit = Time;
 pub type Offset = Time;
 
 impl Time {
+    /// The largest representable time
+    pub const MAX: Time = Time { ns: u64::MAX };
+
     pub const fn zero() -> Self {
         Time { ns: 0 }
     }
 
+    /// Returns None on overflow
+    pub fn checked_add(self, rhs: Time) -> Option<Time> {
+        self.ns.checked_add(rhs.ns).map(Time::from_ns)
+    }
+
+    /// Returns `Time::MAX` on overflow
+    pub fn saturating_add(self, rhs: Time) -> Time {
+        self.checked_add(rhs).unwrap_or(Time::MAX)
+    }
+
+    /// Center of the interval between `a` and `b`, in any order, rounded down to the ns.
+    /// Unlike `(a + b) / 2`, it cannot overflow.
+    pub fn midpoint(a: Time, b: Time) -> Time {
+        let (lower, upper) = if a <= b { (a, b) } else { (b, a) };
+
+        lower + (upper - lower) / 2_u32
+    }
+
+    /// Execution time at `to_hz` of a computation that took `self` at `from_hz` (rounded to the nearest ns),
+    /// assuming it scales inversely with the CPU frequency, i.e. it is CPU-bound.
+    pub fn scale_frequency(self, from_hz: u64, to_hz: u64) -> Time {
+        assert!(from_hz > 0 && to_hz > 0, "Frequencies must be > 0");
+        let ns = (self.ns as u128 * from_hz as u128 + to_hz as u128 / 2) / to_hz as u128;
+
+        Time::from_ns(ns.min(u64::MAX as u128) as u64)
+    }
+
+    /// `self * num / den` computed exactly (rounded down), e.g. `period.mul_ratio(3, 2)`, without the drift of a float factor.
+    /// Saturates at `Time::MAX`.
+    pub fn mul_ratio(self, num: u64, den: u64) -> Time {
+        assert!(den > 0, "The denominator must be > 0");
+        let ns = self.ns as u128 * num as u128 / den as u128;
+
+        Time::from_ns(ns.min(u64::MAX as u128) as u64)
+    }
+
     pub fn is_zero(&self) -> bool {
         self.ns == 0
     }
@@ -65,6 +104,23 @@ impl Time {
         (self.ns as f64) / ((10_f64).powi(9))
     }
 
+    /// Converts the fields of a `struct timespec`.
+    /// Returns None if a field is negative or if the time can't be represented.
+    pub fn from_timespec(sec: i64, nsec: i64) -> Option<Self> {
+        if sec < 0 || nsec < 0 {
+            return None;
+        }
+
+        (sec as u64).checked_mul(1_000_000_000)
+                    .and_then(|ns| ns.checked_add(nsec as u64))
+                    .map(Time::from_ns)
+    }
+
+    /// Returns the fields of a `struct timespec`, `(tv_sec, tv_nsec)` with `tv_nsec` < 10^9
+    pub fn to_timespec(&self) -> (i64, i64) {
+        ((self.ns / 1_000_000_000) as i64, (self.ns % 1_000_000_000) as i64)
+    }
+
     /// Returns the leftmost multiple of resolution
     pub fn truncate(&self, resolution: Time) -> Time {
         let new_ns = (self.ns / resolution.ns) * resolution.ns;
@@ -244,19 +300,160 @@ impl Display for Time {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseTimeError {
+    InvalidNumber(String),
+    UnknownUnit(String),
+}
+
+impl Display for ParseTimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseTimeError::InvalidNumber(s) => write!(f, "invalid time value \"{}\"", s),
+            ParseTimeError::UnknownUnit(s) => write!(f, "unknown time unit \"{}\" (expected ns, us, ms or s)", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseTimeError {}
+
+/* Units accepted by FromStr, from the largest */
+const UNITS: [(&str, u64); 4] = [("s", 1_000_000_000), ("ms", 1_000_000), ("us", 1_000), ("ns", 1)];
+
+/// Unit of a number parsed without an explicit unit, see `Time::parse_with_default_unit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl TimeUnit {
+    pub fn ns_per_unit(&self) -> u64 {
+        match self {
+            TimeUnit::Ns => 1,
+            TimeUnit::Us => 1_000,
+            TimeUnit::Ms => 1_000_000,
+            TimeUnit::S => 1_000_000_000,
+        }
+    }
+}
+
+impl Time {
+    /// Parses a number optionally followed by a unit (ns, us, ms or s), e.g. "10ms" or "1.5 s".
+    /// Without a unit, the number is in `default` units, e.g. "1" is 1ms with `TimeUnit::Ms`.
+    pub fn parse_with_default_unit(s: &str, default: TimeUnit) -> Result<Time, ParseTimeError> {
+        let s = s.trim();
+        let unit_start = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (value, unit) = (s[..unit_start].trim(), &s[unit_start..]);
+
+        let multiplier = if unit.is_empty() {
+            default.ns_per_unit()
+        } else {
+            UNITS.iter()
+                 .find(|(name, _)| *name == unit)
+                 .map(|(_, multiplier)| *multiplier)
+                 .ok_or_else(|| ParseTimeError::UnknownUnit(unit.to_string()))?
+        };
+
+        if let Ok(n) = value.parse::<u64>() {
+            return n.checked_mul(multiplier)
+                    .map(Time::from_ns)
+                    .ok_or_else(|| ParseTimeError::InvalidNumber(value.to_string()));
+        }
+
+        match value.parse::<f64>() {
+            Ok(n) if n.is_finite() && n >= 0.0 => Ok(Time::from_ns((n * multiplier as f64).round() as u64)),
+            _ => Err(ParseTimeError::InvalidNumber(value.to_string())),
+        }
+    }
+}
+
+/// Parses a number optionally followed by a unit (ns, us, ms or s), e.g. "10ms" or "1.5 s".
+/// Without a unit, the number is in nanoseconds.
 impl FromStr for Time {
-    type Err = ParseIntError;
+    type Err = ParseTimeError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let ns = s.parse::<u64>()?;
+        Time::parse_with_default_unit(s, TimeUnit::Ns)
+    }
+}
+
+/// A Time that is (de)serialized as a human-readable string, e.g. "10ms", using the
+/// largest unit that represents it exactly.
+/// Deserialization also accepts plain nanosecond integers, so files written with the
+/// default numeric form of `Time` can still be read.
+/// When formatted with a precision, e.g. `{:.3}`, the largest unit not greater than the
+/// time is used instead, rounded to that many decimals ("1.500us").
+#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Debug, Default)]
+pub struct HumanTime(pub Time);
+
+impl From<Time> for HumanTime {
+    fn from(time: Time) -> Self {
+        HumanTime(time)
+    }
+}
 
-        Ok(Time::from_ns(ns))
+impl From<HumanTime> for Time {
+    fn from(time: HumanTime) -> Self {
+        time.0
+    }
+}
+
+impl Display for HumanTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ns = self.0.ns;
+        if let Some(precision) = f.precision() {
+            let (unit, multiplier) = UNITS.iter()
+                                          .find(|(_, multiplier)| ns >= *multiplier)
+                                          .unwrap_or(&("ns", 1));
+            return write!(f, "{:.*}{}", precision, ns as f64 / *multiplier as f64, unit);
+        }
+        if ns == 0 {
+            return write!(f, "0ns");
+        }
+
+        let (unit, multiplier) = UNITS.iter()
+                                      .find(|(_, multiplier)| ns.is_multiple_of(*multiplier))
+                                      .unwrap(); // "ns" always divides
+        write!(f, "{}{}", ns / multiplier, unit)
+    }
+}
+
+impl Serialize for HumanTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HumanTimeVisitor;
+
+        impl<'de> Visitor<'de> for HumanTimeVisitor {
+            type Value = HumanTime;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a time such as \"10ms\", or an integer number of nanoseconds")
+            }
+
+            fn visit_u64<E: de::Error>(self, ns: u64) -> Result<Self::Value, E> {
+                Ok(HumanTime(Time::from_ns(ns)))
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                s.parse::<Time>().map(HumanTime).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(HumanTimeVisitor)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::time::Time;
+    use crate::time::{Time, HumanTime, ParseTimeError, TimeUnit};
 
     #[test]
     fn test_truncate() {
@@ -285,4 +482,103 @@ mod tests {
         assert_eq!(t4.round(r2), Time::from_ms(1.5));
     }
 
+    #[test]
+    fn test_from_str() {
+        assert_eq!("1500".parse::<Time>(), Ok(Time::from_ns(1500)));
+        assert_eq!("10ms".parse::<Time>(), Ok(Time::from_ms(10.)));
+        assert_eq!("1.5 s".parse::<Time>(), Ok(Time::from_ms(1500.)));
+        assert_eq!("250us".parse::<Time>(), Ok(Time::from_us(250.)));
+        assert_eq!("10h".parse::<Time>(), Err(ParseTimeError::UnknownUnit("h".to_string())));
+        assert!("-1ms".parse::<Time>().is_err());
+    }
+
+    #[test]
+    fn test_parse_with_default_unit() {
+        assert_eq!(Time::parse_with_default_unit("1", TimeUnit::Ms), Ok(Time::from_ms(1.)));
+        assert_eq!(Time::parse_with_default_unit("1.5", TimeUnit::Us), Ok(Time::from_ns(1500)));
+        assert_eq!(Time::parse_with_default_unit("2", TimeUnit::S), Ok(Time::from_s(2.)));
+        // An explicit unit wins
+        assert_eq!(Time::parse_with_default_unit("1000000ns", TimeUnit::Ms), Ok(Time::from_ms(1.)));
+        assert_eq!(Time::parse_with_default_unit("10us", TimeUnit::S), Ok(Time::from_us(10.)));
+        assert_eq!(Time::parse_with_default_unit("10h", TimeUnit::Ms), Err(ParseTimeError::UnknownUnit("h".to_string())));
+    }
+
+    #[test]
+    fn test_human_time_round_trip() {
+        for time in [Time::zero(), Time::from_ns(1500), Time::from_us(3.), Time::from_ms(10.), Time::from_s(2.)].iter() {
+            let s = serde_yaml::to_string(&HumanTime(*time)).unwrap();
+            assert_eq!(serde_yaml::from_str::<HumanTime>(&s).unwrap().0, *time);
+        }
+
+        assert_eq!(HumanTime(Time::from_ms(10.)).to_string(), "10ms");
+        assert_eq!(HumanTime(Time::from_ns(1500)).to_string(), "1500ns");
+        assert_eq!(format!("{:.3}", HumanTime(Time::from_ns(1500))), "1.500us");
+        assert_eq!(format!("{:.1}", HumanTime(Time::zero())), "0.0ns");
+        // Numeric form written by Time
+        assert_eq!(serde_yaml::from_str::<HumanTime>("10000000").unwrap().0, Time::from_ms(10.));
+    }
+
+    #[test]
+    fn test_timespec() {
+        let just_before = Time::from_ns(999_999_999);
+        let boundary = Time::from_s(1.);
+        let just_after = Time::from_ns(1_000_000_001);
+
+        assert_eq!(just_before.to_timespec(), (0, 999_999_999));
+        assert_eq!(boundary.to_timespec(), (1, 0));
+        assert_eq!(just_after.to_timespec(), (1, 1));
+        for time in [just_before, boundary, just_after, Time::MAX].iter() {
+            let (sec, nsec) = time.to_timespec();
+            assert_eq!(Time::from_timespec(sec, nsec), Some(*time));
+        }
+
+        // Non-normalized nanoseconds
+        assert_eq!(Time::from_timespec(1, 1_500_000_000), Some(Time::from_ms(2500.)));
+        assert_eq!(Time::from_timespec(-1, 0), None);
+        assert_eq!(Time::from_timespec(0, -1), None);
+        assert_eq!(Time::from_timespec(i64::MAX, 0), None);
+    }
+
+    #[test]
+    fn test_checked_add() {
+        let almost_max = Time::from_ns(u64::MAX - 1);
+
+        assert_eq!(almost_max.checked_add(Time::from_ns(1)), Some(Time::MAX));
+        assert_eq!(almost_max.checked_add(Time::from_ns(2)), None);
+        assert_eq!(almost_max.saturating_add(Time::from_ns(2)), Time::MAX);
+    }
+
+    #[test]
+    fn test_scale_frequency() {
+        assert_eq!(Time::from_ms(3.).scale_frequency(2_000_000_000, 1_000_000_000), Time::from_ms(6.));
+        assert_eq!(Time::from_ms(3.).scale_frequency(1_000_000_000, 2_000_000_000), Time::from_ms(1.5));
+        assert_eq!(Time::from_ns(1).scale_frequency(1, 3), Time::zero());
+        assert_eq!(Time::MAX.scale_frequency(2, 1), Time::MAX);
+    }
+
+    #[test]
+    fn test_mul_ratio() {
+        assert_eq!(Time::from_ms(10.).mul_ratio(3, 2), Time::from_ms(15.));
+        assert_eq!(Time::from_ms(10.).mul_ratio(1, 3), Time::from_ns(3_333_333));
+        assert_eq!(Time::from_ms(10.).mul_ratio(1, 3).mul_ratio(3, 1), Time::from_ns(9_999_999));
+        assert_eq!(Time::from_ns(7).mul_ratio(0, 5), Time::zero());
+        // No overflow in the intermediate product
+        assert_eq!(Time::from_s(100.).mul_ratio(u64::MAX / 2, u64::MAX), Time::from_s(50.) - Time::from_ns(1));
+        assert_eq!(Time::MAX.mul_ratio(2, 1), Time::MAX);
+    }
+
+    #[test]
+    fn test_midpoint() {
+        assert_eq!(Time::midpoint(Time::from_ms(10.), Time::from_ms(20.)), Time::from_ms(15.));
+        assert_eq!(Time::midpoint(Time::from_ms(20.), Time::from_ms(10.)), Time::from_ms(15.));
+        assert_eq!(Time::midpoint(Time::from_ns(7), Time::from_ns(7)), Time::from_ns(7));
+        // Rounded down
+        assert_eq!(Time::midpoint(Time::from_ns(1), Time::from_ns(4)), Time::from_ns(2));
+
+        // The sum would overflow
+        let half = Time::from_ns(u64::MAX / 2);
+        assert_eq!(Time::midpoint(half, half + Time::from_ns(2)), half + Time::from_ns(1));
+        assert_eq!(Time::midpoint(Time::MAX, Time::MAX), Time::MAX);
+        assert_eq!(Time::midpoint(Time::zero(), Time::MAX), half);
+    }
 }
\ No newline at end of file
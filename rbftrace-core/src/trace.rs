@@ -1,9 +1,11 @@
 use std::path::Path;
+use std::io::{Read, BufRead, BufReader};
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Serialize, Deserialize};
 use serde_yaml;
 
-use crate::{time::*, sys_conf::Pid};
+use crate::{time::*, sys_conf::{Pid, Cpu}};
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum TraceEventType {
@@ -24,6 +26,44 @@ impl TraceEventType {
             TraceEventType::Exit => 'E',
         }
     }
+
+    /// Whether an event of this type can legally follow an event of type `prev` for the same pid.
+    /// A job goes through Activation -> Dispatch -> (Preemption -> Dispatch)* -> Deactivation,
+    /// and a pid can exit at any time.
+    pub fn can_follow(&self, prev: TraceEventType) -> bool {
+        use TraceEventType::*;
+
+        match self {
+            Activation => prev == Deactivation,
+            Dispatch => prev == Activation || prev == Preemption,
+            Preemption | Deactivation => prev == Dispatch,
+            Exit => prev != Exit,
+        }
+    }
+}
+
+/// Tie-break between events with the same timestamp, in the order in which a job goes through them:
+/// Activation < Dispatch < Preemption < Deactivation < Exit.
+/// Note that a job that completes and is immediately re-activated (Deactivation then Activation at the same instant)
+/// is not ordered correctly, no total order can be consistent with every legal sequence.
+impl Ord for TraceEventType {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let rank = |etype: &TraceEventType| match etype {
+            TraceEventType::Activation => 0,
+            TraceEventType::Dispatch => 1,
+            TraceEventType::Preemption => 2,
+            TraceEventType::Deactivation => 3,
+            TraceEventType::Exit => 4,
+        };
+
+        rank(self).cmp(&rank(other))
+    }
+}
+
+impl PartialOrd for TraceEventType {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
@@ -31,12 +71,20 @@ pub struct TraceEvent {
     pub etype : TraceEventType,
     pub pid : Pid,
     pub instant : Time,
+    /// CPU on which the event happened, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu : Option<Cpu>,
 }
 
 impl TraceEvent {
     pub fn new(etype : TraceEventType, pid : Pid, instant : Time) -> Self {
-        TraceEvent { etype, pid, instant }
+        TraceEvent { etype, pid, instant, cpu: None }
+    }
+
+    pub fn with_cpu(self, cpu : Cpu) -> Self {
+        TraceEvent { cpu: Some(cpu), ..self }
     }
+
     pub fn activation(pid: Pid, instant : Time) -> Self {
         TraceEvent::new(TraceEventType::Activation, pid, instant)
     }
@@ -80,31 +128,202 @@ impl TraceEvent {
     
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// Compact form for logs, e.g. "A pid=1234 @ 5.000ms cpu=2" (the CPU only if known)
+impl std::fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} pid={} @ {:.3}", self.etype.short_name(), self.pid, HumanTime(self.instant))?;
+        if let Some(cpu) = self.cpu {
+            write!(f, " cpu={}", cpu)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Clock that produced the timestamps of a trace, named as in ftrace's `trace_clock`.
+/// Timestamps of different clocks can't be compared.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSource {
+    Local,
+    Global,
+    Counter,
+    Uptime,
+    Perf,
+    Mono,
+    MonoRaw,
+    Boot,
+    #[serde(rename = "x86-tsc")]
+    X86Tsc,
+}
+
+impl ClockSource {
+    /// None for clocks that are not known
+    pub fn from_ftrace_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "local" => Some(ClockSource::Local),
+            "global" => Some(ClockSource::Global),
+            "counter" => Some(ClockSource::Counter),
+            "uptime" => Some(ClockSource::Uptime),
+            "perf" => Some(ClockSource::Perf),
+            "mono" => Some(ClockSource::Mono),
+            "mono_raw" => Some(ClockSource::MonoRaw),
+            "boot" => Some(ClockSource::Boot),
+            "x86-tsc" => Some(ClockSource::X86Tsc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct Trace {
+    /// Clock of the timestamps, None if unknown, e.g. for traces written as a plain list of events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock: Option<ClockSource>,
     events: Vec<TraceEvent>
 }
 
+/// A trace file is either a plain list of events, or a trace with its metadata
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TraceFile {
+    Events(Vec<TraceEvent>),
+    Trace(Trace),
+}
+
+/// Counts of the illegal transitions observed for a single pid, see `Trace::validate_sequences`
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy)]
+pub struct SequenceReport {
+    /// Number of events of the pid
+    pub events: usize,
+    /// Activation while the previous job has not completed yet
+    pub double_activation: usize,
+    /// Dispatch not preceded by an Activation or a Preemption
+    pub dispatch_without_activation: usize,
+    /// Preemption while the pid is not running
+    pub preemption_without_dispatch: usize,
+    /// Deactivation right after another Deactivation
+    pub double_deactivation: usize,
+    /// Deactivation while the pid is not running (other than double Deactivation)
+    pub deactivation_without_dispatch: usize,
+    /// Any event after an Exit
+    pub event_after_exit: usize,
+}
+
+impl SequenceReport {
+    pub fn anomalies(&self) -> usize {
+        self.double_activation
+            + self.dispatch_without_activation
+            + self.preemption_without_dispatch
+            + self.double_deactivation
+            + self.deactivation_without_dispatch
+            + self.event_after_exit
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.anomalies() == 0
+    }
+
+    fn record(&mut self, prev: Option<TraceEventType>, event: &TraceEvent) {
+        self.events += 1;
+
+        // The trace can start at any point of a job
+        let prev = match prev {
+            Some(prev) => prev,
+            None => return,
+        };
+
+        if event.etype.can_follow(prev) {
+            return;
+        }
+
+        let counter = match (prev, event.etype) {
+            (TraceEventType::Exit, _) => &mut self.event_after_exit,
+            (_, TraceEventType::Activation) => &mut self.double_activation,
+            (_, TraceEventType::Dispatch) => &mut self.dispatch_without_activation,
+            (_, TraceEventType::Preemption) => &mut self.preemption_without_dispatch,
+            (TraceEventType::Deactivation, TraceEventType::Deactivation) => &mut self.double_deactivation,
+            (_, TraceEventType::Deactivation) => &mut self.deactivation_without_dispatch,
+            (_, TraceEventType::Exit) => unreachable!(),
+        };
+        *counter += 1;
+    }
+}
+
 #[derive(Debug)]
 pub enum TraceError {
     Monotonocity{pos: usize, prev: TraceEvent, event: TraceEvent},
     IO(std::io::Error),
-    YAMLParsing(serde_yaml::Error)
+    YAMLParsing(serde_yaml::Error),
+    /// A line of `trace-cmd report` output could not be parsed (lines are numbered from 1)
+    FtraceParsing{line: usize, content: String},
+    /// The timestamps of the two traces come from different clocks
+    ClockMismatch{left: ClockSource, right: ClockSource},
 }
 
 impl Trace {
     pub fn new() -> Trace {
         Trace{
+            clock: None,
             events: vec![]
         }
     }
 
+    pub fn with_clock(self, clock: ClockSource) -> Self {
+        Trace { clock: Some(clock), ..self }
+    }
+
+    pub fn clock(&self) -> Option<ClockSource> {
+        self.clock
+    }
+
+    /// Clock of the combination of two traces: traces with an unknown clock are assumed to share the clock of the other one
+    fn common_clock(&self, other: &Trace) -> Result<Option<ClockSource>, TraceError> {
+        match (self.clock, other.clock) {
+            (Some(left), Some(right)) if left != right => Err(TraceError::ClockMismatch{left, right}),
+            (left, right) => Ok(left.or(right)),
+        }
+    }
+
     pub fn events(&self) -> impl Iterator<Item=&TraceEvent> {
         self.events.iter()
     }
 
+    /// Beware: changing the instants may break the monotonicity of the trace, see `map_instants` for a checked alternative
+    pub fn events_mut(&mut self) -> impl Iterator<Item=&mut TraceEvent> {
+        self.events.iter_mut()
+    }
+
+    /// Replaces every instant `t` with `f(t)`, e.g. to shift or rescale the trace.
+    /// Fails if the resulting trace is not monotonic, in which case the trace is left unchanged.
+    pub fn map_instants(&mut self, f: impl Fn(Time) -> Time) -> Result<(), TraceError> {
+        let mut mapped = Trace { clock: self.clock, ..Trace::new() };
+        mapped.events.reserve(self.events.len());
+        for event in self.events() {
+            mapped.push(TraceEvent { instant: f(event.instant), ..*event })?;
+        }
+
+        *self = mapped;
+
+        Ok(())
+    }
+
     // trace specific logic for instance
 
+    /// Checks the sequence of events of every pid and counts the illegal transitions, without stopping at the first one.
+    /// Useful to decide whether a trace needs to be cleaned up before extracting a model.
+    pub fn validate_sequences(&self) -> BTreeMap<Pid, SequenceReport> {
+        let mut reports: BTreeMap<Pid, SequenceReport> = BTreeMap::new();
+        let mut last_types: BTreeMap<Pid, TraceEventType> = BTreeMap::new();
+
+        for event in self.events() {
+            let prev = last_types.insert(event.pid, event.etype);
+            reports.entry(event.pid).or_default().record(prev, event);
+        }
+
+        reports
+    }
+
     pub fn push(&mut self, e: TraceEvent) -> Result<(), TraceError> {
         if let Some(prev) = self.events().last() {
             // Activation and Dispatch can have the same timestamp
@@ -120,6 +339,224 @@ impl Trace {
         Ok(())  
     }
 
+    /// Builds a trace from events in any order, sorted by timestamp.
+    /// Events with the same timestamp are ordered by type (see `TraceEventType`'s `Ord`), then keep their relative order.
+    pub fn from_unsorted<I: IntoIterator<Item=TraceEvent>>(events: I) -> Trace {
+        let mut events: Vec<TraceEvent> = events.into_iter().collect();
+        events.sort_by_key(|e| (e.instant, e.etype));
+
+        Trace { clock: None, events }
+    }
+
+    /// Merges two traces, e.g. captured on different CPUs, keeping the relative order of the events of each trace.
+    /// Events with the same timestamp are ordered by type (see `TraceEventType`'s `Ord`), then taken from `self` first.
+    /// Fails if the traces were recorded with different clocks.
+    pub fn merge(&self, other: &Trace) -> Result<Trace, TraceError> {
+        let clock = self.common_clock(other)?;
+        let mut events = Vec::with_capacity(self.events.len() + other.events.len());
+        let mut left = self.events.iter().peekable();
+        let mut right = other.events.iter().peekable();
+
+        loop {
+            let event = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) if (r.instant, r.etype) < (l.instant, l.etype) => right.next(),
+                (Some(_), _) => left.next(),
+                (None, _) => right.next(),
+            };
+            match event {
+                Some(event) => events.push(*event),
+                None => break,
+            }
+        }
+
+        Ok(Trace { clock, events })
+    }
+
+    /// Appends the events of `other`, shifted by `offset`, e.g. to stitch captures taken in several segments.
+    /// Fails if the first shifted event precedes the last event of this trace, or if the traces were recorded with different clocks.
+    pub fn concat_with_offset(&mut self, other: &Trace, offset: Time) -> Result<(), TraceError> {
+        let clock = self.common_clock(other)?;
+        if let (Some(prev), Some(first)) = (self.events.last(), other.events.first()) {
+            let event = TraceEvent { instant: first.instant + offset, ..*first };
+
+            if event.instant < prev.instant {
+                return Err(TraceError::Monotonocity{pos: self.events.len(), prev: *prev, event});
+            }
+        }
+
+        self.events.reserve(other.events.len());
+        for event in other.events() {
+            self.push(TraceEvent { instant: event.instant + offset, ..*event })?;
+        }
+        self.clock = clock;
+
+        Ok(())
+    }
+
+    /// Keeps one activation cycle every `factor` for each pid, where an activation cycle is an Activation
+    /// followed by every event of the same pid up to the next Activation.
+    /// Events preceding the first Activation of a pid and Exit events are always kept.
+    ///
+    /// Beware of aliasing: a task with period P appears to have period `factor * P` in the downsampled trace,
+    /// and a task that is not strictly periodic may appear periodic (or vice versa).
+    pub fn downsample_arrivals(&self, factor: usize) -> Trace {
+        assert!(factor > 0, "Downsampling factor must be > 0");
+
+        let mut activations: BTreeMap<Pid, usize> = BTreeMap::new();
+        let events = self.events()
+            .filter(|e| {
+                let cnt = activations.entry(e.pid).or_insert(0);
+                if e.is_activation() {
+                    *cnt += 1;
+                }
+
+                e.is_exit() || *cnt == 0 || (*cnt - 1).is_multiple_of(factor)
+            })
+            .copied()
+            .collect();
+
+        Trace { clock: self.clock, events }
+    }
+
+    /// Events of `pid` only
+    pub fn filter_pid(&self, pid: Pid) -> Trace {
+        let events = self.events()
+            .filter(|e| e.pid == pid)
+            .copied()
+            .collect();
+
+        Trace { clock: self.clock, events }
+    }
+
+    /// Hash of the events (type, pid, instant and cpu) and of their order, e.g. to key a cache of extraction results.
+    /// The clock is not part of it.
+    /// Unlike `std::hash`, it is stable across runs, platforms and compiler versions (64-bit FNV-1a).
+    pub fn fingerprint(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+
+        let mut hash = OFFSET_BASIS;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(PRIME);
+            }
+        };
+        for event in self.events() {
+            write(&[event.etype.short_name() as u8]);
+            write(&event.pid.to_le_bytes());
+            write(&event.instant.to_ns().to_le_bytes());
+            match event.cpu {
+                Some(cpu) => { write(&[1]); write(&cpu.to_le_bytes()); },
+                None => write(&[0]),
+            }
+        }
+
+        hash
+    }
+
+    /// One trace per pid, keeping the order of the events of each pid
+    pub fn split_by_pid(&self) -> BTreeMap<Pid, Trace> {
+        let mut ret: BTreeMap<Pid, Trace> = BTreeMap::new();
+        for event in self.events() {
+            ret.entry(event.pid).or_insert_with(|| Trace { clock: self.clock, ..Trace::new() }).events.push(*event);
+        }
+
+        ret
+    }
+
+    /// Most frequent distance between consecutive activations of `pid`, as a cheap hint of its period before running the
+    /// extraction, e.g. to pick the resolution or the window size of the extractors.
+    /// The gaps are grouped in bins of a tenth of the order of magnitude of the median gap, and the mean gap of the
+    /// most populated bin is returned. None if the pid has less than two activations.
+    pub fn dominant_interarrival(&self, pid: Pid) -> Option<Time> {
+        let activations: Vec<Time> = self.events()
+            .filter(|e| e.pid == pid && e.is_activation())
+            .map(|e| e.instant)
+            .collect();
+        let mut gaps: Vec<Time> = activations.windows(2).map(|w| w[1] - w[0]).collect();
+        if gaps.is_empty() {
+            return None;
+        }
+
+        gaps.sort_unstable();
+        let median = gaps[gaps.len() / 2];
+        let magnitude = 10u64.pow((median.to_ns().max(1) as f64).log10().floor() as u32);
+        let bin_width = (magnitude / 10).max(1);
+
+        let mut bins: BTreeMap<u64, (u64, Time)> = BTreeMap::new(); // Bin -> (count, total gap)
+        for gap in gaps {
+            let bin = bins.entry(gap.to_ns() / bin_width).or_insert((0, Time::zero()));
+            bin.0 += 1;
+            bin.1 += gap;
+        }
+        // On ties, the shortest gaps win
+        let (count, total) = bins.values().fold((0, Time::zero()), |best, bin| if bin.0 > best.0 { *bin } else { best });
+
+        Some(total / count)
+    }
+
+    /// Intervals longer than `expected_max_gap` without any event, of any pid.
+    /// When the ftrace buffer is too small, the kernel drops events and the trace shows sudden silences,
+    /// so on a busy system these intervals are a hint (not a proof) that events were lost.
+    pub fn detect_dropped_regions(&self, expected_max_gap: Time) -> Vec<(Time, Time)> {
+        self.events
+            .windows(2)
+            .map(|w| (w[0].instant, w[1].instant))
+            .filter(|(start, end)| *end - *start > expected_max_gap)
+            .collect()
+    }
+
+    /// Intervals during which `pid` ran, as `(cpu, dispatch, end)`, from each dispatch with a known CPU to the
+    /// following preemption, deactivation or exit of the task. A dispatch still running at the end of the trace is
+    /// closed at the last event. Follows the migrations, since the CPU of an event is the one it happened on.
+    pub fn cpu_residency(&self, pid: Pid) -> Vec<(Cpu, Time, Time)> {
+        let mut ret = Vec::new();
+        let mut running: Option<(Cpu, Time)> = None;
+
+        for event in self.events().filter(|e| e.pid == pid) {
+            if event.is_dispatch() {
+                if let Some((cpu, start)) = running.take() {
+                    ret.push((cpu, start, event.instant));
+                }
+                running = event.cpu.map(|cpu| (cpu, event.instant));
+            } else if event.is_preemption() || event.is_deactivation() || event.is_exit() {
+                if let Some((cpu, start)) = running.take() {
+                    ret.push((cpu, start, event.instant));
+                }
+            }
+        }
+        if let (Some((cpu, start)), Some(last)) = (running, self.events.last()) {
+            ret.push((cpu, start, last.instant));
+        }
+
+        ret
+    }
+
+    /// Relabels the pids to `1..=n`, in order of first appearance, e.g. to share a trace without the real pids.
+    /// Returns the mapping from the original pids to the new ones.
+    pub fn remap_pids(&mut self) -> BTreeMap<Pid, Pid> {
+        let mut map = BTreeMap::new();
+        for event in self.events() {
+            let next = map.len() as Pid + 1;
+            map.entry(event.pid).or_insert(next);
+        }
+
+        self.apply_pid_map(&map);
+
+        map
+    }
+
+    /// Replaces each pid with its image in `map`. Pids that are not in `map` are left unchanged.
+    pub fn apply_pid_map(&mut self, map: &BTreeMap<Pid, Pid>) {
+        for event in self.events_mut() {
+            if let Some(pid) = map.get(&event.pid) {
+                event.pid = *pid;
+            }
+        }
+    }
+
+    /// Reads a plain list of events, or a trace with its metadata as written by `to_yaml_file`
     pub fn from_yaml_file<P: AsRef<Path>>(path: P) -> Result<Trace, TraceError> {
         let mut ret = Trace::new();
 
@@ -128,13 +565,76 @@ impl Trace {
         match f {
             Err(e) => return Err(TraceError::IO(e)),
             Ok(s) => {
-                match serde_yaml::from_str::<Vec<TraceEvent>>(s.as_str()) {
+                let v = match serde_yaml::from_str::<TraceFile>(s.as_str()) {
                     Err(e) => return Err(TraceError::YAMLParsing(e)),
-                    Ok(v) => {
-                        for event in v {
-                            ret.push(event)?;
-                        }
-                    }
+                    Ok(TraceFile::Events(v)) => v,
+                    Ok(TraceFile::Trace(trace)) => {
+                        ret.clock = trace.clock;
+                        trace.events
+                    },
+                };
+                for event in v {
+                    ret.push(event)?;
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Writes the trace with its metadata, see `from_yaml_file`
+    pub fn to_yaml_file<P: AsRef<Path>>(&self, path: P) -> Result<(), TraceError> {
+        let s = serde_yaml::to_string(self).map_err(TraceError::YAMLParsing)?;
+
+        std::fs::write(path, s).map_err(TraceError::IO)
+    }
+
+    /// Parses the text output of `trace-cmd report`.
+    /// The sched_wakeup(_new), sched_switch, sched_process_exit and sched_migrate_task events
+    /// are turned into TraceEvents as during live tracing, see `SchedEventMapper`.
+    /// Lines of other tracepoints and header lines are skipped.
+    pub fn from_ftrace_text<R: Read>(reader: R) -> Result<Trace, TraceError> {
+        let mut ret = Trace::new();
+        let mut mapper = SchedEventMapper::new();
+
+        for (n, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(TraceError::IO)?;
+            let malformed = || TraceError::FtraceParsing{line: n + 1, content: line.clone()};
+
+            let record = match FtraceRecord::parse(&line) {
+                None => continue,
+                Some(record) => record.ok_or_else(malformed)?,
+            };
+            let fields = &record.fields;
+
+            let sched_event = match record.tracepoint {
+                "sched_switch" => {
+                    let (prev_pid, prev_state, next_pid) = parse_switch(fields).ok_or_else(malformed)?;
+                    let prev_runnable = prev_state == "R" || prev_state == "R+";
+
+                    SchedEvent::Switch{prev_pid, prev_runnable, next_pid}
+                },
+                "sched_wakeup" | "sched_wakeup_new" => {
+                    SchedEvent::Wakeup{pid: parse_pid(fields, "pid").ok_or_else(malformed)?}
+                },
+                "sched_process_exit" => {
+                    SchedEvent::Exit{pid: parse_pid(fields, "pid").ok_or_else(malformed)?}
+                },
+                "sched_migrate_task" => {
+                    let pid = parse_pid(fields, "pid").ok_or_else(malformed)?;
+                    let dest_cpu = field(fields, "dest_cpu")
+                        .and_then(|cpu| cpu.parse().ok())
+                        .ok_or_else(malformed)?;
+
+                    SchedEvent::Migrate{pid, dest_cpu: Some(dest_cpu)}
+                },
+                _ => unreachable!(),
+            };
+
+            if let Some((event, extra_event)) = mapper.map(sched_event, record.instant, Some(record.cpu)) {
+                ret.push(event)?;
+                if let Some(extra_event) = extra_event {
+                    ret.push(extra_event)?;
                 }
             }
         }
@@ -143,6 +643,167 @@ impl Trace {
     }
 }
 
+/* SCHEDULER TRACEPOINTS */
+
+/// The fields of a scheduler tracepoint needed to produce TraceEvents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedEvent {
+    /// sched_switch, `prev_runnable` if the previous task is still in the runqueue
+    Switch{prev_pid: Pid, prev_runnable: bool, next_pid: Pid},
+    /// sched_wakeup and sched_wakeup_new
+    Wakeup{pid: Pid},
+    /// sched_process_exit
+    Exit{pid: Pid},
+    /// sched_migrate_task
+    Migrate{pid: Pid, dest_cpu: Option<Cpu>},
+}
+
+/// Turns scheduler tracepoints into TraceEvents, keeping track of the CPU of each pid.
+/// Shared by the live tracer and the `trace-cmd report` parser, so that both produce the same events.
+#[derive(Debug, Default, Clone)]
+pub struct SchedEventMapper {
+    /// Last known CPU of each pid
+    cpus: HashMap<Pid, Cpu>,
+}
+
+impl SchedEventMapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `cpu` is the CPU that recorded the tracepoint, if known.
+    /// A context switch produces two events: a Preemption (if the previous task is still runnable)
+    /// or a Deactivation for the previous pid, and a Dispatch for the next pid.
+    /// A wakeup is recorded by the waker's CPU, so the activation gets the last known CPU of the woken pid.
+    /// A migration produces no event, it only updates the CPU of the migrated pid.
+    pub fn map(&mut self, sched_event: SchedEvent, instant: Time, cpu: Option<Cpu>) -> Option<(TraceEvent, Option<TraceEvent>)> {
+        match sched_event {
+            SchedEvent::Switch{prev_pid, prev_runnable, next_pid} => {
+                // Either Preemption or Deactivation
+                let etype = if prev_runnable {
+                    TraceEventType::Preemption
+                } else {
+                    TraceEventType::Deactivation
+                };
+                let mut event = TraceEvent::new(etype, prev_pid, instant);
+                event.cpu = cpu;
+
+                // Dispatch
+                let mut extra_event = TraceEvent::dispatch(next_pid, instant);
+                extra_event.cpu = cpu;
+                if let Some(cpu) = cpu {
+                    self.cpus.insert(prev_pid, cpu);
+                    self.cpus.insert(next_pid, cpu);
+                }
+
+                Some((event, Some(extra_event)))
+            },
+            SchedEvent::Wakeup{pid} => {
+                let mut event = TraceEvent::activation(pid, instant);
+                event.cpu = self.cpus.get(&pid).copied();
+
+                Some((event, None))
+            },
+            SchedEvent::Exit{pid} => {
+                self.cpus.remove(&pid);
+                let mut event = TraceEvent::exit(pid, instant);
+                event.cpu = cpu;
+
+                Some((event, None))
+            },
+            SchedEvent::Migrate{pid, dest_cpu} => {
+                if let Some(dest_cpu) = dest_cpu {
+                    self.cpus.insert(pid, dest_cpu);
+                }
+
+                None
+            },
+        }
+    }
+}
+
+/* TRACE-CMD REPORT PARSING */
+
+const FTRACE_TRACEPOINTS: [&str; 5] = [
+    "sched_wakeup",
+    "sched_wakeup_new",
+    "sched_switch",
+    "sched_process_exit",
+    "sched_migrate_task",
+];
+
+/// A line of `trace-cmd report` output: `comm-pid [cpu] (flags) secs.frac: tracepoint: fields`
+struct FtraceRecord<'a> {
+    cpu: Cpu,
+    instant: Time,
+    tracepoint: &'a str,
+    fields: Vec<&'a str>,
+}
+
+impl<'a> FtraceRecord<'a> {
+    /// None if the line is not an event of a supported tracepoint, Some(None) if it is malformed
+    fn parse(line: &'a str) -> Option<Option<Self>> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let pos = tokens.iter().position(|t| {
+            t.strip_suffix(':').is_some_and(|name| FTRACE_TRACEPOINTS.contains(&name))
+        })?;
+        let tracepoint = tokens[pos].trim_end_matches(':');
+
+        let instant = pos.checked_sub(1)
+            .and_then(|i| tokens[i].strip_suffix(':'))
+            .and_then(parse_timestamp);
+        let cpu = tokens[..pos].iter().find_map(|t| {
+            t.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+        });
+
+        Some(match (cpu, instant) {
+            (Some(cpu), Some(instant)) => Some(FtraceRecord { cpu, instant, tracepoint, fields: tokens[pos + 1..].to_vec() }),
+            _ => None,
+        })
+    }
+}
+
+/// Timestamps are printed in seconds, with up to 9 decimal digits
+fn parse_timestamp(ts: &str) -> Option<Time> {
+    let (secs, frac) = ts.split_once('.').unwrap_or((ts, ""));
+    if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs: u64 = secs.parse().ok()?;
+    // Right-pad the fractional part to nanoseconds
+    let frac: u64 = format!("{:0<9}", frac).parse().ok()?;
+    let ns = secs.checked_mul(1_000_000_000)?.checked_add(frac)?;
+
+    Some(Time::from_ns(ns))
+}
+
+/// Value of a `key=value` field
+fn field<'a>(fields: &[&'a str], key: &str) -> Option<&'a str> {
+    fields.iter().find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Pid of a `key=pid` field, or of the first `comm:pid` field (format of the trace-cmd sched plugin)
+fn parse_pid(fields: &[&str], key: &str) -> Option<Pid> {
+    if let Some(pid) = field(fields, key) {
+        return pid.parse().ok();
+    }
+
+    fields.iter().find_map(|f| f.rsplit_once(':')?.1.parse().ok())
+}
+
+/// Previous pid, previous state and next pid of a sched_switch, either from the raw fields
+/// (`prev_pid=1 prev_state=S ==> next_pid=2`) or the sched plugin format (`comm:1 [120] S ==> comm:2 [120]`)
+fn parse_switch<'a>(fields: &[&'a str]) -> Option<(Pid, &'a str, Pid)> {
+    if let (Some(prev_pid), Some(prev_state), Some(next_pid)) = (field(fields, "prev_pid"), field(fields, "prev_state"), field(fields, "next_pid")) {
+        return Some((prev_pid.parse().ok()?, prev_state, next_pid.parse().ok()?));
+    }
+
+    let arrow = fields.iter().position(|f| *f == "==>")?;
+    let (prev, next) = (&fields[..arrow], &fields[arrow + 1..]);
+
+    Some((parse_pid(prev, "prev_pid")?, prev.last()?, parse_pid(next, "next_pid")?))
+}
+
 impl Default for Trace {
     fn default() -> Self {
         Self::new()
@@ -154,16 +815,56 @@ where T: AsRef<[TraceEvent]>
 {
     fn from(events: T) -> Self { 
         Trace {
+            clock: None,
             events: Vec::from(events.as_ref())
         }
     }
 }
 
+/// Builds traces out of whole jobs, e.g. for tests or synthetic workloads.
+/// Each job expands to an activation and a dispatch at its arrival, and a deactivation once its cost has been executed.
+/// Jobs can be added in any order, the events are sorted when the trace is built (see `Trace::from_unsorted`).
+#[derive(Debug, Clone, Default)]
+pub struct TraceBuilder {
+    clock: Option<ClockSource>,
+    events: Vec<TraceEvent>,
+}
+
+impl TraceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clock(self, clock: ClockSource) -> Self {
+        Self { clock: Some(clock), ..self }
+    }
+
+    /// Adds a job of `pid` arriving at `arrival` and executing for `cost` without interruption
+    pub fn job(mut self, pid: Pid, arrival: Time, cost: Time) -> Self {
+        self.events.push(TraceEvent::activation(pid, arrival));
+        self.events.push(TraceEvent::dispatch(pid, arrival));
+        self.events.push(TraceEvent::deactivation(pid, arrival + cost));
+
+        self
+    }
+
+    /// Adds `n` jobs of `pid` with cost `wcet`, the first arriving at `start` and the others every `period`
+    pub fn periodic(self, pid: Pid, start: Time, period: Time, wcet: Time, n: usize) -> Self {
+        (0..n).fold(self, |builder, i| builder.job(pid, start + period * i as u64, wcet))
+    }
+
+    pub fn build(self) -> Trace {
+        let trace = Trace::from_unsorted(self.events);
+
+        Trace { clock: self.clock, ..trace }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::time::Time;
 
-    use super::{TraceEvent, TraceEventType, Trace, TraceError};
+    use super::{TraceEvent, TraceEventType, Trace, TraceBuilder, TraceError, SequenceReport, ClockSource};
 
     #[test]
     pub fn test_from() {
@@ -183,6 +884,47 @@ pub mod tests {
         assert_eq!(events.next(), None);
     }
 
+    #[test]
+    pub fn test_builder() {
+        let trace = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(0)),
+            TraceEvent::dispatch(1, Time::from_ns(0)),
+            TraceEvent::deactivation(1, Time::from_ns(3)),
+            TraceEvent::activation(2, Time::from_ns(5)),
+            TraceEvent::dispatch(2, Time::from_ns(5)),
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(10)),
+            TraceEvent::deactivation(1, Time::from_ns(10)),
+            TraceEvent::deactivation(2, Time::from_ns(12)),
+            TraceEvent::activation(1, Time::from_ns(20)),
+            TraceEvent::dispatch(1, Time::from_ns(20)),
+            TraceEvent::deactivation(1, Time::from_ns(23)),
+        ]);
+
+        // Jobs added out of order, the zero cost job ends at its arrival
+        let built = TraceBuilder::new()
+            .job(1, Time::from_ns(20), Time::from_ns(3))
+            .job(2, Time::from_ns(5), Time::from_ns(7))
+            .job(1, Time::from_ns(10), Time::zero())
+            .job(1, Time::from_ns(0), Time::from_ns(3))
+            .build();
+        assert_eq!(built, trace);
+        assert!(built.validate_sequences().values().all(|report| report.is_clean()));
+
+        let periodic = TraceBuilder::new().periodic(1, Time::from_ns(2), Time::from_ns(10), Time::from_ns(4), 3).build();
+        let expected = TraceBuilder::new()
+            .job(1, Time::from_ns(2), Time::from_ns(4))
+            .job(1, Time::from_ns(12), Time::from_ns(4))
+            .job(1, Time::from_ns(22), Time::from_ns(4))
+            .build();
+        assert_eq!(periodic, expected);
+        assert_eq!(periodic.events().count(), 9);
+        assert_eq!(TraceBuilder::new().periodic(1, Time::zero(), Time::from_ns(10), Time::from_ns(4), 0).build(), Trace::new());
+
+        let clocked = TraceBuilder::new().with_clock(ClockSource::Mono).job(1, Time::zero(), Time::from_ns(1)).build();
+        assert_eq!(clocked.clock(), Some(ClockSource::Mono));
+    }
+
     #[test]
     pub fn test_push() -> Result<(), TraceError> {
         let mut trace = Trace::new();
@@ -250,4 +992,406 @@ pub mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_yaml_cpu() {
+        let with_cpu = TraceEvent::dispatch(1, Time::from_ns(2)).with_cpu(3);
+        let without_cpu = TraceEvent::dispatch(1, Time::from_ns(2));
+
+        let s = serde_yaml::to_string(&with_cpu).unwrap();
+        assert_eq!(serde_yaml::from_str::<TraceEvent>(&s).unwrap(), with_cpu);
+
+        // Traces recorded before the cpu field existed are still valid
+        let s = serde_yaml::to_string(&without_cpu).unwrap();
+        assert!(!s.contains("cpu"));
+        assert_eq!(serde_yaml::from_str::<TraceEvent>(&s).unwrap().cpu, None);
+    }
+
+    #[test]
+    pub fn test_display() {
+        assert_eq!(TraceEvent::activation(1234, Time::from_ms(5.)).to_string(), "A pid=1234 @ 5.000ms");
+        assert_eq!(TraceEvent::dispatch(7, Time::from_ns(1_500_250_000)).with_cpu(2).to_string(), "R pid=7 @ 1.500s cpu=2");
+    }
+
+    #[test]
+    pub fn test_predicates() {
+        let a = TraceEvent::activation(1, Time::from_ns(1));
+        let d = TraceEvent::deactivation(1, Time::from_ns(1));
+        let r = TraceEvent::dispatch(1, Time::from_ns(1));
+        let p = TraceEvent::preemption(1, Time::from_ns(1));
+        let e = TraceEvent::exit(1, Time::from_ns(1));
+
+        assert!(a.is_activation() && !a.is_deactivation() && !a.is_dispatch() && !a.is_preemption() && !a.is_exit());
+        assert!(!d.is_activation() && d.is_deactivation() && !d.is_dispatch() && !d.is_preemption() && !d.is_exit());
+        assert!(!r.is_activation() && !r.is_deactivation() && r.is_dispatch() && !r.is_preemption() && !r.is_exit());
+        assert!(!p.is_activation() && !p.is_deactivation() && !p.is_dispatch() && p.is_preemption() && !p.is_exit());
+        assert!(!e.is_activation() && !e.is_deactivation() && !e.is_dispatch() && !e.is_preemption() && e.is_exit());
+    }
+
+    #[test]
+    pub fn test_validate_sequences() {
+        let trace = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(1)),
+            TraceEvent::dispatch(1, Time::from_ns(1)),
+            TraceEvent::activation(2, Time::from_ns(2)),
+            TraceEvent::deactivation(1, Time::from_ns(3)),
+            TraceEvent::dispatch(2, Time::from_ns(3)),
+            TraceEvent::deactivation(2, Time::from_ns(4)),
+            // Missing activation of pid 1
+            TraceEvent::dispatch(1, Time::from_ns(5)),
+            TraceEvent::deactivation(1, Time::from_ns(6)),
+            TraceEvent::activation(1, Time::from_ns(7)),
+            TraceEvent::dispatch(1, Time::from_ns(7)),
+            TraceEvent::deactivation(1, Time::from_ns(8)),
+        ]);
+
+        let reports = trace.validate_sequences();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[&1], SequenceReport { events: 8, dispatch_without_activation: 1, ..Default::default() });
+        assert_eq!(reports[&1].anomalies(), 1);
+        assert!(reports[&2].is_clean());
+    }
+
+    #[test]
+    pub fn test_downsample_arrivals() {
+        let trace = Trace::from([
+            TraceEvent::dispatch(1, Time::from_ns(0)),
+            TraceEvent::deactivation(1, Time::from_ns(1)),
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(10)),
+            TraceEvent::deactivation(1, Time::from_ns(12)),
+            TraceEvent::activation(1, Time::from_ns(20)),
+            TraceEvent::dispatch(1, Time::from_ns(20)),
+            TraceEvent::deactivation(1, Time::from_ns(22)),
+            TraceEvent::activation(1, Time::from_ns(30)),
+            TraceEvent::dispatch(1, Time::from_ns(30)),
+            TraceEvent::deactivation(1, Time::from_ns(32)),
+            TraceEvent::exit(1, Time::from_ns(35)),
+        ]);
+
+        let expected = Trace::from([
+            TraceEvent::dispatch(1, Time::from_ns(0)),
+            TraceEvent::deactivation(1, Time::from_ns(1)),
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(10)),
+            TraceEvent::deactivation(1, Time::from_ns(12)),
+            TraceEvent::activation(1, Time::from_ns(30)),
+            TraceEvent::dispatch(1, Time::from_ns(30)),
+            TraceEvent::deactivation(1, Time::from_ns(32)),
+            TraceEvent::exit(1, Time::from_ns(35)),
+        ]);
+
+        assert_eq!(trace.downsample_arrivals(2), expected);
+        assert_eq!(trace.downsample_arrivals(1), trace);
+    }
+
+    #[test]
+    pub fn test_concat_with_offset() {
+        let segment = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(0)),
+            TraceEvent::deactivation(1, Time::from_ns(2)),
+        ]);
+
+        let mut trace = Trace::from([TraceEvent::activation(1, Time::from_ns(0))]);
+        trace.concat_with_offset(&segment, Time::from_ns(10)).unwrap();
+
+        assert_eq!(trace, Trace::from([
+            TraceEvent::activation(1, Time::from_ns(0)),
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::deactivation(1, Time::from_ns(12)),
+        ]));
+
+        // The second segment would start before the end of the trace
+        let res = trace.concat_with_offset(&segment, Time::from_ns(11));
+        assert!(matches!(res, Err(TraceError::Monotonocity{pos: 3, ..})));
+        assert_eq!(trace.events().count(), 3);
+    }
+
+    #[test]
+    pub fn test_coincident_events() {
+        let t = Time::from_ns(10);
+        let activation = TraceEvent::activation(1, t);
+        let dispatch = TraceEvent::dispatch(1, t);
+
+        let expected = Trace::from([activation, dispatch]);
+        assert_eq!(Trace::from_unsorted([dispatch, activation]), expected);
+        assert_eq!(Trace::from([dispatch]).merge(&Trace::from([activation])).unwrap(), expected);
+        assert_eq!(Trace::from([activation]).merge(&Trace::from([dispatch])).unwrap(), expected);
+
+        assert!(TraceEventType::Activation < TraceEventType::Dispatch);
+        assert!(TraceEventType::Dispatch < TraceEventType::Preemption);
+        assert!(TraceEventType::Preemption < TraceEventType::Deactivation);
+        assert!(TraceEventType::Deactivation < TraceEventType::Exit);
+    }
+
+    #[test]
+    pub fn test_merge() {
+        // Switch on CPU 0 from pid 1 to pid 2, while pid 3 runs on CPU 1
+        let cpu_0 = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(1)),
+            TraceEvent::dispatch(1, Time::from_ns(1)),
+            TraceEvent::preemption(1, Time::from_ns(5)),
+            TraceEvent::dispatch(2, Time::from_ns(5)),
+        ]);
+        let cpu_1 = Trace::from([
+            TraceEvent::dispatch(3, Time::from_ns(2)),
+            TraceEvent::deactivation(3, Time::from_ns(5)),
+            TraceEvent::activation(3, Time::from_ns(8)),
+        ]);
+
+        let merged = cpu_0.merge(&cpu_1).unwrap();
+        let events: Vec<_> = merged.events().map(|e| (e.instant.to_ns(), e.pid, e.etype.short_name())).collect();
+        assert_eq!(events, [(1, 1, 'A'), (1, 1, 'R'), (2, 3, 'R'), (5, 1, 'P'), (5, 2, 'R'), (5, 3, 'D'), (8, 3, 'A')]);
+        // Every event is kept, and the result is monotonic
+        assert!(merged.events().zip(merged.events().skip(1)).all(|(a, b)| a.instant <= b.instant));
+    }
+
+    #[test]
+    pub fn test_clock_source() {
+        let mono = Trace::from([TraceEvent::activation(1, Time::from_ns(10))]).with_clock(ClockSource::Mono);
+        let boot = Trace::from([TraceEvent::activation(2, Time::from_ns(11))]).with_clock(ClockSource::Boot);
+        let unknown = Trace::from([TraceEvent::activation(3, Time::from_ns(12))]);
+
+        assert!(matches!(mono.merge(&boot), Err(TraceError::ClockMismatch{left: ClockSource::Mono, right: ClockSource::Boot})));
+        assert!(matches!(mono.clone().concat_with_offset(&boot, Time::zero()), Err(TraceError::ClockMismatch{..})));
+        // An unknown clock is assumed to match
+        assert_eq!(unknown.merge(&mono).unwrap().clock(), Some(ClockSource::Mono));
+        assert_eq!(mono.filter_pid(1).clock(), Some(ClockSource::Mono));
+
+        // Kept in trace files, which can still be plain lists of events
+        let path = std::env::temp_dir().join(format!("trace-clock-{}.yaml", std::process::id()));
+        mono.to_yaml_file(&path).unwrap();
+        assert_eq!(Trace::from_yaml_file(&path).unwrap(), mono);
+        std::fs::write(&path, serde_yaml::to_string(&[TraceEvent::activation(1, Time::from_ns(10))]).unwrap()).unwrap();
+        assert_eq!(Trace::from_yaml_file(&path).unwrap().clock(), None);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ClockSource::from_ftrace_name("mono_raw"), Some(ClockSource::MonoRaw));
+        assert_eq!(ClockSource::from_ftrace_name("tai"), None);
+    }
+
+    #[test]
+    pub fn test_filter_pid() {
+        let trace = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::activation(2, Time::from_ns(11)),
+            TraceEvent::dispatch(1, Time::from_ns(12)),
+        ]);
+
+        assert_eq!(trace.filter_pid(1), Trace::from([
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(12)),
+        ]));
+        assert_eq!(trace.filter_pid(3), Trace::new());
+    }
+
+    #[test]
+    pub fn test_fingerprint() {
+        let events = [
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(10)).with_cpu(0),
+            TraceEvent::deactivation(1, Time::from_ns(12)),
+        ];
+        let trace = Trace::from(events);
+
+        assert_eq!(trace.fingerprint(), Trace::from(events).fingerprint());
+        // Fixed value, the fingerprint must not change between runs
+        assert_eq!(Trace::new().fingerprint(), 0xcbf29ce484222325);
+        let built = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(2.), 3)
+            .job(2, Time::from_ms(5.), Time::from_ms(1.))
+            .build();
+        assert_eq!(built.fingerprint(), 0x073761854fe67e3a);
+
+        let mut changed = events;
+        changed[2].instant = Time::from_ns(13);
+        assert_ne!(trace.fingerprint(), Trace::from(changed).fingerprint());
+
+        let mut swapped = events;
+        swapped.swap(0, 1);
+        assert_ne!(trace.fingerprint(), Trace::from(swapped).fingerprint());
+
+        assert_ne!(trace.fingerprint(), Trace::from([events[0], events[1].with_cpu(1), events[2]]).fingerprint());
+    }
+
+    #[test]
+    pub fn test_split_by_pid() {
+        let trace = Trace::from([
+            TraceEvent::activation(2, Time::from_ns(10)),
+            TraceEvent::activation(1, Time::from_ns(11)),
+            TraceEvent::dispatch(2, Time::from_ns(12)),
+        ]);
+
+        let split = trace.split_by_pid();
+        assert_eq!(split.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        for (pid, task_trace) in split.iter() {
+            assert_eq!(*task_trace, trace.filter_pid(*pid));
+        }
+        assert!(Trace::new().split_by_pid().is_empty());
+    }
+
+    #[test]
+    pub fn test_dominant_interarrival() {
+        // Period of 10ms with up to 0.6ms of jitter, a missed activation and an interfering task
+        let mut builder = TraceBuilder::new().periodic(2, Time::from_ms(1.), Time::from_ms(7.), Time::from_ms(1.), 50);
+        for i in (0..50).filter(|i| *i != 20) {
+            let jitter = Time::from_us(((i * 37) % 7) as f64 * 100.);
+            builder = builder.job(1, Time::from_ms(10. * i as f64) + jitter, Time::from_ms(2.));
+        }
+        let trace = builder.build();
+
+        let period = trace.dominant_interarrival(1).unwrap();
+        assert!(period >= Time::from_ms(9.7) && period <= Time::from_ms(10.3), "{}", period);
+        assert_eq!(trace.dominant_interarrival(2), Some(Time::from_ms(7.)));
+
+        assert_eq!(trace.dominant_interarrival(3), None);
+        assert_eq!(TraceBuilder::new().job(1, Time::zero(), Time::from_ms(1.)).build().dominant_interarrival(1), None);
+    }
+
+    #[test]
+    pub fn test_detect_dropped_regions() {
+        // Two tasks, both silent between 100ms and 600ms
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::zero(), Time::from_ms(10.), Time::from_ms(1.), 10)
+            .periodic(2, Time::from_ms(5.), Time::from_ms(20.), Time::from_ms(2.), 5)
+            .periodic(1, Time::from_ms(600.), Time::from_ms(10.), Time::from_ms(1.), 10)
+            .periodic(2, Time::from_ms(605.), Time::from_ms(20.), Time::from_ms(2.), 5)
+            .build();
+
+        assert_eq!(trace.detect_dropped_regions(Time::from_ms(50.)), vec![(Time::from_ms(91.), Time::from_ms(600.))]);
+        assert!(trace.detect_dropped_regions(Time::from_ms(600.)).is_empty());
+        // Each task alone is silent for 99ms, but not the trace
+        assert!(TraceBuilder::new().periodic(1, Time::zero(), Time::from_ms(100.), Time::from_ms(1.), 10)
+                                   .periodic(2, Time::from_ms(50.), Time::from_ms(100.), Time::from_ms(1.), 10)
+                                   .build()
+                                   .detect_dropped_regions(Time::from_ms(60.))
+                                   .is_empty());
+        assert!(Trace::new().detect_dropped_regions(Time::zero()).is_empty());
+    }
+
+    #[test]
+    pub fn test_remap_pids() {
+        let original = Trace::from([
+            TraceEvent::activation(4242, Time::from_ns(0)),
+            TraceEvent::dispatch(4242, Time::from_ns(0)),
+            TraceEvent::activation(17, Time::from_ns(1)),
+            TraceEvent::preemption(4242, Time::from_ns(1)),
+            TraceEvent::dispatch(17, Time::from_ns(1)).with_cpu(2),
+            TraceEvent::deactivation(17, Time::from_ns(3)),
+            TraceEvent::exit(4242, Time::from_ns(5)),
+        ]);
+
+        let mut trace = original.clone();
+        let map = trace.remap_pids();
+
+        assert_eq!(map, [(4242, 1), (17, 2)].iter().copied().collect());
+        assert_eq!(trace.events().count(), original.events().count());
+        for (event, orig) in trace.events().zip(original.events()) {
+            assert_eq!(*event, TraceEvent { pid: map[&orig.pid], ..*orig });
+        }
+
+        // Mapping back restores the original trace
+        let inverse = map.iter().map(|(k, v)| (*v, *k)).collect();
+        trace.apply_pid_map(&inverse);
+        assert_eq!(trace, original);
+    }
+
+    #[test]
+    pub fn test_map_instants() {
+        let original = Trace::from([
+            TraceEvent::activation(1, Time::from_ns(10)),
+            TraceEvent::dispatch(1, Time::from_ns(10)),
+            TraceEvent::deactivation(1, Time::from_ns(12)),
+            TraceEvent::activation(1, Time::from_ns(20)),
+        ]);
+
+        let mut trace = original.clone();
+        trace.map_instants(|t| t + Time::from_ns(100)).unwrap();
+
+        let instants: Vec<_> = trace.events().map(|e| e.instant.to_ns()).collect();
+        assert_eq!(instants, [110, 110, 112, 120]);
+        assert!(trace.events().zip(trace.events().skip(1)).all(|(a, b)| a.instant <= b.instant));
+
+        // Reversing time breaks monotonicity
+        let res = trace.map_instants(|t| Time::from_ns(1000) - t);
+        assert!(matches!(res, Err(TraceError::Monotonocity{pos: 2, ..})));
+        assert_eq!(trace.events().count(), 4);
+        assert_eq!(trace.events().next().unwrap().instant, Time::from_ns(110));
+    }
+
+    #[test]
+    pub fn test_events_mut() {
+        let mut trace = Trace::from([TraceEvent::activation(1, Time::from_ns(10))]);
+        for event in trace.events_mut() {
+            event.cpu = Some(3);
+        }
+
+        assert_eq!(trace, Trace::from([TraceEvent::activation(1, Time::from_ns(10)).with_cpu(3)]));
+    }
+
+    #[test]
+    pub fn test_ftrace_text() -> Result<(), TraceError> {
+        let report = "\
+cpus=4
+          <idle>-0     [001]  100.000001000: sched_wakeup:         comm=foo pid=42 prio=120 target_cpu=001
+          <idle>-0     [001]  100.000002: sched_switch:         prev_comm=swapper/1 prev_pid=0 prev_prio=120 prev_state=R ==> next_comm=foo next_pid=42 next_prio=120
+             foo-42    [001]  100.000005: sched_switch:         prev_comm=foo prev_pid=42 prev_prio=120 prev_state=R+ ==> next_comm=bar next_pid=43 next_prio=100
+             bar-43    [001]  100.000007: sched_switch:         prev_comm=bar prev_pid=43 prev_prio=100 prev_state=S ==> next_comm=foo next_pid=42 next_prio=120
+             foo-42    [001]  100.000008: sched_stat_runtime:   comm=foo pid=42 runtime=3000 [ns] vruntime=0 [ns]
+             foo-42    [001]  100.000009: sched_process_exit:   comm=foo pid=42 prio=120
+";
+        let trace = Trace::from_ftrace_text(report.as_bytes())?;
+
+        assert_eq!(trace, Trace::from([
+            TraceEvent::activation(42, Time::from_ns(100_000_001_000)),
+            TraceEvent::preemption(0, Time::from_ns(100_000_002_000)).with_cpu(1),
+            TraceEvent::dispatch(42, Time::from_ns(100_000_002_000)).with_cpu(1),
+            TraceEvent::preemption(42, Time::from_ns(100_000_005_000)).with_cpu(1),
+            TraceEvent::dispatch(43, Time::from_ns(100_000_005_000)).with_cpu(1),
+            TraceEvent::deactivation(43, Time::from_ns(100_000_007_000)).with_cpu(1),
+            TraceEvent::dispatch(42, Time::from_ns(100_000_007_000)).with_cpu(1),
+            TraceEvent::exit(42, Time::from_ns(100_000_009_000)).with_cpu(1),
+        ]));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_ftrace_text_sched_plugin() -> Result<(), TraceError> {
+        // Format of the trace-cmd sched plugin, with latency flags
+        let report = "\
+             bar-43    [002] d..2  7.5: sched_switch:         bar:43 [100] D ==> Web Content:44 [120]
+             bar-43    [000] d..3  8.25: sched_migrate_task:  comm=foo pid=42 prio=120 orig_cpu=0 dest_cpu=3
+             bar-43    [000] d..3  8.5: sched_wakeup:         Web Content:44 [120] CPU:002
+             bar-43    [000] d..3  9: sched_wakeup_new:     foo:42 [120] success=1 CPU:003
+";
+        let trace = Trace::from_ftrace_text(report.as_bytes())?;
+
+        assert_eq!(trace, Trace::from([
+            TraceEvent::deactivation(43, Time::from_ms(7500.)).with_cpu(2),
+            TraceEvent::dispatch(44, Time::from_ms(7500.)).with_cpu(2),
+            // The CPU of a wakeup is the last known CPU of the woken pid
+            TraceEvent::activation(44, Time::from_ms(8500.)).with_cpu(2),
+            TraceEvent::activation(42, Time::from_s(9.)).with_cpu(3),
+        ]));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_ftrace_text_malformed() {
+        let report = "\
+             foo-42    [001]  100.000009: sched_process_exit:   comm=foo prio=120
+";
+        let res = Trace::from_ftrace_text(report.as_bytes());
+        assert!(matches!(res, Err(TraceError::FtraceParsing{line: 1, ..})));
+
+        let report = "\
+             foo-42    [001]  100.000009: sched_wakeup:   comm=foo pid=42
+             foo-42    [001]  100.000008: sched_wakeup:   comm=foo pid=42
+";
+        let res = Trace::from_ftrace_text(report.as_bytes());
+        assert!(matches!(res, Err(TraceError::Monotonocity{pos: 1, ..})));
+    }
+}
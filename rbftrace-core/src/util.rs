@@ -19,3 +19,18 @@ pub fn run_cmd_opt(cmd: String) -> Option<String> {
         Err(_) => None 
     }
 }
+
+/// Log level of the binaries for their `-v`/`-q` flags: warnings by default, each `-v` shows one more level
+/// (info, debug, trace), and `-q` only keeps the errors.
+pub fn log_level(verbose: u64, quiet: bool) -> log::LevelFilter {
+    if quiet {
+        return log::LevelFilter::Error;
+    }
+
+    match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
@@ -2,16 +2,17 @@ use std::path::{PathBuf, Path};
 
 use rbftrace_core::{
     model::{SystemModel, PeriodicTask, PeriodicSelfSuspendingTask}, 
-    sys_conf::{SysConf},
-    trace::Trace, 
-    time::{Time, Jitter}
+    sys_conf::{SysConf, Pid},
+    trace::{Trace, TraceEvent},
+    time::{Time, Jitter, TimeUnit, ParseTimeError, HumanTime},
+    util::log_level,
 };
 use rbftrace_model_extraction::{
     periodic::{PeriodicTaskExtractionParams},
     spectral::{SpectralExtractionParams},
     rbf::{RBFExtractionParams},
     SystemModelExtractor, 
-    composite::{CompositeExtractionParams, CompositeModelExtractor, CompositeModel},
+    composite::{CompositeExtractionParams, CompositeModel, CompositeModelExtractor, SystemSummary},
 };
 
 use dd::WriteYAML;
@@ -21,30 +22,50 @@ fn create_dir<P: AsRef<Path>>(output_dir: P) -> Result<(), AppError> {
     std::fs::create_dir_all(output_dir).map_err(|e| AppError::OSError(e)) 
 }
 
-fn print_periodic_models(system_model: &SystemModel<CompositeModel>) {
-    for pid in system_model.pids() {
-        println!("PID {}:", pid);
-        let model = system_model.get_model(*pid)
-                                    .map(|m| m.periodic)
-                                    .flatten();
+/* Time options without a unit are in their natural unit, e.g. "1" is 1ms for the jitter bound */
+fn parse_ms(s: &str) -> Result<Time, ParseTimeError> {
+    Time::parse_with_default_unit(s, TimeUnit::Ms)
+}
 
-        if let Some(model) = model {
-            model.pretty_print();
-        } else {
-            println!("Not periodic");
-        }
+fn parse_us(s: &str) -> Result<Time, ParseTimeError> {
+    Time::parse_with_default_unit(s, TimeUnit::Us)
+}
+
+/// Keep at most `max_events` events, and only the events within `max_duration` of the first one
+fn cap_trace(trace: &Trace, max_events: Option<usize>, max_duration: Option<Time>) -> Trace {
+    let start = trace.events().next().map(|e| e.instant).unwrap_or_default();
+    let events: Vec<TraceEvent> = trace.events()
+        .take(max_events.unwrap_or(usize::MAX))
+        .take_while(|e| max_duration.is_none_or(|d| e.instant - start <= d))
+        .copied()
+        .collect();
+
+    match trace.clock() {
+        Some(clock) => Trace::from(events).with_clock(clock),
+        None => Trace::from(events),
     }
 }
 
 fn main() {
     let args = Opt::from_args();
+    env_logger::Builder::new().filter_level(log_level(args.verbose, args.quiet)).init();
 
     // Check args
     if let Some(0) = args.update_arrival {
         panic!("Arrivals must be > 0");
     }
+    if let Some(0) = args.downsample {
+        panic!("Downsampling factor must be > 0");
+    }
+    if args.cpu_freq == Some(0) || args.ref_cpu_freq == Some(0) {
+        panic!("CPU frequencies must be > 0");
+    }
+
+    std::process::exit(exit_code(_main(args)));
+}
 
-    let exit_code = match _main(args) {
+fn exit_code(result: AppResult) -> i32 {
+    match result {
         Ok(()) => 0,
         Err(AppError::TraceError(e)) => {
             eprintln!("Trace error: {:#?}", e);
@@ -58,27 +79,119 @@ fn main() {
             eprintln!("Cannot deserialize: {:#?}", e);
             3
         },
-    };
-    std::process::exit(exit_code);
+        Err(AppError::InvalidTrace(e)) => {
+            eprintln!("Invalid trace: {}", e);
+            4
+        },
+    }
+}
+
+/// Prints the events of each pid, their illegal sequences and the silent regions of the trace.
+/// Fails if the trace is empty or has illegal sequences. The silent regions are only a hint, see `Trace::detect_dropped_regions`.
+fn validate(trace: &Trace, max_event_gap: Time) -> AppResult {
+    let reports = trace.validate_sequences();
+    let t = HumanTime;
+    let mut anomalies = 0;
+
+    println!("{} events, {} pids", trace.events().count(), reports.len());
+    for (pid, report) in &reports {
+        println!("PID {}: {} events, {} illegal sequences", pid, report.events, report.anomalies());
+        let counts = [
+            ("activation before the completion of the previous job", report.double_activation),
+            ("dispatch without activation", report.dispatch_without_activation),
+            ("preemption while not running", report.preemption_without_dispatch),
+            ("double deactivation", report.double_deactivation),
+            ("deactivation while not running", report.deactivation_without_dispatch),
+            ("event after exit", report.event_after_exit),
+        ];
+        for (name, count) in counts.iter().filter(|(_, count)| *count > 0) {
+            println!("    {}: {}", name, count);
+        }
+        anomalies += report.anomalies();
+    }
+    if !max_event_gap.is_zero() {
+        for (start, end) in trace.detect_dropped_regions(max_event_gap) {
+            println!("No events between {} and {}: events may have been dropped", t(start), t(end));
+        }
+    }
+
+    if reports.is_empty() {
+        Err(AppError::InvalidTrace("no events".to_string()))
+    } else if anomalies > 0 {
+        Err(AppError::InvalidTrace(format!("{} illegal sequences of events", anomalies)))
+    } else {
+        Ok(())
+    }
 }
 
 
 fn _main(args: Opt) -> AppResult {
-    let trace = Trace::from_yaml_file(&args.source_path)?;
+    let mut trace = Trace::from_yaml_file(&args.source_path)?;
+    if let Some(pid) = args.pid {
+        trace = trace.filter_pid(pid);
+    }
+    if let Some(factor) = args.downsample {
+        trace = trace.downsample_arrivals(factor);
+    }
+    if args.anonymize {
+        trace.remap_pids();
+    }
+    if args.max_events.is_some() || args.max_duration.is_some() {
+        trace = cap_trace(&trace, args.max_events, args.max_duration.map(|d| Time::from_s(d as f64)));
+    }
+    if args.validate {
+        return validate(&trace, args.max_event_gap);
+    }
+    if !args.max_event_gap.is_zero() {
+        for (start, end) in trace.detect_dropped_regions(args.max_event_gap) {
+            log::warn!("No events between {} and {}: events may have been dropped, try a bigger ftrace buffer", start, end);
+        }
+    }
+    let sys_conf = SysConf::from(&args);
     let extraction_params = CompositeExtractionParams::from(&args);
-    let mut model = SystemModel::new(SysConf::default());
+    let trace_end = trace.events().last().map(|event| event.instant);
+    let mut model = SystemModel::new(sys_conf.clone());
     let mut report_periodic = dd::Report::<PeriodicTask>::new();
     let mut report_periodic_ss = dd::Report::<PeriodicSelfSuspendingTask>::new();
+    let mut report_rbf = dd::Report::<dd::OutputRbf>::new();
+    let mut report_stream = None;
 
     if args.update_interval.is_none() && args.update_arrival.is_none() {
-        if args.report {
-            eprintln!("Option --report set for a one shot extraction. Report won't be written");
+        let report_options = [(args.report, "--report"), (args.report_stream, "--report-stream"), (args.rbf_report, "--rbf-report")];
+        for (_, option) in report_options.iter().filter(|(set, _)| *set) {
+            log::warn!("Option {} set for a one shot extraction. Report won't be written", option);
         }
         /* ONE-SHOT */
-        model = SystemModelExtractor::<CompositeModelExtractor>::extract_from_trace(extraction_params, SysConf::default(), trace);
+        let mut model_extractor = SystemModelExtractor::<CompositeModelExtractor>::new(extraction_params, sys_conf);
+        model_extractor.push_trace(&trace);
+        if let Some(end) = trace_end.filter(|_| args.finalize_open_jobs) {
+            model_extractor.finalize_open_jobs(end);
+        }
+        model = model_extractor.extract_model();
+        log::info!("Extractors of {} tasks hold about {} KiB", model.pids().count(), model_extractor.memory_footprint() / 1024);
     } else {
         /* INCREMENTAL */
-        let mut model_extractor = SystemModelExtractor::<CompositeModelExtractor>::new(extraction_params, SysConf::default());
+        if args.report_stream {
+            let path = args.output_path.as_ref().unwrap();
+            create_dir(path)?;
+            report_stream = Some(dd::ReportStream::new(path));
+        }
+        let mut model_extractor = SystemModelExtractor::<CompositeModelExtractor>::new(extraction_params, sys_conf.clone());
+        let mut exited_models = SystemModel::new(sys_conf);
+        let mut push_reports = |count: usize, model: &SystemModel<CompositeModel>| -> AppResult {
+            if args.report {
+                report_periodic.push_model(count, model);
+                report_periodic_ss.push_model(count, model);
+            }
+            if args.rbf_report {
+                report_rbf.push_model(count, model);
+            }
+            if let Some(report_stream) = &mut report_stream {
+                report_stream.push_model(count, model)?;
+            }
+
+            Ok(())
+        };
 
         let mut last_update_time = Time::zero();
         let mut model_changed = false;
@@ -96,6 +209,15 @@ fn _main(args: Opt) -> AppResult {
                 arrival_cnt += 1;
             }
 
+            /* The model of a task that exits is final: report it once, then stop extracting it */
+            if event.is_exit() {
+                let exited = model_extractor.take_exited_models();
+                push_reports(arrival_cnt as usize, &exited)?;
+                for pid in exited.pids() {
+                    exited_models.set_task_model(*pid, exited.get_model(*pid).unwrap().clone());
+                }
+            }
+
             /* Perform model extraction every update_interval seconds or every update_arrival arrivals */
             if last_update_time.is_zero() {
                 last_update_time = event.instant;
@@ -113,34 +235,44 @@ fn _main(args: Opt) -> AppResult {
 
                 /* Print current models */
                 if args.print {
-                    print_periodic_models(&model);
+                    model.pretty_print();
                     println!("----------");
                 }
                 /* Add to report */
-                if args.report {
-                    report_periodic.push_model(arrival_cnt as usize, &model);
-                    report_periodic_ss.push_model(arrival_cnt as usize, &model);
-                }
+                push_reports(arrival_cnt as usize, &model)?;
 
                 last_update_time = event.instant;
                 model_changed = false;
             }
         }
 
+        /* The job running when the trace ends completes at the last event */
+        if let Some(end) = trace_end.filter(|_| args.finalize_open_jobs) {
+            if model_extractor.finalize_open_jobs(end) {
+                model_changed = true;
+                arrival_cnt += 1;
+            }
+        }
+
         /* Trace might have been shorter than update_interval or there might be events left */
         if model_changed {
             model = model_extractor.extract_model();
             /* Add to report */
-            if args.report {
-                report_periodic.push_model(arrival_cnt as usize, &model);
-                report_periodic_ss.push_model(arrival_cnt as usize, &model);
+            push_reports(arrival_cnt as usize, &model)?;
+        }
+        log::info!("Extractors of {} running tasks hold about {} KiB", model_extractor.pids().count(), model_extractor.memory_footprint() / 1024);
+
+        /* The final models include the tasks that exited, unless their pid was reused */
+        for pid in exited_models.pids() {
+            if !model_extractor.pids().any(|live| live == pid) {
+                model.set_task_model(*pid, exited_models.get_model(*pid).unwrap().clone());
             }
         }
     }
 
     /* Print final models */
     if args.print || args.output_path.is_none() {
-        print_periodic_models(&model);
+        model.pretty_print();
     }
 
     if let Some(mut path) = args.output_path {
@@ -149,11 +281,18 @@ fn _main(args: Opt) -> AppResult {
         if args.report {
             report_periodic.write_yaml(&path)?;
             report_periodic_ss.write_yaml(&path)?;
-        } else {
-            path.push("rbf"); // Create also rbf subdir
-            create_dir(&path)?;
-            path.pop();
-            dd::Output::from(&model).write_yaml(path)?;
+        } else if !args.report_stream {
+            let write_rbf = !(args.no_rbf_files || args.rbf_off);
+            if write_rbf {
+                path.push("rbf"); // Create also rbf subdir
+                create_dir(&path)?;
+                path.pop();
+            }
+            dd::Output::from(&model).with_rbf_files(write_rbf).write_yaml(&path)?;
+        }
+
+        if args.rbf_report {
+            report_rbf.write_yaml(&path)?;
         }
     }
 
@@ -189,10 +328,77 @@ pub struct Opt {
     #[structopt(long="report", requires("output-path"))]
     pub report: bool,
 
+    /// Like --report, but each step is appended to the report as soon as it is extracted,
+    /// instead of keeping the whole report in memory.
+    /// Reports are written in output_path/[pid].[model].report.ndjson, one JSON object per step.
+    #[structopt(long="report-stream", requires("output-path"), conflicts_with("report"))]
+    pub report_stream: bool,
+
+    /// Output a file with the RBF curve of each task at each step, to follow its evolution.
+    /// Reports are written in output_path/[pid].rbf.report.yaml
+    #[structopt(long="rbf-report", requires("output-path"))]
+    pub rbf_report: bool,
+
+    /// Keep only one activation cycle every n before extraction, to speed up very long traces.
+    /// The periods of the periodic models are divided by n to undo the aliasing,
+    /// but beware that the spectral and RBF models are extracted from the downsampled arrivals.
+    #[structopt(long)]
+    pub downsample: Option<usize>,
+
+    /// CPU frequency (Hz) at which the trace was recorded.
+    #[structopt(long="cpu-freq")]
+    pub cpu_freq: Option<u64>,
+
+    /// Reference CPU frequency (Hz): the costs measured at --cpu-freq are scaled to it before computing
+    /// the WCET of the periodic models. It is recorded in the system configuration of the extracted model.
+    #[structopt(long="ref-cpu-freq", requires("cpu-freq"))]
+    pub ref_cpu_freq: Option<u64>,
+
+    /// Only extract the models of this pid (before relabeling with --anonymize).
+    #[structopt(long)]
+    pub pid: Option<Pid>,
+
+    /// Relabel the pids to 1..=n, in order of first appearance, so that the output doesn't contain the real pids.
+    #[structopt(long)]
+    pub anonymize: bool,
+
+    /// Stop feeding events to the extractors after n events, then extract and report as usual.
+    #[structopt(long)]
+    pub max_events: Option<usize>,
+
+    /// Stop feeding events to the extractors after this many seconds of trace (from the first event),
+    /// then extract and report as usual.
+    #[structopt(long)]
+    pub max_duration: Option<f32>,
+
+    /// Account the job running when the trace ends, as if it completed at the last event.
+    /// Its execution time only covers the part of the job that was traced.
+    #[structopt(long)]
+    pub finalize_open_jobs: bool,
+
+    /// Warn about intervals of the trace longer than this without any event (in milliseconds, or with a unit such as "1s"),
+    /// a hint that the kernel dropped events because the ftrace buffer was too small. A value of 0 disables the check.
+    #[structopt(long, default_value="1s", parse(try_from_str = parse_ms))]
+    pub max_event_gap: Time,
+
+    /// Only check the trace and print a report, without extracting any model: the illegal sequences of events of each pid,
+    /// and the silent regions (see --max-event-gap). Exits with a nonzero code if the trace is empty or has illegal sequences.
+    /// Timestamps that go backwards already fail when reading the trace.
+    #[structopt(long)]
+    pub validate: bool,
+
     /// Print extracted scalar models at each step.
     #[structopt(short = "p", long)]
     pub print: bool,
 
+    /// Show more diagnostics of the extractors (repeatable: -v info, -vv debug, -vvv trace). Warnings are shown by default.
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    pub verbose: u64,
+
+    /// Only show errors.
+    #[structopt(short = "q", long, conflicts_with("verbose"))]
+    pub quiet: bool,
+
     // TOGGLE MODEL MATCHERS
     /// Toggle periodic extractor
     #[structopt(short = "P", long)]
@@ -202,19 +408,27 @@ pub struct Opt {
     #[structopt(short = "S", long)]
     pub spectral_off: bool,
 
-    /// Toggle RBF extractor
+    /// Toggle RBF extractor (no RBF files are written either)
     #[structopt(short = "R", long)]
     pub rbf_off: bool,
 
+    /// Don't write the RBF files (output_path/rbf/[pid].rbf.yaml), only the periodic models.
+    #[structopt(long)]
+    pub no_rbf_files: bool,
+
     // TUNABLES
-    /// Jitter bound (in nanoseconds).
-    #[structopt(short = "J", long="j-max", default_value="1500000")]
+    /// Jitter bound (in milliseconds, or with a unit such as "1500us").
+    #[structopt(short = "J", long="j-max", default_value="1.5ms", parse(try_from_str = parse_ms))]
     pub jitter_bound: Jitter,
 
-    /// Resolution of the trace (in nanoseconds). A value of 1 means nanosecond resolution.
-    #[structopt(short = "r", long="resolution", default_value="100000")]
+    /// Resolution of the trace (in microseconds, or with a unit such as "100ns"). A value of 1ns means nanosecond resolution.
+    #[structopt(short = "r", long="resolution", default_value="100us", parse(try_from_str = parse_us))]
     pub resolution: Time,
 
+    /// Jobs with a smaller execution time (in microseconds, or with a unit such as "500ns") are ignored as noise.
+    #[structopt(long="min-job-cost", default_value="0", parse(try_from_str = parse_us))]
+    pub min_job_cost: Time,
+
     /// Maximal arrival window for RBFs and spectral extractor.
     #[structopt(short = "w", long, default_value="1000")]
     pub window_size: usize,
@@ -226,6 +440,19 @@ pub struct Opt {
     /// Cutoff spectral density for period picking in the spectral extractor.
     #[structopt(short = "f", long, default_value="0.5")]
     pub fft_cutoff: f32,
+
+    /// Minimum normalized power by which a spike must exceed the adjacent frequencies in the spectral extractor.
+    #[structopt(long, default_value="0")]
+    pub min_spike_prominence: f32,
+
+    /// Minimum power of the strongest frequency in the spectral extractor, relative to a pure cosine (between 0 and 1).
+    #[structopt(long, default_value="0")]
+    pub min_periodicity_power: f32,
+
+    /// When the signal of the spectral extractor exceeds the maximal size, lower its sampling rate
+    /// to cover the whole trace instead of cropping its tail.
+    #[structopt(long)]
+    pub resample_signal: bool,
 }
 
 impl From<&Opt> for CompositeExtractionParams {
@@ -233,16 +460,25 @@ impl From<&Opt> for CompositeExtractionParams {
         let periodic = PeriodicTaskExtractionParams {
             j_max: opts.jitter_bound,
             resolution: opts.resolution,
+            min_job_cost: opts.min_job_cost,
+            downsampling: opts.downsample,
+            frequency_scaling: opts.cpu_freq.and_then(|hz| SysConf::from(opts).frequency_scaling(hz)),
+            ..Default::default()
         };
 
         let spectral = SpectralExtractionParams {
             max_signal_len: opts.signal_size,
             window_size: opts.window_size,
             fft_filter_cutoff: opts.fft_cutoff,
+            resample_whole_trace: opts.resample_signal,
+            min_spike_prominence: opts.min_spike_prominence,
+            min_periodicity_power: opts.min_periodicity_power,
         };
 
         let rbf = RBFExtractionParams {
-            window_size: opts.window_size
+            window_size: opts.window_size,
+            min_job_cost: opts.min_job_cost,
+            ..Default::default()
         };
 
         CompositeExtractionParams {
@@ -255,11 +491,21 @@ impl From<&Opt> for CompositeExtractionParams {
         }
     }
 }
+
+impl From<&Opt> for SysConf {
+    fn from(opts: &Opt) -> Self {
+        SysConf {
+            ref_cpu_freq_hz: opts.ref_cpu_freq,
+            ..Default::default()
+        }
+    }
+}
+
 /* I/O formats and conversions */
 mod dd {
-    use std::{collections::BTreeMap, path::Path, fs::OpenOptions};
+    use std::{collections::BTreeMap, path::{Path, PathBuf}, fs::{File, OpenOptions}, io::Write};
     use rbftrace_core::{model::{SystemModel, PeriodicTask, PeriodicSelfSuspendingTask}, rbf::RbfCurve};
-    use rbftrace_model_extraction::composite::CompositeModel;
+    use rbftrace_model_extraction::composite::{CompositeModel, CompositeModels};
     use serde::{Deserialize, Serialize, Serializer};
 
     use rbftrace_core::{sys_conf::Pid, rbf::Point};
@@ -276,14 +522,21 @@ mod dd {
     #[derive(Debug)]
     pub struct Output {
         pub models: BTreeMap<Pid, CompositeModel>,
+        /// Write output_dir/rbf/[pid].rbf.yaml, the rbf subdir must exist
+        pub rbf_files: bool,
     }
     
     impl Output {
         pub fn new() -> Self {
             Output {
                 models: BTreeMap::new(),
+                rbf_files: true,
             }
         }
+
+        pub fn with_rbf_files(self, rbf_files: bool) -> Self {
+            Self { rbf_files, ..self }
+        }
     }
     
     impl From<&SystemModel<CompositeModel>> for Output {
@@ -333,6 +586,9 @@ mod dd {
                 }
 
                 /* RBF */
+                if !self.rbf_files {
+                    continue;
+                }
                 let rbf = OutputRbf::from(&model.rbf);
                 let filename = format!("{}.rbf.yaml", pid);
                 let path = Path::new(output_dir.as_ref()).join("rbf").join(filename);
@@ -356,13 +612,8 @@ mod dd {
     
     impl From<&RbfCurve> for OutputRbf {
         fn from(rbf_curve: &RbfCurve) -> Self {
-            let mut points: Vec<Point> = Vec::new();
-            for p in rbf_curve.curve.into_iter() {
-                points.push(p);
-            }
-
             OutputRbf {
-                rbf: points,
+                rbf: rbf_curve.as_points(),
             }
         }
     }
@@ -396,8 +647,9 @@ mod dd {
         }
 
         pub fn push_model(&mut self, count: usize, model: &SystemModel<CompositeModel>) {
+            let periodic_tasks = model.periodic_tasks();
             for pid in model.pids() {
-                let m = model.get_model(*pid).map(|e| e.periodic).flatten();
+                let m = periodic_tasks.get(pid).map(|t| **t);
                 let record_entry = ReportEntry{
                     sample_count: count,
                     model: m
@@ -439,8 +691,9 @@ mod dd {
         }
 
         pub fn push_model(&mut self, count: usize, model: &SystemModel<CompositeModel>) {
+            let periodic_ss_tasks = model.periodic_ss_tasks();
             for pid in model.pids() {
-                let m = model.get_model(*pid).map(|e| e.periodic_ss.clone()).flatten();
+                let m = periodic_ss_tasks.get(pid).map(|t| (*t).clone());
                 let record_entry = ReportEntry{
                     sample_count: count,
                     model: m
@@ -473,6 +726,91 @@ mod dd {
             Ok(())
         }
     }
+
+    impl Report<OutputRbf> {
+        pub fn new() -> Self {
+            Self {
+                entries: BTreeMap::new()
+            }
+        }
+
+        pub fn push_model(&mut self, count: usize, model: &SystemModel<CompositeModel>) {
+            for (pid, rbf) in model.rbf_curves() {
+                let record_entry = ReportEntry{
+                    sample_count: count,
+                    model: Some(OutputRbf::from(rbf))
+                };
+
+                self.entries.entry(pid).or_default().push(record_entry);
+            }
+        }
+    }
+
+    impl WriteYAML for Report<OutputRbf> {
+        fn write_yaml<P: AsRef<Path>>(&self, output_dir: P) -> Result<(), AppError>{
+            for (pid, model) in &self.entries {
+                let filename = format!("{}.rbf.report.yaml", pid);
+                let path = Path::new(output_dir.as_ref()).join(filename);
+
+                let file = OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(path)
+                .map_err(AppError::OSError)?;
+
+                serde_yaml::to_writer(file, &model).map_err(AppError::DeserializationFailure)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// A report that is written incrementally: the entry of each pid is appended to
+    /// output_dir/[pid].[model].report.ndjson as soon as it is pushed.
+    pub struct ReportStream {
+        output_dir: PathBuf,
+        files: BTreeMap<(Pid, &'static str), File>,
+    }
+
+    impl ReportStream {
+        pub fn new<P: AsRef<Path>>(output_dir: P) -> Self {
+            Self {
+                output_dir: output_dir.as_ref().to_path_buf(),
+                files: BTreeMap::new(),
+            }
+        }
+
+        pub fn push_model(&mut self, count: usize, model: &SystemModel<CompositeModel>) -> Result<(), AppError> {
+            for pid in model.pids() {
+                let m = model.get_model(*pid).unwrap();
+
+                self.append(*pid, "periodic", &ReportEntry{ sample_count: count, model: m.periodic })?;
+                self.append(*pid, "periodic_ss", &ReportEntry{ sample_count: count, model: m.periodic_ss.clone() })?;
+            }
+
+            Ok(())
+        }
+
+        fn append<T: Serialize>(&mut self, pid: Pid, model_name: &'static str, entry: &ReportEntry<T>) -> Result<(), AppError> {
+            if !self.files.contains_key(&(pid, model_name)) {
+                let filename = format!("{}.{}.report.ndjson", pid, model_name);
+                let path = self.output_dir.join(filename);
+
+                let file = OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(path)?;
+
+                self.files.insert((pid, model_name), file);
+            }
+
+            let mut file = self.files.get(&(pid, model_name)).unwrap();
+            serde_json::to_writer(&mut file, entry).map_err(|e| AppError::OSError(e.into()))?;
+            writeln!(file)?;
+
+            Ok(())
+        }
+    }
 }
 
 /* Error handling */
@@ -484,6 +822,7 @@ pub enum AppError {
     // MatcherError(),
     OSError(std::io::Error),
     DeserializationFailure(serde_yaml::Error),
+    InvalidTrace(String),
 }
 
 impl From<rbftrace_core::trace::TraceError> for AppError {
@@ -503,3 +842,241 @@ impl From<serde_yaml::Error> for AppError {
         AppError::DeserializationFailure(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{model::{SystemModel, PeriodicTask}, rbf::RbfCurve, sys_conf::{SysConf, Pid}, time::Time, trace::{Trace, TraceBuilder, TraceEvent}, util::log_level};
+    use rbftrace_model_extraction::{SystemModelExtractor, composite::{CompositeModel, CompositeModelExtractor, CompositeExtractionParams}};
+
+    use log::LevelFilter;
+    use structopt::StructOpt;
+
+    use crate::{cap_trace, dd::ReportStream, exit_code, Opt, _main};
+
+    #[test]
+    fn report_stream_one_line_per_step() {
+        let output_dir = std::env::temp_dir().join(format!("match-model-report-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&output_dir).unwrap();
+
+        let periodic = PeriodicTask::new(Time::from_ms(10.), Time::zero(), Time::zero(), Time::from_ms(2.));
+        let mut model = SystemModel::new(SysConf::default());
+        model.set_task_model(1, CompositeModel::new(Some(periodic), None, RbfCurve::new(1, 10)));
+        model.set_task_model(2, CompositeModel::new(None, None, RbfCurve::new(2, 10)));
+
+        let mut report_stream = ReportStream::new(&output_dir);
+        for step in 0..3 {
+            assert!(report_stream.push_model(step, &model).is_ok());
+        }
+        drop(report_stream);
+
+        for pid in [1, 2] {
+            for model_name in ["periodic", "periodic_ss"] {
+                let path = output_dir.join(format!("{}.{}.report.ndjson", pid, model_name));
+                let content = std::fs::read_to_string(path).unwrap();
+
+                assert_eq!(content.lines().count(), 3);
+            }
+        }
+
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn verbosity_flags() {
+        let level = |flags: &[&str]| {
+            let args = Opt::from_iter(["match-model", "-s", "trace.yaml"].iter().chain(flags));
+            log_level(args.verbose, args.quiet)
+        };
+
+        assert_eq!(level(&[]), LevelFilter::Warn);
+        assert_eq!(level(&["-v"]), LevelFilter::Info);
+        assert_eq!(level(&["-vv"]), LevelFilter::Debug);
+        assert_eq!(level(&["-v", "--verbose", "-v"]), LevelFilter::Trace);
+        assert_eq!(level(&["-q"]), LevelFilter::Error);
+        assert!(Opt::from_iter_safe(["match-model", "-s", "trace.yaml", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn default_time_units() {
+        let args = |flags: &[&str]| Opt::from_iter(["match-model", "-s", "trace.yaml"].iter().chain(flags));
+
+        let defaults = args(&[]);
+        assert_eq!(defaults.jitter_bound, Time::from_us(1500.));
+        assert_eq!(defaults.resolution, Time::from_us(100.));
+        assert_eq!(defaults.max_event_gap, Time::from_s(1.));
+
+        assert_eq!(args(&["--j-max", "1"]).jitter_bound, Time::from_ms(1.));
+        assert_eq!(args(&["--j-max", "1000000ns"]).jitter_bound, Time::from_ms(1.));
+        assert_eq!(args(&["-r", "1ns"]).resolution, Time::from_ns(1));
+        assert_eq!(args(&["--min-job-cost", "50"]).min_job_cost, Time::from_us(50.));
+        assert!(Opt::from_iter_safe(["match-model", "-s", "trace.yaml", "--j-max", "1h"]).is_err());
+    }
+
+    #[test]
+    fn frequency_scaling() {
+        let args = |flags: &[&str]| Opt::from_iter(["match-model", "-s", "trace.yaml"].iter().chain(flags));
+
+        let scaled = args(&["--cpu-freq", "2000000000", "--ref-cpu-freq", "1000000000"]);
+        assert_eq!(SysConf::from(&scaled).ref_cpu_freq_hz, Some(1_000_000_000));
+        assert_eq!(CompositeExtractionParams::from(&scaled).periodic.frequency_scaling, Some((2_000_000_000, 1_000_000_000)));
+
+        // Without a reference, the costs are not scaled
+        assert_eq!(CompositeExtractionParams::from(&args(&["--cpu-freq", "2000000000"])).periodic.frequency_scaling, None);
+        assert!(Opt::from_iter_safe(["match-model", "-s", "trace.yaml", "--ref-cpu-freq", "1000000000"]).is_err());
+    }
+
+    #[test]
+    fn single_pid() {
+        let dir = std::env::temp_dir().join(format!("match-model-single-pid-{}", std::process::id()));
+        let output_dir = dir.join("out");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut events = vec![];
+        for i in 0..5 {
+            for pid in [1, 2] {
+                let arrival = Time::from_ms(10. * i as f64 + pid as f64);
+                events.push(TraceEvent::activation(pid, arrival));
+                events.push(TraceEvent::dispatch(pid, arrival));
+                events.push(TraceEvent::deactivation(pid, arrival + Time::from_ms(0.5)));
+            }
+        }
+        let source = dir.join("trace.yaml");
+        std::fs::write(&source, serde_yaml::to_string(&events).unwrap()).unwrap();
+
+        let args = Opt::from_iter(["match-model", "-s", source.to_str().unwrap(), "-o", output_dir.to_str().unwrap(), "--pid", "2"]);
+        assert!(_main(args).is_ok());
+
+        let rbf_files: Vec<_> = std::fs::read_dir(output_dir.join("rbf")).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(rbf_files, ["2.rbf.yaml"]);
+        assert!(output_dir.join("2.periodic.yaml").exists());
+        assert!(!output_dir.join("1.periodic.yaml").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_rbf_files() {
+        let dir = std::env::temp_dir().join(format!("match-model-no-rbf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 10)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(20.), Time::from_ms(1.), 5)
+            .build();
+        let source = dir.join("trace.yaml");
+        trace.to_yaml_file(&source).unwrap();
+
+        let run = |output_dir: &std::path::Path, flags: &[&str]| {
+            let args = ["match-model", "-s", source.to_str().unwrap(), "-o", output_dir.to_str().unwrap()];
+            assert!(_main(Opt::from_iter(args.iter().chain(flags))).is_ok());
+            let mut files: Vec<_> = std::fs::read_dir(output_dir).unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(run(&dir.join("all"), &[]), ["1.periodic.yaml", "2.periodic.yaml", "rbf"]);
+        assert_eq!(run(&dir.join("no-rbf"), &["--no-rbf-files"]), ["1.periodic.yaml", "2.periodic.yaml"]);
+        assert_eq!(run(&dir.join("rbf-off"), &["--rbf-off"]), ["1.periodic.yaml", "2.periodic.yaml"]);
+        assert_eq!(std::fs::read_to_string(dir.join("no-rbf/1.periodic.yaml")).unwrap(),
+                   std::fs::read_to_string(dir.join("all/1.periodic.yaml")).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exited_pid_reported_once() {
+        let dir = std::env::temp_dir().join(format!("match-model-exit-{}", std::process::id()));
+        let output_dir = dir.join("out");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Pid 1 exits after 5 jobs, pid 2 keeps running
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 5)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(10.), Time::from_ms(1.), 10)
+            .build()
+            .merge(&Trace::from([TraceEvent::exit(1, Time::from_ms(45.))]))
+            .unwrap();
+        let source = dir.join("trace.yaml");
+        trace.to_yaml_file(&source).unwrap();
+
+        let args = Opt::from_iter(["match-model", "-s", source.to_str().unwrap(), "-o", output_dir.to_str().unwrap(), "-a", "1", "--report-stream"]);
+        assert!(_main(args).is_ok());
+
+        let sample_counts = |pid: Pid| -> Vec<u64> {
+            let content = std::fs::read_to_string(output_dir.join(format!("{}.periodic.report.ndjson", pid))).unwrap();
+            content.lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["sample_count"].as_u64().unwrap())
+                .collect()
+        };
+        let (counts_1, counts_2) = (sample_counts(1), sample_counts(2));
+
+        // Pid 1 is reported at the checkpoints before its exit, plus once with its final model at the exit,
+        // while the checkpoints that follow only report pid 2
+        let exit_count = *counts_1.last().unwrap();
+        assert!(counts_1.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(counts_1.len(), counts_2.iter().filter(|count| **count <= exit_count).count() + 1);
+        assert!(counts_2.iter().filter(|count| **count > exit_count).count() >= 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn capped_trace() {
+        // Two jobs of 1ms, then a job of 5ms
+        let trace = Trace::from([
+            TraceEvent::activation(1, Time::from_ms(0.)),
+            TraceEvent::dispatch(1, Time::from_ms(0.)),
+            TraceEvent::deactivation(1, Time::from_ms(1.)),
+            TraceEvent::activation(1, Time::from_ms(10.)),
+            TraceEvent::dispatch(1, Time::from_ms(10.)),
+            TraceEvent::deactivation(1, Time::from_ms(11.)),
+            TraceEvent::activation(1, Time::from_ms(20.)),
+            TraceEvent::dispatch(1, Time::from_ms(20.)),
+            TraceEvent::deactivation(1, Time::from_ms(25.)),
+            TraceEvent::activation(1, Time::from_ms(30.)),
+        ]);
+
+        let capped = cap_trace(&trace, Some(7), None);
+        assert_eq!(capped.events().count(), 7);
+        assert_eq!(cap_trace(&trace, None, Some(Time::from_ms(15.))).events().count(), 6);
+        assert_eq!(cap_trace(&trace, None, None), trace);
+
+        // A job is detected at the next activation, so only the first two jobs are seen
+        let params = || CompositeExtractionParams { rbf_enabled: true, ..Default::default() };
+        let extract = |trace| SystemModelExtractor::<CompositeModelExtractor>::extract_from_trace(params(), SysConf::default(), trace);
+        assert_eq!(extract(capped).get_model(1).unwrap().rbf.get(Time::from_ms(100.)), Time::from_ms(2.));
+        assert_eq!(extract(trace).get_model(1).unwrap().rbf.get(Time::from_ms(100.)), Time::from_ms(7.));
+    }
+
+    #[test]
+    fn validate() {
+        let dir = std::env::temp_dir().join(format!("match-model-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let clean = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 10)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(20.), Time::from_ms(1.), 5)
+            .build();
+        // Dispatched twice without being preempted
+        let mut events: Vec<TraceEvent> = clean.events().copied().collect();
+        events.push(TraceEvent::dispatch(1, Time::from_ms(2.)));
+        let corrupted = Trace::from_unsorted(events);
+
+        let run = |trace: &Trace, name: &str| {
+            let source = dir.join(name);
+            trace.to_yaml_file(&source).unwrap();
+            // No output directory is needed, nothing is extracted
+            exit_code(_main(Opt::from_iter(["match-model", "-s", source.to_str().unwrap(), "--validate"])))
+        };
+
+        assert_eq!(run(&clean, "clean.yaml"), 0);
+        assert_ne!(run(&corrupted, "corrupted.yaml"), 0);
+        assert_ne!(run(&Trace::new(), "empty.yaml"), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,11 @@
 //! This module contains an extractor composed of all the supported type of extractors.
 //! This is useful to extract several models at once.
 
-use rbftrace_core::{model::PeriodicTask, model::PeriodicSelfSuspendingTask,
-                    rbf::RbfCurve, trace::TraceEvent, time::Time};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use rbftrace_core::{model::PeriodicTask, model::PeriodicSelfSuspendingTask, model::SystemModel, model::ModelError,
+                    rbf::RbfCurve, trace::TraceEvent, time::{Time, HumanTime}, sys_conf::{Pid, Priority}};
 
 use crate::{periodic::{PeriodicTaskExtractionParams, PeriodicTaskExtractor},
             spectral::{SpectralExtractionParams, SpectralExtractor},
@@ -15,6 +18,8 @@ pub struct CompositeModelExtractor {
     periodic_enabled: bool,
     spectral_enabled: bool,
     rbf_enabled: bool,
+    pid: Pid,
+    prio: Priority,
 }
 
 #[derive(Default)]
@@ -34,11 +39,147 @@ pub struct CompositeModel {
     pub rbf: RbfCurve
 }
 
+/// The periodic model of a task, see `CompositeModel::best_periodic`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PeriodicModel<'a> {
+    Periodic(&'a PeriodicTask),
+    SelfSuspending(&'a PeriodicSelfSuspendingTask),
+    NotPeriodic,
+}
+
 impl CompositeModel {
     pub fn new(periodic: Option<PeriodicTask>, periodic_ss: Option<PeriodicSelfSuspendingTask>,
             rbf: RbfCurve) -> Self {
         Self {periodic, periodic_ss, rbf}
     }
+
+    /// The periodic model of the task, following the hierarchy of the extractors:
+    /// the self-suspending model is only used when no plain periodic model was found.
+    pub fn best_periodic(&self) -> PeriodicModel<'_> {
+        match (&self.periodic, &self.periodic_ss) {
+            (Some(periodic), _) => PeriodicModel::Periodic(periodic),
+            (None, Some(periodic_ss)) => PeriodicModel::SelfSuspending(periodic_ss),
+            (None, None) => PeriodicModel::NotPeriodic,
+        }
+    }
+}
+
+/// Interference analysis on the curves of a system model
+pub trait Interference {
+    /// Sum of the RBF curves of the tasks with a strictly higher priority than `target` that can run on one of its CPUs.
+    /// Priorities and affinities are taken from the system configuration; for tasks missing from it,
+    /// the priority of the curve is used and the task is assumed to share a CPU with the target.
+    fn interference_curve(&self, target: Pid) -> RbfCurve;
+}
+
+impl Interference for SystemModel<CompositeModel> {
+    fn interference_curve(&self, target: Pid) -> RbfCurve {
+        let sys_conf = self.get_sys_conf();
+        let prio_of = |pid: Pid| sys_conf.priority_of(pid)
+                                         .or_else(|| self.get_model(pid).map(|m| m.rbf.prio))
+                                         .unwrap_or(0);
+        let shares_cpu = |pid: Pid| match (sys_conf.affinity_of(target), sys_conf.affinity_of(pid)) {
+            (Some(t), Some(o)) if !t.is_empty() && !o.is_empty() => {
+                t.iter().any(|cpu| o.contains(cpu))
+            },
+            _ => true,
+        };
+
+        let target_prio = prio_of(target);
+        let mut ret = RbfCurve::new(target, RBFExtractionParams::default().window_size);
+
+        for pid in self.pids() {
+            if *pid != target && prio_of(*pid) > target_prio && shares_cpu(*pid) {
+                ret.sum(&self.get_model(*pid).unwrap().rbf);
+            }
+        }
+        // The sum is an aggregate without pid and priority
+        ret.pid = target;
+        ret.prio = target_prio;
+
+        ret
+    }
+}
+
+/// Typed views over the models of a system model, one entry per pid that has a model of that type
+pub trait CompositeModels {
+    fn rbf_curves(&self) -> BTreeMap<Pid, &RbfCurve>;
+    fn periodic_tasks(&self) -> BTreeMap<Pid, &PeriodicTask>;
+    fn periodic_ss_tasks(&self) -> BTreeMap<Pid, &PeriodicSelfSuspendingTask>;
+}
+
+impl CompositeModels for SystemModel<CompositeModel> {
+    fn rbf_curves(&self) -> BTreeMap<Pid, &RbfCurve> {
+        self.pids()
+            .filter_map(|pid| self.get_model(*pid).map(|m| (*pid, &m.rbf)))
+            .collect()
+    }
+
+    fn periodic_tasks(&self) -> BTreeMap<Pid, &PeriodicTask> {
+        self.pids()
+            .filter_map(|pid| self.get_model(*pid).and_then(|m| m.periodic.as_ref()).map(|t| (*pid, t)))
+            .collect()
+    }
+
+    fn periodic_ss_tasks(&self) -> BTreeMap<Pid, &PeriodicSelfSuspendingTask> {
+        self.pids()
+            .filter_map(|pid| self.get_model(*pid).and_then(|m| m.periodic_ss.as_ref()).map(|t| (*pid, t)))
+            .collect()
+    }
+}
+
+/// Human readable dump of a system model
+pub trait SystemSummary {
+    /// One block per pid with its periodic, periodic with self-suspensions and RBF models,
+    /// followed by the total utilization of the (self-suspending) periodic tasks.
+    fn summary(&self) -> String;
+
+    fn pretty_print(&self) {
+        print!("{}", self.summary());
+    }
+}
+
+impl SystemSummary for SystemModel<CompositeModel> {
+    fn summary(&self) -> String {
+        let mut ret = String::new();
+        let mut utilization = 0.0;
+        let t = HumanTime;
+
+        // Writing to a String can't fail
+        for pid in self.pids() {
+            let model = self.get_model(*pid).unwrap();
+            writeln!(ret, "PID {}:", pid).unwrap();
+
+            match model.best_periodic() {
+                PeriodicModel::Periodic(periodic) => {
+                    writeln!(ret, "    periodic: P = {}, J = {}, O = {}, WCET = {}, U = {:.3}",
+                             t(periodic.period), t(periodic.jitter), t(periodic.offset), t(periodic.wcet), periodic.utilization()).unwrap();
+                    utilization += periodic.utilization();
+                },
+                PeriodicModel::SelfSuspending(periodic_ss) => {
+                    let u = if periodic_ss.period.is_zero() { 0.0 } else { periodic_ss.total_wcet.to_ns() as f64 / periodic_ss.period.to_ns() as f64 };
+                    writeln!(ret, "    periodic_ss: P = {}, WCET = {}, WCSS = {}, segments = {}, U = {:.3}",
+                             t(periodic_ss.period), t(periodic_ss.total_wcet), t(periodic_ss.total_wcss), periodic_ss.computation_segments(), u).unwrap();
+                    utilization += u;
+                },
+                PeriodicModel::NotPeriodic => writeln!(ret, "    not periodic").unwrap(),
+            }
+            writeln!(ret, "    rbf: WCET = {}, points = {}", t(model.rbf.wcet), model.rbf.as_points().len()).unwrap();
+        }
+        writeln!(ret, "Utilization: {:.3}", utilization).unwrap();
+
+        ret
+    }
+}
+
+impl CompositeModelExtractor {
+    /// Empty curve used when the RBF extractor is disabled, still carrying the pid and the priority of the task
+    fn placeholder_rbf(&self) -> RbfCurve {
+        let mut ret = RbfCurve::new(self.pid, 1);
+        ret.prio = self.prio;
+
+        ret
+    }
 }
 
 impl TaskModelExtractor for CompositeModelExtractor {
@@ -53,6 +194,8 @@ impl TaskModelExtractor for CompositeModelExtractor {
             periodic_enabled: params.periodic_enabled,
             spectral_enabled: params.spectral_enabled,
             rbf_enabled: params.rbf_enabled,
+            pid: 0,
+            prio: 0,
         }
     }
 
@@ -78,11 +221,41 @@ impl TaskModelExtractor for CompositeModelExtractor {
         periodic_changed || spectral_changed || rbf_changed
     }
 
+    fn set_task_attributes(&mut self, pid: Pid, prio: Priority) {
+        self.pid = pid;
+        self.prio = prio;
+        self.rbf_extractor.set_task_attributes(pid, prio);
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        let mut changed = false;
+
+        if self.rbf_enabled {
+            changed |= self.rbf_extractor.finalize_open_jobs(end);
+        }
+        if self.periodic_enabled {
+            changed |= self.periodic_extractor.finalize_open_jobs(end);
+        }
+        if self.spectral_enabled {
+            changed |= self.spectral_extractor.finalize_open_jobs(end);
+        }
+
+        changed
+    }
+
+    /// Disabled extractors hold their initial buffers
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.periodic_extractor.heap_size()
+            + self.spectral_extractor.heap_size()
+            + self.rbf_extractor.heap_size()
+    }
+
     /// Implements the hierarchy of the model extractors.
     fn extract_model(&mut self) -> Option<Self::Model> {
         let mut periodic = None;
         let mut periodic_ss = None;
-        let mut rbf = RbfCurve::new(0, 1);
+        let mut rbf = self.placeholder_rbf();
 
         if self.rbf_enabled {
             rbf = self.rbf_extractor.extract_model().unwrap(); // RBFs can always be extracted
@@ -97,6 +270,25 @@ impl TaskModelExtractor for CompositeModelExtractor {
         Some(CompositeModel::new(periodic, periodic_ss, rbf))
     }
 
+    /// Same hierarchy as `extract_model`, failing on the first malformed input reported by an enabled extractor
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        let mut periodic = None;
+        let mut periodic_ss = None;
+        let mut rbf = self.placeholder_rbf();
+
+        if self.rbf_enabled {
+            rbf = self.rbf_extractor.try_extract_model()?.unwrap(); // RBFs can always be extracted
+        }
+        if self.periodic_enabled {
+            periodic = self.periodic_extractor.try_extract_model()?;
+        }
+        if self.spectral_enabled && periodic.is_none() {
+            periodic_ss = self.spectral_extractor.try_extract_model()?;
+        }
+
+        Ok(Some(CompositeModel::new(periodic, periodic_ss, rbf)))
+    }
+
     fn match_trace(&mut self, trace: &rbftrace_core::trace::Trace) -> bool {
         self.push_trace(trace);
         self.is_matching()
@@ -107,4 +299,182 @@ impl TaskModelExtractor for CompositeModelExtractor {
             self.push_event(*event);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{
+        model::{SystemModel, PeriodicTask, PeriodicSelfSuspendingTask},
+        rbf::{RbfCurve, Point},
+        sys_conf::{SysConf, ThreadInfo, Pid, Priority},
+        time::Time,
+        trace::{TraceBuilder, TraceEvent}};
+
+    use crate::{SystemModelExtractor, TaskModelExtractor};
+    use crate::composite::{CompositeModel, CompositeModelExtractor, CompositeExtractionParams, CompositeModels, Interference, PeriodicModel, SystemSummary};
+
+    #[test]
+    fn interference_curve() {
+        let tasks = [(1, 30, 10), (2, 20, 20), (3, 10, 30)];
+        let mut sys_conf = SysConf::default();
+        for (pid, prio, _) in tasks {
+            sys_conf.rt_threads_info.insert(pid, ThreadInfo { pid, prio, ..Default::default() });
+        }
+
+        let mut model = SystemModel::new(sys_conf);
+        for (pid, _, period) in tasks {
+            // The curves do not carry the priority, it must be taken from the system configuration
+            model.set_task_model(pid, CompositeModel::new(None, None, periodic_rbf(pid, 0, period)));
+        }
+
+        // Highest priority: no interference
+        let curve: Vec<Point> = model.interference_curve(1).curve.into_iter().collect();
+        assert_eq!(curve, [p(0, 0)]);
+
+        // Tasks 1 and 2
+        let curve = model.interference_curve(3);
+        let mut expected = periodic_rbf(1, 0, 10);
+        expected.sum(&periodic_rbf(2, 0, 20));
+
+        assert!(curve.approx_eq(&expected, Time::zero()));
+        assert_eq!(curve.pid, 3);
+        assert_eq!(curve.prio, 10);
+    }
+
+    #[test]
+    fn typed_maps() {
+        let periodic = PeriodicTask::new(Time::from_ms(10.), Time::zero(), Time::zero(), Time::from_ms(1.));
+        let periodic_ss = PeriodicSelfSuspendingTask { period: Time::from_ms(20.), ..Default::default() };
+
+        let mut model = SystemModel::new(SysConf::default());
+        model.set_task_model(1, CompositeModel::new(Some(periodic), None, periodic_rbf(1, 0, 10)));
+        model.set_task_model(2, CompositeModel::new(None, Some(periodic_ss.clone()), periodic_rbf(2, 0, 20)));
+        model.set_task_model(3, CompositeModel::new(None, None, periodic_rbf(3, 0, 30)));
+
+        let rbf_curves = model.rbf_curves();
+        assert_eq!(rbf_curves.keys().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(rbf_curves[&3].pid, 3);
+
+        let periodic_tasks = model.periodic_tasks();
+        assert_eq!(periodic_tasks.keys().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(*periodic_tasks[&1], periodic);
+
+        let periodic_ss_tasks = model.periodic_ss_tasks();
+        assert_eq!(periodic_ss_tasks.keys().copied().collect::<Vec<_>>(), [2]);
+        assert_eq!(*periodic_ss_tasks[&2], periodic_ss);
+    }
+
+    #[test]
+    fn map_to_periodic() {
+        let mut sys_conf = SysConf::default();
+        sys_conf.rt_threads_info.insert(1, ThreadInfo { pid: 1, prio: 10, ..Default::default() });
+        let periodic = PeriodicTask::new(Time::from_ms(10.), Time::zero(), Time::zero(), Time::from_ms(1.));
+        let periodic_ss = PeriodicSelfSuspendingTask { period: Time::from_ms(20.), ..Default::default() };
+
+        let mut model = SystemModel::new(sys_conf.clone());
+        model.set_task_model(1, CompositeModel::new(Some(periodic), None, periodic_rbf(1, 0, 10)));
+        model.set_task_model(2, CompositeModel::new(None, Some(periodic_ss), periodic_rbf(2, 0, 20)));
+        model.set_task_model(3, CompositeModel::new(None, None, periodic_rbf(3, 0, 30)));
+
+        let periodic_model: SystemModel<PeriodicTask> = model.clone().map(|m| m.periodic);
+        assert_eq!(periodic_model.pids().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(periodic_model.get_model(1), Some(&periodic));
+        assert!(periodic_model.get_sys_conf().semantically_eq(&sys_conf));
+
+        let rbf_model = model.map(|m| Some(m.rbf));
+        assert_eq!(rbf_model.pids().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(rbf_model.get_model(3).unwrap().pid, 3);
+    }
+
+    #[test]
+    fn best_periodic() {
+        let periodic = PeriodicTask::new(Time::from_ms(10.), Time::zero(), Time::zero(), Time::from_ms(1.));
+        let periodic_ss = PeriodicSelfSuspendingTask { period: Time::from_ms(20.), ..Default::default() };
+
+        let model = CompositeModel::new(None, Some(periodic_ss.clone()), periodic_rbf(1, 0, 20));
+        assert_eq!(model.best_periodic(), PeriodicModel::SelfSuspending(&periodic_ss));
+
+        let model = CompositeModel::new(Some(periodic), None, periodic_rbf(1, 0, 10));
+        assert_eq!(model.best_periodic(), PeriodicModel::Periodic(&periodic));
+
+        let model = CompositeModel::new(None, None, periodic_rbf(1, 0, 10));
+        assert_eq!(model.best_periodic(), PeriodicModel::NotPeriodic);
+    }
+
+    #[test]
+    fn summary() {
+        let periodic = PeriodicTask::new(Time::from_ms(10.), Time::zero(), Time::zero(), Time::from_ms(1.));
+
+        let mut model = SystemModel::new(SysConf::default());
+        model.set_task_model(1, CompositeModel::new(Some(periodic), None, periodic_rbf(1, 0, 10)));
+        model.set_task_model(2, CompositeModel::new(None, None, periodic_rbf(2, 0, 20)));
+
+        assert_eq!(model.summary(), "\
+PID 1:
+    periodic: P = 10ms, J = 0ns, O = 0ns, WCET = 1ms, U = 0.100
+    rbf: WCET = 2ns, points = 6
+PID 2:
+    not periodic
+    rbf: WCET = 2ns, points = 6
+Utilization: 0.100
+");
+    }
+
+    /* Support */
+
+    fn periodic_rbf(pid: Pid, prio: Priority, period_ns: u64) -> RbfCurve {
+        let mut rbf = RbfCurve::new(pid, 100);
+        rbf.prio = prio;
+        for i in 0..5 {
+            rbf.add_arrival(Time::from_ns(i * period_ns), Time::from_ns(2));
+        }
+
+        rbf
+    }
+
+    fn p(delta_ns: u64, cost_ns: u64) -> Point {
+        Point::new(Time::from_ns(delta_ns), Time::from_ns(cost_ns))
+    }
+
+    #[test]
+    fn empty_and_single_event() {
+        let params = CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, spectral_enabled: true, ..Default::default() };
+        let mut extractor = CompositeModelExtractor::from_params(&params);
+        let empty = extractor.try_extract_model().unwrap().unwrap();
+        assert_eq!(empty.best_periodic(), PeriodicModel::NotPeriodic);
+        assert_eq!(empty.rbf.as_points().len(), 1);
+
+        extractor.push_event(TraceEvent::activation(1, Time::from_ms(5.)));
+        let single = extractor.try_extract_model().unwrap().unwrap();
+        assert_eq!(single.best_periodic(), PeriodicModel::NotPeriodic);
+        assert_eq!(single.rbf.as_points().len(), 1);
+
+        // Nothing to summarize either
+        let model = SystemModel::<CompositeModel>::new(SysConf::default());
+        assert_eq!(model.summary(), "Utilization: 0.000\n");
+    }
+
+    #[test]
+    fn curve_attributes_from_sys_conf() {
+        let mut sys_conf = SysConf::default();
+        sys_conf.rt_threads_info.insert(1, ThreadInfo { pid: 1, prio: 42, ..Default::default() });
+
+        let trace = TraceBuilder::new()
+            .job(1, Time::from_ms(1.), Time::from_ms(1.))
+            .job(2, Time::from_ms(1.), Time::from_ms(1.))
+            .build();
+
+        for rbf_enabled in [true, false] {
+            let params = CompositeExtractionParams { rbf_enabled, periodic_enabled: true, ..Default::default() };
+            let mut extractor = SystemModelExtractor::<CompositeModelExtractor>::new(params, sys_conf.clone());
+            extractor.push_trace(&trace);
+
+            let model = extractor.extract_model();
+            let rbf_1 = &model.get_model(1).unwrap().rbf;
+            assert_eq!((rbf_1.pid, rbf_1.prio), (1, 42));
+            // Not in the configuration
+            let rbf_2 = &model.get_model(2).unwrap().rbf;
+            assert_eq!((rbf_2.pid, rbf_2.prio), (2, 0));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! This module contains a detector of priority inversions, from the events of all the tasks.
+
+use std::collections::BTreeMap;
+
+use rbftrace_core::{trace::{Trace, TraceEvent, TraceEventType}, sys_conf::{SysConf, Pid, Cpu}, time::Time};
+
+/// A task (the victim) was ready while a task with a lower priority (the usurper) ran on a CPU it is allowed to run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InversionEvent {
+    pub victim: Pid,
+    pub usurper: Pid,
+    pub start: Time,
+    pub duration: Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+    Ready,
+    Running(Option<Cpu>),
+}
+
+/// Follows the state of the tasks through their activations, dispatches, preemptions and deactivations,
+/// and reports the intervals during which a ready task waited for a task with a lower priority.
+/// Priorities and affinities are taken from the system configuration: the tasks missing from it are ignored,
+/// and a task with an empty affinity is assumed to compete for any CPU. If the CPU of the running task is unknown,
+/// the two tasks compete if their affinities intersect.
+pub struct PriorityInversionDetector {
+    sys_conf: SysConf,
+    min_duration: Time,
+    /// Tasks in the runqueue, the others are blocked
+    states: BTreeMap<Pid, TaskState>,
+    /// (victim, usurper) -> start of the inversion
+    ongoing: BTreeMap<(Pid, Pid), Time>,
+    inversions: Vec<InversionEvent>,
+}
+
+impl PriorityInversionDetector {
+    pub fn new(sys_conf: SysConf) -> Self {
+        Self {
+            sys_conf,
+            min_duration: Time::zero(),
+            states: BTreeMap::new(),
+            ongoing: BTreeMap::new(),
+            inversions: Vec::new(),
+        }
+    }
+
+    /// Ignore the inversions shorter than `min_duration`, e.g. the latency of a preemption
+    pub fn with_min_duration(self, min_duration: Time) -> Self {
+        Self { min_duration, ..self }
+    }
+
+    /// Push all the events of a trace, close the inversions still ongoing at its last event, and return all the inversions
+    pub fn detect(sys_conf: SysConf, trace: &Trace) -> Vec<InversionEvent> {
+        let mut detector = Self::new(sys_conf);
+        for event in trace.events() {
+            detector.push_event(*event);
+        }
+        if let Some(last) = trace.events().last() {
+            detector.finalize(last.instant);
+        }
+
+        detector.inversions
+    }
+
+    /// Returns true if an inversion ended with this event
+    pub fn push_event(&mut self, event: TraceEvent) -> bool {
+        if self.sys_conf.priority_of(event.pid).is_none() {
+            return false;
+        }
+
+        match event.etype {
+            TraceEventType::Activation | TraceEventType::Preemption => {
+                self.states.insert(event.pid, TaskState::Ready);
+            },
+            TraceEventType::Dispatch => {
+                self.states.insert(event.pid, TaskState::Running(event.cpu));
+            },
+            TraceEventType::Deactivation | TraceEventType::Exit => {
+                self.states.remove(&event.pid);
+            },
+        }
+
+        self.update_inversions(event.instant)
+    }
+
+    /// Close the inversions still ongoing at `end`, e.g. the end of the trace
+    pub fn finalize(&mut self, end: Time) {
+        for ((victim, usurper), start) in std::mem::take(&mut self.ongoing) {
+            self.close(victim, usurper, start, end);
+        }
+    }
+
+    /// Inversions that ended so far, in order of end
+    pub fn inversions(&self) -> &[InversionEvent] {
+        &self.inversions
+    }
+
+    fn update_inversions(&mut self, now: Time) -> bool {
+        let inverted = self.inverted_pairs();
+        let mut ended = false;
+
+        let ongoing = std::mem::take(&mut self.ongoing);
+        for (pair, start) in ongoing {
+            if inverted.contains(&pair) {
+                self.ongoing.insert(pair, start);
+            } else {
+                ended |= self.close(pair.0, pair.1, start, now);
+            }
+        }
+        for pair in inverted {
+            self.ongoing.entry(pair).or_insert(now);
+        }
+
+        ended
+    }
+
+    /// (victim, usurper) pairs in the current state
+    fn inverted_pairs(&self) -> Vec<(Pid, Pid)> {
+        let prio = |pid: Pid| self.sys_conf.priority_of(pid).unwrap();
+        let mut ret = Vec::new();
+
+        for (victim, _) in self.states.iter().filter(|(_, state)| **state == TaskState::Ready) {
+            for (usurper, state) in self.states.iter() {
+                if let TaskState::Running(cpu) = state {
+                    if prio(*usurper) < prio(*victim) && self.can_run_on(*victim, *usurper, *cpu) {
+                        ret.push((*victim, *usurper));
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Whether `victim` could run on the CPU of `usurper`
+    fn can_run_on(&self, victim: Pid, usurper: Pid, cpu: Option<Cpu>) -> bool {
+        // None if the task can run on any CPU
+        let affinity = |pid: Pid| self.sys_conf.affinity_of(pid).filter(|affinity| !affinity.is_empty());
+
+        match (affinity(victim), cpu) {
+            (None, _) => true,
+            (Some(victim_affinity), Some(cpu)) => victim_affinity.contains(&cpu),
+            (Some(victim_affinity), None) => match affinity(usurper) {
+                Some(usurper_affinity) => victim_affinity.iter().any(|cpu| usurper_affinity.contains(cpu)),
+                None => true,
+            },
+        }
+    }
+
+    fn close(&mut self, victim: Pid, usurper: Pid, start: Time, end: Time) -> bool {
+        let duration = end - start;
+        if duration.is_zero() || duration < self.min_duration {
+            return false;
+        }
+
+        self.inversions.push(InversionEvent { victim, usurper, start, duration });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{sys_conf::{SysConf, ThreadInfo, Pid, Cpu}, time::Time, trace::{Trace, TraceEvent}};
+
+    use crate::inversion::{InversionEvent, PriorityInversionDetector};
+
+    #[test]
+    fn two_tasks() {
+        let ms = Time::from_ms;
+        // 1 has a lower priority than 2, both on CPU 0
+        let trace = Trace::from_unsorted([
+            // 1 keeps running for 3ms after 2 is woken up
+            TraceEvent::activation(1, ms(0.)),
+            TraceEvent::dispatch(1, ms(0.)).with_cpu(0),
+            TraceEvent::activation(2, ms(5.)),
+            TraceEvent::deactivation(1, ms(8.)).with_cpu(0),
+            TraceEvent::dispatch(2, ms(8.)).with_cpu(0),
+            TraceEvent::deactivation(2, ms(10.)).with_cpu(0),
+            // 2 preempts 1 immediately
+            TraceEvent::activation(1, ms(20.)),
+            TraceEvent::dispatch(1, ms(20.)).with_cpu(0),
+            TraceEvent::activation(2, ms(25.)),
+            TraceEvent::preemption(1, ms(25.)).with_cpu(0),
+            TraceEvent::dispatch(2, ms(25.)).with_cpu(0),
+            TraceEvent::deactivation(2, ms(27.)).with_cpu(0),
+            TraceEvent::dispatch(1, ms(27.)).with_cpu(0),
+            TraceEvent::deactivation(1, ms(30.)).with_cpu(0),
+        ]);
+
+        let inversions = PriorityInversionDetector::detect(sys_conf(&[(1, 10, vec![0]), (2, 50, vec![0])]), &trace);
+        assert_eq!(inversions, [InversionEvent { victim: 2, usurper: 1, start: ms(5.), duration: ms(3.) }]);
+
+        // 2 can't run on the CPU of 1
+        assert!(PriorityInversionDetector::detect(sys_conf(&[(1, 10, vec![0]), (2, 50, vec![1])]), &trace).is_empty());
+        // Unknown priorities
+        assert!(PriorityInversionDetector::detect(sys_conf(&[(2, 50, vec![0])]), &trace).is_empty());
+
+        let mut detector = PriorityInversionDetector::new(sys_conf(&[(1, 10, vec![0]), (2, 50, vec![0])])).with_min_duration(ms(5.));
+        for event in trace.events() {
+            assert!(!detector.push_event(*event));
+        }
+        assert!(detector.inversions().is_empty());
+    }
+
+    #[test]
+    fn ongoing_at_the_end() {
+        let ms = Time::from_ms;
+        let trace = Trace::from_unsorted([
+            TraceEvent::activation(1, ms(0.)),
+            TraceEvent::dispatch(1, ms(0.)),
+            TraceEvent::activation(2, ms(1.)),
+            TraceEvent::activation(3, ms(2.)),
+        ]);
+
+        // No CPU in the events, 1 runs on one of the CPUs of its affinity
+        let inversions = PriorityInversionDetector::detect(sys_conf(&[(1, 10, vec![0, 1]), (2, 50, vec![1]), (3, 5, vec![0])]), &trace);
+        assert_eq!(inversions, [InversionEvent { victim: 2, usurper: 1, start: ms(1.), duration: ms(1.) }]);
+
+        // Disjoint affinities
+        assert!(PriorityInversionDetector::detect(sys_conf(&[(1, 10, vec![0]), (2, 50, vec![1])]), &trace).is_empty());
+        // Unknown affinity of the usurper
+        let inversions = PriorityInversionDetector::detect(sys_conf(&[(1, 10, vec![]), (2, 50, vec![1])]), &trace);
+        assert_eq!(inversions, [InversionEvent { victim: 2, usurper: 1, start: ms(1.), duration: ms(1.) }]);
+    }
+
+    fn sys_conf(threads: &[(Pid, u32, Vec<Cpu>)]) -> SysConf {
+        let mut sys_conf = SysConf::default();
+        for (pid, prio, affinity) in threads {
+            sys_conf.rt_threads_info.insert(*pid, ThreadInfo { pid: *pid, prio: *prio, affinity: affinity.clone(), ..Default::default() });
+        }
+
+        sys_conf
+    }
+}
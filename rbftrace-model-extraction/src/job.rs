@@ -1,11 +1,51 @@
 //! This module contains a job extractor. 
 
-use rbftrace_core::{trace::TraceEvent, time::Time, model::Job};
+use std::collections::VecDeque;
+
+use rbftrace_core::{trace::{Trace, TraceEvent}, time::{Time, Cost}, sys_conf::Pid, model::Job};
+
+use crate::TaskModelExtractor;
+
+/// Number of completed jobs kept by each `JobExtractor` for `recent_jobs`
+pub const RECENT_JOBS_CAPACITY: usize = 64;
+
+/// Jobs reconstructed from a trace, without running a task level extractor
+pub trait TraceArrivals {
+    /// `(arrived_at, execution_time)` of the jobs of `pid` completed in the trace, in order of completion,
+    /// e.g. to build its curve with `RbfCurve::from`. The job still running at the end of the trace is left out.
+    fn to_arrivals(&self, pid: Pid) -> Vec<(Time, Cost)>;
+}
+
+impl TraceArrivals for Trace {
+    fn to_arrivals(&self, pid: Pid) -> Vec<(Time, Cost)> {
+        let mut job_detector = JobExtractor::new();
+
+        self.events()
+            .filter(|event| event.pid == pid)
+            .filter_map(|event| job_detector.push_event(event))
+            .map(|job| (job.arrived_at, job.execution_time))
+            .collect()
+    }
+}
 
 pub struct JobExtractor {
     last_event: Option<TraceEvent>,
     last_activation: Option<TraceEvent>,
     preemption_time: Time,
+    /// Relative deadline used to flag late jobs, if any
+    deadline: Option<Time>,
+    /// Completion times of the jobs whose response time exceeded the deadline
+    deadline_misses: Vec<Time>,
+    /// If set, an Activation that follows a Deactivation by at most this amount of time
+    /// resumes the same job after a self-suspension, instead of starting a new job
+    suspension_timeout: Option<Time>,
+    suspension_time: Time,
+    /// Job that completed, unless the next Activation turns out to be a resumption
+    pending_job: Option<Job>,
+    /// Last completed jobs, at most RECENT_JOBS_CAPACITY
+    recent_jobs: VecDeque<Job>,
+    /// Events skipped because they happened before the event they end
+    out_of_order_events: usize,
 }
 
 impl JobExtractor {
@@ -14,36 +54,128 @@ impl JobExtractor {
             last_event: None,
             last_activation: None,
             preemption_time: Time::zero(),
+            deadline: None,
+            deadline_misses: Vec::new(),
+            suspension_timeout: None,
+            suspension_time: Time::zero(),
+            pending_job: None,
+            recent_jobs: VecDeque::with_capacity(RECENT_JOBS_CAPACITY),
+            out_of_order_events: 0,
         }
     }
 
+    /// Builds a `JobExtractor` that records the jobs whose response time
+    /// (`completed_at - arrived_at`) exceeds the relative deadline `d`.
+    pub fn with_deadline(d: Time) -> Self {
+        Self {
+            deadline: Some(d),
+            ..Self::new()
+        }
+    }
+
+    /// Builds a `JobExtractor` for self-suspending tasks: a Deactivation followed by an Activation
+    /// within `timeout` is a self-suspension of the current job, accounted in `Job::suspension_time`.
+    /// As a consequence, a job is only returned once the next Activation (or Exit) shows that it completed.
+    pub fn with_suspension_timeout(timeout: Time) -> Self {
+        Self {
+            suspension_timeout: Some(timeout),
+            ..Self::new()
+        }
+    }
+
+    /// Completion times of the jobs that missed the deadline, in order of completion.
+    /// Always empty if the extractor was not built with `with_deadline`.
+    pub fn deadline_misses(&self) -> &[Time] {
+        &self.deadline_misses
+    }
+
+    /// Number of events skipped by `push_event` because they happened before the event they end
+    pub fn out_of_order_events(&self) -> usize {
+        self.out_of_order_events
+    }
+
+    /// Approximate number of bytes held, including the recent jobs and the deadline misses
+    pub fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_size()
+    }
+
+    /// Approximate number of bytes allocated on the heap for the recent jobs and the deadline misses,
+    /// i.e. `memory_footprint` of an extractor embedded in another struct
+    pub fn heap_size(&self) -> usize {
+        self.recent_jobs.capacity() * std::mem::size_of::<Job>()
+            + self.deadline_misses.capacity() * std::mem::size_of::<Time>()
+    }
+
+    /// The last `n` completed jobs, from the oldest to the most recent.
+    /// At most `RECENT_JOBS_CAPACITY` jobs are kept.
+    pub fn recent_jobs(&self, n: usize) -> Vec<Job> {
+        let skip = self.recent_jobs.len().saturating_sub(n);
+
+        self.recent_jobs.iter().skip(skip).copied().collect()
+    }
+
     /// `push_event` updates the internal state with an event 
     /// and returns `Some(job)` if this event marks the completion of a job.
     /// Returns None if the arrival of the job has been pushed in the `JobExtractor`.
+    /// A Deactivation before the Activation of the job, or a Dispatch before the Preemption it ends,
+    /// is skipped and counted in `out_of_order_events`.
     pub fn push_event(&mut self, event: &TraceEvent) -> Option<Job>{
+        let mut completed_job = None;
+
+        if self.is_out_of_order(event) {
+            log::warn!("Job extractor: event of task {} at {} skipped, it precedes the start of the job or of the preemption", event.pid, event.instant);
+            self.out_of_order_events += 1;
+            return None;
+        }
+
         if event.is_activation() {
+            if let Some(pending_job) = self.pending_job.take() {
+                let gap = event.instant - pending_job.completed_at;
+
+                if gap <= self.suspension_timeout.unwrap() {
+                    // Resume the same job
+                    self.suspension_time += gap;
+                    self.last_event = Some(*event);
+
+                    return None;
+                }
+                completed_job = Some(self.complete(pending_job));
+            }
+
             self.preemption_time = Time::zero();
+            self.suspension_time = Time::zero();
             self.last_activation = Some(*event);
         }
 
         if event.is_deactivation() {
             if let Some(last_activation) = self.last_activation {
-                assert!(last_activation.instant <= event.instant);
                 self.last_event = Some(*event);
 
-                return Some(Job {
-                    execution_time: event.instant - last_activation.instant - self.preemption_time,
+                let job = Job {
+                    execution_time: event.instant - last_activation.instant - self.preemption_time - self.suspension_time,
                     arrived_at: last_activation.instant,
                     completed_at: event.instant,
                     preemption_time: self.preemption_time,
-                });
+                    suspension_time: self.suspension_time,
+                };
+
+                if self.suspension_timeout.is_some() {
+                    self.pending_job = Some(job);
+                    return None;
+                }
+                return Some(self.complete(job));
             } 
         }
 
+        if event.is_exit() {
+            if let Some(pending_job) = self.pending_job.take() {
+                completed_job = Some(self.complete(pending_job));
+            }
+        }
+
         if event.is_dispatch() {
             if let Some(last_event) = self.last_event {
                 if last_event.is_preemption() {
-                    assert!(last_event.instant <= event.instant);
                     self.preemption_time = event.instant - last_event.instant;
                 }
             }
@@ -51,7 +183,29 @@ impl JobExtractor {
         
         self.last_event = Some(*event);
         
-        None
+        completed_job
+    }
+
+    /// Closes the job still in flight at the end of the trace, e.g. if the capture stopped while the task was running,
+    /// as if it completed at `end`. Its execution time only covers the part of the job that was traced.
+    /// A job waiting for the next Activation (see `with_suspension_timeout`) is also considered complete.
+    /// Returns None if no job was open.
+    pub fn finalize_open_jobs(&mut self, end: Time) -> Option<Job> {
+        let last_event = self.last_event?;
+
+        if last_event.is_activation() || last_event.is_dispatch() || last_event.is_preemption() {
+            if last_event.is_preemption() {
+                // The task waited for the CPU until the end of the trace
+                self.push_event(&TraceEvent::dispatch(last_event.pid, end));
+            }
+            if let Some(job) = self.push_event(&TraceEvent::deactivation(last_event.pid, end)) {
+                return Some(job);
+            }
+        }
+
+        let pending_job = self.pending_job.take()?;
+
+        Some(self.complete(pending_job))
     }
 
     /// Indicates if the last events pushed in the extractor marked a job complection.
@@ -59,4 +213,391 @@ impl JobExtractor {
         self.last_event
             .map_or(false, |e| e.is_deactivation())
     }
-}
\ No newline at end of file
+
+    fn is_out_of_order(&self, event: &TraceEvent) -> bool {
+        if event.is_deactivation() {
+            return self.last_activation.is_some_and(|a| event.instant < a.instant);
+        }
+        if event.is_dispatch() {
+            return self.last_event.is_some_and(|e| e.is_preemption() && event.instant < e.instant);
+        }
+
+        false
+    }
+
+    fn complete(&mut self, job: Job) -> Job {
+        if let Some(deadline) = self.deadline {
+            if job.completed_at - job.arrived_at > deadline {
+                self.deadline_misses.push(job.completed_at);
+            }
+        }
+
+        if self.recent_jobs.len() == RECENT_JOBS_CAPACITY {
+            self.recent_jobs.pop_front();
+        }
+        self.recent_jobs.push_back(job);
+
+        job
+    }
+}
+
+#[derive(Default)]
+pub struct JobCollectionParams {
+    /// See `JobExtractor::with_suspension_timeout`
+    pub suspension_timeout: Option<Time>,
+}
+
+/// An extractor whose model is the list of all the jobs reconstructed by a `JobExtractor`, in order of completion,
+/// for users that want to run their own analysis on the jobs rather than fit a model.
+pub struct JobCollector {
+    job_detector: JobExtractor,
+    jobs: Vec<Job>,
+}
+
+impl JobCollector {
+    /// Jobs completed so far
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+}
+
+impl TaskModelExtractor for JobCollector {
+    type Model = Vec<Job>;
+    type Params = JobCollectionParams;
+
+    fn from_params(params: &Self::Params) -> Self {
+        let job_detector = match params.suspension_timeout {
+            Some(timeout) => JobExtractor::with_suspension_timeout(timeout),
+            None => JobExtractor::new(),
+        };
+
+        Self {
+            job_detector,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// At least one job completed
+    fn is_matching(&self) -> bool {
+        !self.jobs.is_empty()
+    }
+
+    /// Returns true if a job completed
+    fn push_event(&mut self, event: TraceEvent) -> bool {
+        match self.job_detector.push_event(&event) {
+            Some(job) => {
+                self.jobs.push(job);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn extract_model(&mut self) -> Option<Self::Model> {
+        Some(self.jobs.clone())
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        match self.job_detector.finalize_open_jobs(end) {
+            Some(job) => {
+                self.jobs.push(job);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// All the jobs are kept
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.job_detector.heap_size()
+            + self.jobs.capacity() * std::mem::size_of::<Job>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{rbf::RbfCurve, sys_conf::SysConf, time::Time, trace::{Trace, TraceBuilder, TraceEvent}};
+
+    use crate::{SystemModelExtractor, TaskModelExtractor};
+    use crate::job::{JobExtractor, JobCollector, JobCollectionParams, TraceArrivals, RECENT_JOBS_CAPACITY};
+    use crate::rbf::{RBFExtractor, RBFExtractionParams};
+
+    #[test]
+    fn trace_arrivals() {
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(2.), 20)
+            .periodic(2, Time::from_ms(3.), Time::from_ms(7.), Time::from_ms(1.), 20)
+            .build();
+
+        let arrivals = trace.to_arrivals(1);
+        let expected: Vec<_> = (0..20).map(|i| (Time::from_ms(1. + 10. * i as f64), Time::from_ms(2.))).collect();
+        assert_eq!(arrivals, expected);
+
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams::default());
+        extractor.push_trace(&trace.filter_pid(1));
+        let extracted = extractor.extract_model().unwrap();
+        let curve = RbfCurve::from(&arrivals);
+        assert_eq!(curve.as_points(), extracted.as_points());
+        assert_eq!(curve.wcet, extracted.wcet);
+
+        assert!(trace.to_arrivals(3).is_empty());
+    }
+
+    #[test]
+    fn deadline_misses() {
+        let trace = Trace::from([
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::dispatch(0, Time::from_ms(5.)),
+            TraceEvent::deactivation(0, Time::from_ms(7.)),
+
+            // Late job
+            TraceEvent::activation(0, Time::from_ms(15.)),
+            TraceEvent::dispatch(0, Time::from_ms(15.)),
+            TraceEvent::preemption(0, Time::from_ms(16.)),
+            TraceEvent::dispatch(0, Time::from_ms(19.)),
+            TraceEvent::deactivation(0, Time::from_ms(20.)),
+
+            TraceEvent::activation(0, Time::from_ms(25.)),
+            TraceEvent::dispatch(0, Time::from_ms(25.)),
+            TraceEvent::deactivation(0, Time::from_ms(29.)),
+        ]);
+
+        let mut extractor = JobExtractor::with_deadline(Time::from_ms(4.));
+        let mut jobs = 0;
+        for event in trace.events() {
+            if extractor.push_event(event).is_some() {
+                jobs += 1;
+            }
+        }
+
+        assert_eq!(jobs, 3);
+        assert_eq!(extractor.deadline_misses(), [Time::from_ms(20.)]);
+    }
+
+    #[test]
+    fn no_deadline_no_misses() {
+        let mut extractor = JobExtractor::new();
+        extractor.push_event(&TraceEvent::activation(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::deactivation(0, Time::from_ms(50.)));
+
+        assert!(extractor.deadline_misses().is_empty());
+    }
+
+    #[test]
+    fn out_of_order_events() {
+        let mut extractor = JobExtractor::new();
+        extractor.push_event(&TraceEvent::activation(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::preemption(0, Time::from_ms(6.)));
+        // Dispatch and deactivation before the preemption and the activation
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(4.)));
+        assert!(extractor.push_event(&TraceEvent::deactivation(0, Time::from_ms(3.))).is_none());
+        assert_eq!(extractor.out_of_order_events(), 2);
+
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(7.)));
+        let job = extractor.push_event(&TraceEvent::deactivation(0, Time::from_ms(8.))).unwrap();
+        assert_eq!(job.execution_time, Time::from_ms(2.));
+        assert_eq!(job.preemption_time, Time::from_ms(1.));
+    }
+
+    #[test]
+    fn finalize_open_jobs() {
+        // The trace ends while the second job is running
+        let trace = Trace::from([
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::dispatch(0, Time::from_ms(5.)),
+            TraceEvent::deactivation(0, Time::from_ms(7.)),
+            TraceEvent::activation(0, Time::from_ms(15.)),
+            TraceEvent::dispatch(0, Time::from_ms(15.)),
+        ]);
+
+        let mut extractor = JobExtractor::new();
+        let jobs: Vec<_> = trace.events().filter_map(|e| extractor.push_event(e)).collect();
+        assert_eq!(jobs.len(), 1);
+
+        let job = extractor.finalize_open_jobs(Time::from_ms(18.)).unwrap();
+        assert_eq!(job.arrived_at, Time::from_ms(15.));
+        assert_eq!(job.completed_at, Time::from_ms(18.));
+        assert_eq!(job.execution_time, Time::from_ms(3.));
+        assert!(extractor.last_event_was_job_completion());
+        assert!(extractor.finalize_open_jobs(Time::from_ms(20.)).is_none());
+
+        // Preempted until the end of the trace
+        let mut extractor = JobExtractor::new();
+        extractor.push_event(&TraceEvent::activation(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::preemption(0, Time::from_ms(6.)));
+        let job = extractor.finalize_open_jobs(Time::from_ms(10.)).unwrap();
+        assert_eq!(job.execution_time, Time::from_ms(1.));
+        assert_eq!(job.preemption_time, Time::from_ms(4.));
+
+        // A self-suspending job only completes at the next activation, or at the end of the trace
+        let mut extractor = JobExtractor::with_suspension_timeout(Time::from_ms(2.));
+        extractor.push_event(&TraceEvent::activation(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(5.)));
+        assert!(extractor.push_event(&TraceEvent::deactivation(0, Time::from_ms(7.))).is_none());
+        assert_eq!(extractor.finalize_open_jobs(Time::from_ms(8.)).unwrap().completed_at, Time::from_ms(7.));
+
+        // Trace ending with a completed job
+        let mut extractor = JobExtractor::new();
+        assert!(extractor.finalize_open_jobs(Time::from_ms(8.)).is_none());
+        extractor.push_event(&TraceEvent::activation(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::dispatch(0, Time::from_ms(5.)));
+        extractor.push_event(&TraceEvent::deactivation(0, Time::from_ms(7.)));
+        assert!(extractor.finalize_open_jobs(Time::from_ms(8.)).is_none());
+
+        // Propagated by the system level extractor
+        let mut extractor = SystemModelExtractor::<JobCollector>::new(JobCollectionParams::default(), SysConf::default());
+        for event in trace.events() {
+            extractor.push_event(*event);
+        }
+        assert!(extractor.finalize_open_jobs(Time::from_ms(18.)));
+        assert_eq!(extractor.extract_model().get_model(0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn preempted_job() {
+        let mut extractor = JobExtractor::with_suspension_timeout(Time::from_ms(2.));
+        let events = [
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::dispatch(0, Time::from_ms(5.)),
+            TraceEvent::preemption(0, Time::from_ms(6.)),
+            TraceEvent::dispatch(0, Time::from_ms(7.)),
+            TraceEvent::deactivation(0, Time::from_ms(9.)),
+            TraceEvent::activation(0, Time::from_ms(15.)),
+        ];
+
+        let jobs: Vec<_> = events.iter().filter_map(|e| extractor.push_event(e)).collect();
+
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].arrived_at, Time::from_ms(5.));
+        assert_eq!(jobs[0].completed_at, Time::from_ms(9.));
+        assert_eq!(jobs[0].execution_time, Time::from_ms(3.));
+        assert_eq!(jobs[0].preemption_time, Time::from_ms(1.));
+        assert_eq!(jobs[0].suspension_time, Time::zero());
+    }
+
+    #[test]
+    fn self_suspending_job() {
+        let mut extractor = JobExtractor::with_suspension_timeout(Time::from_ms(2.));
+        let events = [
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::dispatch(0, Time::from_ms(5.)),
+            TraceEvent::deactivation(0, Time::from_ms(6.)),
+            // Self-suspension
+            TraceEvent::activation(0, Time::from_ms(7.)),
+            TraceEvent::dispatch(0, Time::from_ms(7.)),
+            TraceEvent::deactivation(0, Time::from_ms(9.)),
+            // New job
+            TraceEvent::activation(0, Time::from_ms(15.)),
+            TraceEvent::dispatch(0, Time::from_ms(15.)),
+            TraceEvent::deactivation(0, Time::from_ms(16.)),
+            TraceEvent::exit(0, Time::from_ms(20.)),
+        ];
+
+        let jobs: Vec<_> = events.iter().filter_map(|e| extractor.push_event(e)).collect();
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].arrived_at, Time::from_ms(5.));
+        assert_eq!(jobs[0].completed_at, Time::from_ms(9.));
+        assert_eq!(jobs[0].execution_time, Time::from_ms(3.));
+        assert_eq!(jobs[0].preemption_time, Time::zero());
+        assert_eq!(jobs[0].suspension_time, Time::from_ms(1.));
+        assert_eq!(jobs[1].arrived_at, Time::from_ms(15.));
+        assert_eq!(jobs[1].suspension_time, Time::zero());
+    }
+
+    #[test]
+    fn self_suspension_without_timeout() {
+        // By default, every Deactivation completes a job
+        let mut extractor = JobExtractor::new();
+        let events = [
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::dispatch(0, Time::from_ms(5.)),
+            TraceEvent::deactivation(0, Time::from_ms(6.)),
+            TraceEvent::activation(0, Time::from_ms(7.)),
+            TraceEvent::dispatch(0, Time::from_ms(7.)),
+            TraceEvent::deactivation(0, Time::from_ms(9.)),
+        ];
+
+        let jobs: Vec<_> = events.iter().filter_map(|e| extractor.push_event(e)).collect();
+
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs.iter().all(|job| job.suspension_time.is_zero()));
+    }
+
+    #[test]
+    fn job_collector() {
+        let mut events = vec![];
+        for i in 0..3 {
+            let start = Time::from_ms(10. * i as f64);
+            events.push(TraceEvent::activation(1, start));
+            events.push(TraceEvent::dispatch(1, start));
+            // Preempted by pid 2 for 1ms
+            events.push(TraceEvent::preemption(1, start + Time::from_ms(1.)));
+            events.push(TraceEvent::activation(2, start + Time::from_ms(1.)));
+            events.push(TraceEvent::dispatch(2, start + Time::from_ms(1.)));
+            events.push(TraceEvent::deactivation(2, start + Time::from_ms(2.)));
+            events.push(TraceEvent::dispatch(1, start + Time::from_ms(2.)));
+            events.push(TraceEvent::deactivation(1, start + Time::from_ms(4.)));
+        }
+
+        let model = SystemModelExtractor::<JobCollector>::extract_from_trace(JobCollectionParams::default(), SysConf::default(), Trace::from(events));
+
+        let jobs = model.get_model(1).unwrap();
+        assert_eq!(jobs.len(), 3);
+        for (i, job) in jobs.iter().enumerate() {
+            let start = Time::from_ms(10. * i as f64);
+            assert_eq!(job.arrived_at, start);
+            assert_eq!(job.completed_at, start + Time::from_ms(4.));
+            assert_eq!(job.execution_time, Time::from_ms(3.));
+            assert_eq!(job.preemption_time, Time::from_ms(1.));
+            assert_eq!(job.suspension_time, Time::zero());
+        }
+
+        let jobs = model.get_model(2).unwrap();
+        assert_eq!(jobs.len(), 3);
+        assert!(jobs.iter().all(|job| job.execution_time == Time::from_ms(1.)));
+    }
+
+    #[test]
+    fn recent_jobs() {
+        let mut extractor = JobExtractor::new();
+        for i in 0..5 {
+            let start = Time::from_ms(10. * i as f64);
+            extractor.push_event(&TraceEvent::activation(0, start));
+            extractor.push_event(&TraceEvent::dispatch(0, start));
+            extractor.push_event(&TraceEvent::deactivation(0, start + Time::from_ms(1.)));
+        }
+
+        let arrivals: Vec<_> = extractor.recent_jobs(3).iter().map(|job| job.arrived_at).collect();
+        assert_eq!(arrivals, [Time::from_ms(20.), Time::from_ms(30.), Time::from_ms(40.)]);
+        assert_eq!(extractor.recent_jobs(10).len(), 5);
+    }
+
+    #[test]
+    fn recent_jobs_bounded() {
+        let mut extractor = JobExtractor::new();
+        for i in 0..(RECENT_JOBS_CAPACITY + 10) {
+            let start = Time::from_ms(10. * i as f64);
+            extractor.push_event(&TraceEvent::activation(0, start));
+            extractor.push_event(&TraceEvent::dispatch(0, start));
+            extractor.push_event(&TraceEvent::deactivation(0, start + Time::from_ms(1.)));
+        }
+
+        let jobs = extractor.recent_jobs(usize::MAX);
+        assert_eq!(jobs.len(), RECENT_JOBS_CAPACITY);
+        assert_eq!(jobs[0].arrived_at, Time::from_ms(100.));
+    }
+
+    #[test]
+    fn empty_and_single_event() {
+        let mut collector = JobCollector::from_params(&JobCollectionParams::default());
+        assert!(collector.extract_model().unwrap().is_empty());
+
+        collector.push_event(TraceEvent::activation(1, Time::from_ms(5.)));
+        assert!(collector.extract_model().unwrap().is_empty());
+    }
+}
@@ -34,24 +34,26 @@
 //! let mut extractor: SystemModelExtractor<CompositeModelExtractor>;
 //! extractor = SystemModelExtractor::new(params, sysconf);
 //!  
-//! for event in trace.events() {
-//!     extractor.push_event(*event);
-//! }
+//! extractor.push_trace(&trace);
 //! 
 //! let model = extractor.extract_model();
 //! ``` 
 
 use rbftrace_core::trace::{Trace, TraceEvent};
-use rbftrace_core::model::{SystemModel};
-use rbftrace_core::sys_conf::{SysConf, Pid};
+use rbftrace_core::model::{SystemModel, ModelError};
+use rbftrace_core::sys_conf::{SysConf, Pid, Priority};
+use rbftrace_core::time::Time;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 
 pub mod periodic;
 pub mod spectral;
 pub mod rbf;
 pub mod job;
 pub mod composite;
+pub mod timed;
+pub mod preferring;
+pub mod inversion;
 
 /// This trait defines the behaviour of a task level extractor.
 /// A task level extractor extracts a model from a stream of trace 
@@ -79,6 +81,28 @@ pub trait TaskModelExtractor {
     /// Extract a model based on the current extractor state if matching.
     fn extract_model(&mut self) -> Option<Self::Model>;
 
+    /// Like `extract_model`, but reports malformed input (e.g. non-monotonic arrivals) as an error,
+    /// whereas `extract_model` ignores the malformed input or doesn't match.
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        Ok(self.extract_model())
+    }
+
+    /// Attach the pid and the scheduling priority of the task to the extracted models, for extractors whose models carry them.
+    /// Called by `SystemModelExtractor` when the extractor of a task is created.
+    fn set_task_attributes(&mut self, _pid: Pid, _prio: Priority) {}
+
+    /// Account the job still in flight at the end of the trace as if it completed at `end`, see `JobExtractor::finalize_open_jobs`.
+    /// Returns a boolean value indicating wether the extracted model changes.
+    fn finalize_open_jobs(&mut self, _end: Time) -> bool {
+        false
+    }
+
+    /// Approximate number of bytes held by the extractor, including its buffers (e.g. windows of activations or jobs, curves).
+    /// The default only counts the extractor itself, without what it allocates.
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
     /// Call `push_trace` and check if the model is still matching
     fn match_trace(&mut self, trace: &Trace) -> bool {
         self.push_trace(trace);
@@ -103,6 +127,8 @@ pub struct SystemModelExtractor<T: TaskModelExtractor> {
     params: T::Params,
     sys_conf: SysConf,
     extractors: HashMap<Pid, T>,
+    /// Extractors of the tasks that exited, until their models are taken with `take_exited_models`
+    exited: BTreeMap<Pid, T>,
 }
 
 impl<T: TaskModelExtractor> SystemModelExtractor<T> {
@@ -110,23 +136,110 @@ impl<T: TaskModelExtractor> SystemModelExtractor<T> {
         Self {
             params,
             sys_conf,
-            extractors: HashMap::new()
+            extractors: HashMap::new(),
+            exited: BTreeMap::new(),
         }
     }
 
     /// Push an event to the model extractor associated with the pid of this event's emitter.
+    /// After an Exit, the extractor of the task no longer receives events: a later event with the same pid
+    /// (i.e. a reused pid) starts a new extractor. See `take_exited_models`.
     pub fn push_event(&mut self, event: TraceEvent) -> bool {
         let params = &self.params;
-        self.extractors
+        let sys_conf = &self.sys_conf;
+        let extractor = self.extractors
             .entry(event.pid)
-            .or_insert_with(|| T::from_params(params))
-            .push_event(event)
+            .or_insert_with(|| Self::task_extractor(params, sys_conf, event.pid));
+        let changed = extractor.push_event(event);
+
+        if event.is_exit() {
+            let extractor = self.extractors.remove(&event.pid).unwrap();
+            self.exited.insert(event.pid, extractor);
+        }
+
+        changed
+    }
+
+    /// Push a batch of events in order, see `push_event`.
+    /// Returns true if the model of any task could have changed.
+    pub fn push_events(&mut self, events: impl IntoIterator<Item=TraceEvent>) -> bool {
+        let mut changed = false;
+        for event in events {
+            changed |= self.push_event(event);
+        }
+
+        changed
+    }
+
+    /// Push all the events of a trace, see `push_events`.
+    /// Returns true if the model of any task could have changed.
+    pub fn push_trace(&mut self, trace: &Trace) -> bool {
+        self.push_events(trace.events().copied())
+    }
+
+    /// Final models of the tasks that exited since the last call. Their extractors are dropped, so that
+    /// `extract_model` only extracts the running tasks from then on. Until then, `extract_model` includes them.
+    pub fn take_exited_models(&mut self) -> SystemModel<T::Model> {
+        let mut system_model = SystemModel::new(self.sys_conf.clone());
+
+        for (pid, mut extractor) in std::mem::take(&mut self.exited) {
+            if let Some(task_model) = extractor.extract_model() {
+                system_model.set_task_model(pid, task_model);
+            }
+        }
+
+        system_model
+    }
+
+    fn task_extractor(params: &T::Params, sys_conf: &SysConf, pid: Pid) -> T {
+        let mut extractor = T::from_params(params);
+        let prio = sys_conf.priority_of(pid).unwrap_or(0);
+        extractor.set_task_attributes(pid, prio);
+
+        extractor
+    }
+
+    /// Close the jobs still in flight in all the tasks at the end of the trace, see `TaskModelExtractor::finalize_open_jobs`.
+    /// Returns true if the model of any task changed.
+    pub fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        let mut changed = false;
+        for extractor in self.extractors.values_mut() {
+            changed |= extractor.finalize_open_jobs(end);
+        }
+
+        changed
+    }
+
+    /// Read access to the extractor of a task, None if no event of this pid has been pushed yet.
+    /// Once the task exited, this is its last extractor until the models of the exited tasks are taken.
+    pub fn extractor_for(&self, pid: Pid) -> Option<&T> {
+        self.extractors.get(&pid).or_else(|| self.exited.get(&pid))
+    }
+
+    /// Approximate number of bytes held by the extractors of all the tasks, see `TaskModelExtractor::memory_footprint`
+    pub fn memory_footprint(&self) -> usize {
+        let footprint = |(_, extractor): (&Pid, &T)| std::mem::size_of::<Pid>() + extractor.memory_footprint();
+
+        std::mem::size_of::<Self>()
+            + self.extractors.iter().map(footprint).sum::<usize>()
+            + self.exited.iter().map(footprint).sum::<usize>()
+    }
+
+    /// Pids of the running tasks for which at least one event has been pushed, in no particular order
+    pub fn pids(&self) -> impl Iterator<Item=&Pid> {
+        self.extractors.keys()
     }
 
-    /// Extract a system model from the current extraction state
+    /// Extract a system model from the current extraction state, including the tasks that exited
+    /// and whose models haven't been taken with `take_exited_models`
     pub fn extract_model(&mut self) -> SystemModel<T::Model> {
         let mut system_model = SystemModel::new(self.sys_conf.clone());
 
+        for (pid, extractor) in self.exited.iter_mut() {
+            if let Some(task_model) = extractor.extract_model() {
+                system_model.set_task_model(*pid, task_model);
+            }
+        }
         for (pid, extractor) in self.extractors.iter_mut() {
             if let Some(task_model) = extractor.extract_model() {
                 system_model.set_task_model(*pid, task_model);
@@ -140,11 +253,168 @@ impl<T: TaskModelExtractor> SystemModelExtractor<T> {
     /// Use this method for one shot model extraction.
     pub fn extract_from_trace(params: T::Params, sys_conf: SysConf, trace: Trace) -> SystemModel<T::Model> {
         let mut extractor = Self::new(params, sys_conf);
+        extractor.push_trace(&trace);
 
-        for event in trace.events() {
-            extractor.push_event(*event);
+        extractor.extract_model()
+    }
+
+    /// Same as `extract_from_trace`, but the trace is first split by pid and each task's events are pushed contiguously,
+    /// which is friendlier to the cache on large interleaved traces.
+    /// The events still go through `push_event`, so exits and reused pids are handled in the same way.
+    /// The result is identical as long as the task extractors do not depend on the events of other tasks.
+    pub fn from_trace_grouped(params: T::Params, sys_conf: SysConf, trace: Trace) -> SystemModel<T::Model> {
+        let mut extractor = Self::new(params, sys_conf);
+
+        for task_trace in trace.split_by_pid().values() {
+            extractor.push_trace(task_trace);
         }
 
         extractor.extract_model()
     }
-} 
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{sys_conf::{SysConf, ThreadInfo}, time::Time, trace::{Trace, TraceBuilder, TraceEvent}};
+
+    use crate::{
+        SystemModelExtractor,
+        composite::{CompositeModelExtractor, CompositeExtractionParams},
+        rbf::{RBFExtractor, RBFExtractionParams}};
+
+    #[test]
+    fn extractor_for() {
+        let mut extractor = SystemModelExtractor::<RBFExtractor>::new(RBFExtractionParams::default(), SysConf::default());
+        extractor.push_event(TraceEvent::activation(1, Time::from_ms(1.)));
+        extractor.push_event(TraceEvent::activation(2, Time::from_ms(2.)));
+
+        assert!(extractor.extractor_for(1).is_some());
+        assert!(extractor.extractor_for(2).is_some());
+        assert!(extractor.extractor_for(3).is_none());
+
+        let mut pids: Vec<_> = extractor.pids().copied().collect();
+        pids.sort_unstable();
+        assert_eq!(pids, [1, 2]);
+    }
+
+    #[test]
+    fn exited_tasks() {
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 3)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(10.), Time::from_ms(1.), 5)
+            .build();
+        let mut extractor = SystemModelExtractor::<RBFExtractor>::new(RBFExtractionParams::default(), SysConf::default());
+        for event in trace.events().filter(|e| e.instant < Time::from_ms(30.)) {
+            extractor.push_event(*event);
+        }
+        extractor.push_event(TraceEvent::exit(1, Time::from_ms(30.)));
+        for event in trace.events().filter(|e| e.instant >= Time::from_ms(30.)) {
+            extractor.push_event(*event);
+        }
+
+        // Until taken, the exited task is still extracted
+        assert_eq!(extractor.pids().copied().collect::<Vec<_>>(), [2]);
+        assert_eq!(extractor.extract_model().pids().copied().collect::<Vec<_>>(), [1, 2]);
+        assert!(extractor.extractor_for(1).is_some());
+
+        let exited = extractor.take_exited_models();
+        assert_eq!(exited.pids().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(exited.get_model(1).unwrap().get(Time::from_ms(100.)), Time::from_ms(3.));
+        assert!(extractor.take_exited_models().pids().next().is_none());
+        assert_eq!(extractor.extract_model().pids().copied().collect::<Vec<_>>(), [2]);
+        assert!(extractor.extractor_for(1).is_none());
+
+        // A reused pid starts from scratch
+        extractor.push_trace(&TraceBuilder::new().job(1, Time::from_ms(100.), Time::from_ms(2.)).build());
+        assert_eq!(extractor.extract_model().get_model(1).unwrap().get(Time::from_ms(100.)), Time::from_ms(2.));
+    }
+
+    #[test]
+    fn grouped_matches_interleaved() {
+        let mut sys_conf = SysConf::default();
+        sys_conf.rt_threads_info.insert(2, ThreadInfo { pid: 2, prio: 10, ..Default::default() });
+
+        // Three periodic tasks, interleaved
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(2.), 20)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(15.), Time::from_ms(3.), 20)
+            .periodic(3, Time::from_ms(3.), Time::from_ms(25.), Time::from_ms(1.), 20)
+            .build();
+
+        let params = CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, spectral_enabled: true, ..Default::default() };
+        let interleaved = SystemModelExtractor::<CompositeModelExtractor>::extract_from_trace(params, sys_conf.clone(), trace.clone());
+        let params = CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, spectral_enabled: true, ..Default::default() };
+        let grouped = SystemModelExtractor::<CompositeModelExtractor>::from_trace_grouped(params, sys_conf, trace);
+
+        assert_eq!(grouped.pids().count(), 3);
+        assert_eq!(grouped, interleaved);
+    }
+
+    #[test]
+    fn grouped_reused_pid() {
+        // Pid 1 exits and is reused by a task with a larger cost
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 3)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(10.), Time::from_ms(1.), 12)
+            .job(1, Time::from_ms(100.), Time::from_ms(2.))
+            .build();
+        let trace = Trace::from_unsorted(trace.events().copied().chain([TraceEvent::exit(1, Time::from_ms(30.))]));
+
+        let interleaved = SystemModelExtractor::<RBFExtractor>::extract_from_trace(RBFExtractionParams::default(), SysConf::default(), trace.clone());
+        let grouped = SystemModelExtractor::<RBFExtractor>::from_trace_grouped(RBFExtractionParams::default(), SysConf::default(), trace);
+
+        assert_eq!(grouped, interleaved);
+        assert_eq!(grouped.get_model(1).unwrap().get(Time::from_ms(100.)), Time::from_ms(2.));
+    }
+
+    #[test]
+    fn push_events() {
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(2.), 20)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(15.), Time::from_ms(3.), 20)
+            .build();
+
+        let mut per_event = SystemModelExtractor::<CompositeModelExtractor>::new(CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, ..Default::default() }, SysConf::default());
+        let mut changed = false;
+        for event in trace.events() {
+            changed |= per_event.push_event(*event);
+        }
+        let mut batch = SystemModelExtractor::<CompositeModelExtractor>::new(CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, ..Default::default() }, SysConf::default());
+
+        assert!(changed);
+        assert!(batch.push_trace(&trace));
+        assert_eq!(batch.extract_model(), per_event.extract_model());
+
+        // Activations alone don't complete any job
+        assert!(!batch.push_events([TraceEvent::activation(1, Time::from_s(1.)), TraceEvent::activation(2, Time::from_s(1.))]));
+        assert!(!batch.push_events([]));
+    }
+
+    #[test]
+    fn memory_footprint() {
+        let trace = TraceBuilder::new()
+            .periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(2.), 200)
+            .periodic(2, Time::from_ms(2.), Time::from_ms(15.), Time::from_ms(3.), 200)
+            .build();
+        let params = CompositeExtractionParams {
+            rbf_enabled: true, periodic_enabled: true, spectral_enabled: true,
+            rbf: RBFExtractionParams { window_size: 16, ..Default::default() },
+            ..Default::default()
+        };
+        let mut extractor = SystemModelExtractor::<CompositeModelExtractor>::new(params, SysConf::default());
+        let empty = extractor.memory_footprint();
+
+        let mut footprints = Vec::new();
+        for chunk in trace.events().collect::<Vec<_>>().chunks(60) {
+            extractor.push_events(chunk.iter().copied().copied());
+            footprints.push(extractor.memory_footprint());
+        }
+
+        // The curves grow until their windows are full, the ring buffers are allocated upfront
+        assert!(empty < footprints[0]);
+        assert!(footprints[0] < footprints[2]);
+        assert!(footprints.windows(2).all(|w| w[0] <= w[1]));
+        let plateau = footprints[footprints.len() / 2];
+        assert!(footprints[footprints.len() / 2..].iter().all(|footprint| *footprint == plateau));
+    }
+}
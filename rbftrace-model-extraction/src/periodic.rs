@@ -1,5 +1,7 @@
 //! This modules contains a model extractor for non self-suspending periodic tasks.
 
+use std::collections::VecDeque;
+
 use rbftrace_core::{
     trace::{TraceEvent, TraceEventType}, 
     time::Time, math::Interval, model::PeriodicTask};
@@ -12,17 +14,50 @@ use rbftrace_core::model::Job;
 
 pub struct PeriodicTaskExtractionParams {
     pub resolution: Time,
-    pub j_max: Time
+    pub j_max: Time,
+    /// Jobs with a smaller execution time are considered noise and don't contribute to the WCET
+    pub min_job_cost: Time,
+    /// Smoothing factor of the exponentially weighted moving average of the interarrival times, in (0, 1].
+    /// Higher values follow a drifting period faster, lower values smooth out the jitter.
+    pub ewma_alpha: f64,
+    /// Number of activations remembered, rounded up to a power of two.
+    /// None derives it from the jitter bound and the resolution (`2 * j_max / resolution + 1`).
+    pub window_size: Option<usize>,
+    /// (measured, reference) CPU frequencies in Hz: observed costs are scaled to the reference frequency
+    /// before being used for the WCET, e.g. from `SysConf::frequency_scaling` with the reference `SysConf::ref_cpu_freq_hz`.
+    pub frequency_scaling: Option<(u64, u64)>,
+    /// Factor of `Trace::downsample_arrivals` applied to the trace: the detected period is divided by it
+    pub downsampling: Option<usize>,
 }
 
 impl Default for PeriodicTaskExtractionParams {
     fn default() -> Self {
         Self { resolution: Time::from_ms(0.1), 
-               j_max: Time::from_ms(1.0)
+               j_max: Time::from_ms(1.0),
+               min_job_cost: Time::zero(),
+               ewma_alpha: DEFAULT_EWMA_ALPHA,
+               window_size: None,
+               frequency_scaling: None,
+               downsampling: None,
             }
     }
 }
 
+/// Outcome of the periodic matching so far, see `PeriodicTaskExtractor::status`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchStatus {
+    /// Not enough activations to estimate a period: the number of activations still needed
+    Insufficient(usize),
+    /// The activations can't be explained by a period within the jitter bound
+    NotPeriodic,
+    Matched,
+}
+
+/// Number of activations needed to estimate a period
+const MIN_ACTIVATIONS: usize = 2;
+
+const DEFAULT_EWMA_ALPHA: f64 = 0.1;
+
 pub struct PeriodicTaskExtractor {
     resolution: Time,
     j_max: Time,
@@ -33,9 +68,20 @@ pub struct PeriodicTaskExtractor {
     current_model: Option<PeriodicTask>,
 
     average_gap: Time,
+    /// Exponentially weighted moving average of the interarrival times, unlike `average_gap` it has no hard window
+    ewma_gap: Option<Time>,
+    ewma_alpha: f64,
     wcet: Time,
+    min_job_cost: Time,
+    frequency_scaling: Option<(u64, u64)>,
+    downsampling: u64,
 
     curr_period_range: Option<Interval<Time>>,
+    /// `curr_period_range` after each of the last activations, oldest first, see `with_period_range_history`
+    period_range_history: VecDeque<Interval<Time>>,
+    period_range_history_size: usize,
+    /// Largest jitter of all the models extracted so far, unlike the jitter of the model it survives the window
+    lifetime_jitter: Option<Time>,
     job_detector: JobExtractor,
 
     last_job: Option<Job>,
@@ -53,14 +99,111 @@ impl PeriodicTaskExtractor {
             activation_history,
             still_periodic: false,
             average_gap: Time::zero(),
+            ewma_gap: None,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
             curr_period_range: None, 
+            period_range_history: VecDeque::new(),
+            period_range_history_size: 0,
+            lifetime_jitter: None,
             current_model: None,
             job_detector: JobExtractor::new(),
             wcet: Time::zero(),
+            min_job_cost: Time::zero(),
+            frequency_scaling: None,
+            downsampling: 1,
             last_job: None,
         }
     }
 
+    /// Approximate number of bytes allocated on the heap, see `memory_footprint`
+    pub(crate) fn heap_size(&self) -> usize {
+        self.activation_history.capacity() * std::mem::size_of::<TraceEvent>()
+            + self.period_range_history.capacity() * std::mem::size_of::<Interval<Time>>()
+            + self.job_detector.heap_size()
+    }
+
+    /// Jobs with an execution time smaller than `min_job_cost` are ignored when tracking the WCET.
+    /// Their activations are still used to detect the period.
+    pub fn with_min_job_cost(self, min_job_cost: Time) -> Self {
+        Self { min_job_cost, ..self }
+    }
+
+    /// Remember the last `window_size` activations (rounded up to a power of two) instead of
+    /// the number derived from the jitter bound. Must be called before pushing events.
+    pub fn with_window_size(self, window_size: usize) -> Self {
+        assert!(window_size >= MIN_ACTIVATIONS, "The window must hold at least {} activations", MIN_ACTIVATIONS);
+        let activation_history = AllocRingBuffer::with_capacity(window_size.next_power_of_two());
+
+        Self { activation_history, ..self }
+    }
+
+    /// Scale the observed costs from the `from_hz` CPU frequency to the reference `to_hz`, see `PeriodicTask::normalize_wcet`.
+    /// `min_job_cost` applies to the scaled costs.
+    pub fn with_frequency_scaling(self, from_hz: u64, to_hz: u64) -> Self {
+        assert!(from_hz > 0 && to_hz > 0, "Frequencies must be > 0");
+        Self { frequency_scaling: Some((from_hz, to_hz)), ..self }
+    }
+
+    /// The events come from a trace downsampled by `factor`, see `Trace::downsample_arrivals`.
+    /// The extracted period is divided by `factor` to undo the aliasing, and the offset is taken modulo the corrected period.
+    pub fn with_downsampling(self, factor: usize) -> Self {
+        assert!(factor > 0, "Downsampling factor must be > 0");
+        Self { downsampling: factor as u64, ..self }
+    }
+
+    /// Record the feasible period range after each activation, keeping the last `size` ranges, see `period_range_history`
+    pub fn with_period_range_history(self, size: usize) -> Self {
+        Self { period_range_history: VecDeque::with_capacity(size), period_range_history_size: size, ..self }
+    }
+
+    /// Smoothing factor of the EWMA period estimate, see `ewma_period`
+    pub fn with_ewma_alpha(self, ewma_alpha: f64) -> Self {
+        assert!(ewma_alpha > 0.0 && ewma_alpha <= 1.0, "EWMA alpha must be in (0, 1]");
+        Self { ewma_alpha, ..self }
+    }
+
+    /// Period estimated with an exponentially weighted moving average of the interarrival times.
+    /// Unlike the period of the extracted model, which is based on a fixed window of activations,
+    /// it follows a slowly drifting period smoothly. None until two activations have been seen.
+    pub fn ewma_period(&self) -> Option<Time> {
+        self.ewma_gap
+    }
+
+    /// Jitter of the current model, measured over the activations of the window only:
+    /// a large deviation is forgotten once its activation leaves the window. None if not periodic.
+    pub fn windowed_jitter(&self) -> Option<Time> {
+        self.current_model.map(|model| model.jitter)
+    }
+
+    /// Largest jitter ever measured, including the activations that left the window (see `windowed_jitter`).
+    /// None if the task has never been periodic.
+    pub fn lifetime_jitter(&self) -> Option<Time> {
+        self.lifetime_jitter
+    }
+
+    /// How the feasible period range narrowed over the last activations, from the oldest to the most recent.
+    /// An empty range means that the activations stopped being periodic.
+    /// Empty unless enabled with `with_period_range_history`.
+    pub fn period_range_history(&self) -> Vec<Interval<Time>> {
+        self.period_range_history.iter().copied().collect()
+    }
+
+    /// The last `n` jobs completed by the task, from the oldest to the most recent
+    pub fn recent_jobs(&self, n: usize) -> Vec<Job> {
+        self.job_detector.recent_jobs(n)
+    }
+
+    /// Tells apart a task that is not periodic from one with too few activations, for which `is_matching` is false as well
+    pub fn status(&self) -> MatchStatus {
+        if self.activation_history.len() < MIN_ACTIVATIONS {
+            MatchStatus::Insufficient(MIN_ACTIVATIONS - self.activation_history.len())
+        } else if self.still_periodic {
+            MatchStatus::Matched
+        } else {
+            MatchStatus::NotPeriodic
+        }
+    }
+
     fn update_period_range(&mut self) {
         let event_count = self.activation_history.len() - 1;
 
@@ -82,9 +225,20 @@ impl PeriodicTaskExtractor {
                 |prev| prev.intersection(&obs_period_range));
 
             self.curr_period_range = Some(new_period_range);
+            self.record_period_range(new_period_range);
         }
     }
 
+    fn record_period_range(&mut self, period_range: Interval<Time>) {
+        if self.period_range_history_size == 0 {
+            return
+        }
+        if self.period_range_history.len() == self.period_range_history_size {
+            self.period_range_history.pop_front();
+        }
+        self.period_range_history.push_back(period_range);
+    }
+
     fn push_activation_and_update_average_gap(&mut self, event: TraceEvent) {
         let new_diff = event.instant - self.activation_history.back().unwrap().instant;
         let mut k = self.activation_history.len() - 1;
@@ -109,14 +263,26 @@ impl PeriodicTaskExtractor {
 
         self.activation_history.push(event);
         self.average_gap = new_average_gap;
+        self.update_ewma_gap(new_diff);
+    }
+
+    fn update_ewma_gap(&mut self, new_diff: Time) {
+        let ewma_gap = match self.ewma_gap {
+            None => new_diff,
+            Some(prev) => {
+                let ns = self.ewma_alpha * new_diff.to_ns() as f64 + (1.0 - self.ewma_alpha) * prev.to_ns() as f64;
+                Time::from_ns(ns.round() as u64)
+            },
+        };
+
+        self.ewma_gap = Some(ewma_gap);
     }
 
     fn find_period(&mut self) {
         if let Some(mut model) = self.current_model {
             let mut period = self.average_gap;
             let mut period_found = false;
-            let interval_left = self.curr_period_range.unwrap().get_lower().unwrap();
-            let interval_right = self.curr_period_range.unwrap().get_upper().unwrap();
+            let period_range = self.curr_period_range.unwrap();
 
             let min_magnitude = (self.resolution.to_ns() as f64).log10() as u32;
             let mut magnitude = 10;
@@ -124,7 +290,7 @@ impl PeriodicTaskExtractor {
             while !period_found && magnitude >= min_magnitude {
                 let granularity = Time::from(10_u64.pow(magnitude));
                 period = self.average_gap.round(granularity);
-                if interval_left <= period && period <= interval_right {
+                if period_range.contains(period) {
                     period_found = true;
                 }
 
@@ -135,8 +301,8 @@ impl PeriodicTaskExtractor {
                 model.period = period;
             } else {
                 // No period found in the interval with granularity >= resolution
-                // Pick a period anyway
-                model.period = self.average_gap.round(self.resolution);
+                // Pick a period anyway, as close as possible to the average gap
+                model.period = period_range.clamp(self.average_gap.round(self.resolution)).unwrap();
             }
 
             // In case average_gap < resolution/2
@@ -170,6 +336,7 @@ impl PeriodicTaskExtractor {
 
             model.offset = min_jo.truncate(self.resolution);
             model.jitter = max_jo - model.offset;
+            self.lifetime_jitter = Some(self.lifetime_jitter.map_or(model.jitter, |jitter| jitter.max(model.jitter)));
             
             self.current_model.replace(model);
         }
@@ -177,8 +344,15 @@ impl PeriodicTaskExtractor {
     }
 
     fn update_still_periodic(&mut self) {
+        let was_periodic = self.still_periodic;
+        // Activations closer than the resolution on average (e.g. all at the same instant) can't have a period
         self.still_periodic = self.curr_period_range
-                                  .map_or(false, |i| !i.is_empty());
+                                  .map_or(false, |i| !i.is_empty())
+                              && self.average_gap >= self.resolution;
+        if was_periodic && !self.still_periodic {
+            log::debug!("Periodic extractor: no period within the jitter bound anymore, last activation at {}",
+                        self.activation_history.back().map_or(Time::zero(), |e| e.instant));
+        }
         
        self.current_model = if self.still_periodic {
             Some(PeriodicTask::default())
@@ -211,15 +385,25 @@ impl PeriodicTaskExtractor {
     fn push_deactivation(&mut self, event: TraceEvent) {
         assert!(event.is_deactivation());
         
-        if let Some(job) = &self.last_job {
+        if let Some(job) = self.last_job {
             if job.completed_at == event.instant {
-                self.wcet = self.wcet.max(job.execution_time);
+                self.update_wcet(job);
+            }
+        }
+    }
 
-                if let Some(mut model) = self.current_model {
-                    model.wcet = self.wcet;
-                    
-                    self.current_model.replace(model);
-                }
+    fn update_wcet(&mut self, job: Job) {
+        let cost = match self.frequency_scaling {
+            Some((from_hz, to_hz)) => job.execution_time.scale_frequency(from_hz, to_hz),
+            None => job.execution_time,
+        };
+        if cost >= self.min_job_cost {
+            self.wcet = self.wcet.max(cost);
+
+            if let Some(mut model) = self.current_model {
+                model.wcet = self.wcet;
+                
+                self.current_model.replace(model);
             }
         }
     }
@@ -231,7 +415,24 @@ impl TaskModelExtractor for PeriodicTaskExtractor {
 
     
     fn from_params(params: &Self::Params) -> Self {
-        Self::new(params.j_max, params.resolution)
+        let extractor = Self::new(params.j_max, params.resolution)
+            .with_min_job_cost(params.min_job_cost)
+            .with_ewma_alpha(params.ewma_alpha);
+
+        let extractor = match params.frequency_scaling {
+            Some((from_hz, to_hz)) => extractor.with_frequency_scaling(from_hz, to_hz),
+            None => extractor,
+        };
+
+        let extractor = match params.downsampling {
+            Some(factor) => extractor.with_downsampling(factor),
+            None => extractor,
+        };
+
+        match params.window_size {
+            Some(window_size) => extractor.with_window_size(window_size),
+            None => extractor,
+        }
     }
 
     fn is_matching(&self) -> bool {
@@ -257,16 +458,39 @@ impl TaskModelExtractor for PeriodicTaskExtractor {
 
     // The periodic extractor is completely incremental, so there is no need to manually trigger the extraction
     fn extract_model(&mut self) -> Option<Self::Model> {
-        self.current_model
+        let mut model = self.current_model?;
+        if self.downsampling > 1 {
+            model.period = (model.period / self.downsampling).max(Time::from_ns(1));
+            model.offset %= model.period;
+        }
+
+        Some(model)
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        let maybe_job = self.job_detector.finalize_open_jobs(end);
+
+        if let Some(job) = maybe_job {
+            self.last_job = maybe_job;
+            self.update_wcet(job);
+        }
+
+        maybe_job.is_some()
+    }
+
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_size()
     }
 }
 
 // Reminder: These tests are using a Jmax of 1ms
 #[cfg(test)]
 mod test {
-    use rbftrace_core::{time::Time, trace::{Trace, TraceEvent}, model::PeriodicTask};
+    use rbftrace_core::{time::Time, trace::{Trace, TraceBuilder, TraceEvent}, model::PeriodicTask};
 
-    use crate::periodic::{PeriodicTaskExtractor, TaskModelExtractor};
+    use ringbuffer::{RingBuffer, RingBufferExt};
+
+    use crate::periodic::{PeriodicTaskExtractor, PeriodicTaskExtractionParams, TaskModelExtractor, MatchStatus};
 
     #[test]
     pub fn periodic_fixed_exec_time(){
@@ -529,4 +753,222 @@ mod test {
             extractor.extract_model(), None
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn periodic_downsampled(){
+        let trace = TraceBuilder::new()
+            .periodic(0, Time::from_ms(5.), Time::from_ms(10.), Time::from_ms(2.), 10)
+            .build();
+
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0));
+        extractor.push_trace(&trace);
+        let model = extractor.extract_model().unwrap();
+        assert_eq!(model.period, Time::from_ms(10.));
+
+        // The period is preserved once the aliasing is undone
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0)).with_downsampling(2);
+        extractor.push_trace(&trace.downsample_arrivals(2));
+        assert_eq!(extractor.extract_model(), Some(model));
+
+        // Otherwise the detected period is aliased by the downsampling factor
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0));
+        extractor.push_trace(&trace.downsample_arrivals(2));
+        assert_eq!(extractor.extract_model().unwrap().period, Time::from_ms(20.));
+    }
+
+    #[test]
+    pub fn periodic_concatenated(){
+        let mut segment = vec![];
+        for i in 0..5 {
+            let arrival = Time::from_ms(5. + 10. * i as f64);
+            segment.push(TraceEvent::activation(0, arrival));
+            segment.push(TraceEvent::dispatch(0, arrival));
+            segment.push(TraceEvent::deactivation(0, arrival + Time::from_ms(2.)));
+        }
+        let segment = Trace::from(segment);
+
+        // The second capture starts where the period of the first one would place it
+        let mut trace = segment.clone();
+        trace.concat_with_offset(&segment, Time::from_ms(50.)).unwrap();
+
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0));
+        extractor.push_trace(&trace);
+        let model = extractor.extract_model().unwrap();
+
+        assert_eq!(model.period, Time::from_ms(10.));
+        assert_eq!(extractor.status(), MatchStatus::Matched);
+    }
+
+    #[test]
+    pub fn status_insufficient() {
+        let trace = Trace::from([
+            TraceEvent::activation(0, Time::from_ms(5.)),
+            TraceEvent::deactivation(0, Time::from_ms(7.)),
+        ]);
+
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0));
+        assert_eq!(extractor.status(), MatchStatus::Insufficient(2));
+        extractor.push_trace(&trace);
+
+        assert_eq!(extractor.status(), MatchStatus::Insufficient(1));
+        assert!(!extractor.is_matching());
+        assert_eq!(extractor.extract_model(), None);
+    }
+
+    #[test]
+    pub fn status_not_periodic() {
+        let mut events = vec![];
+        for arrival in [0., 10., 15., 40., 42.].iter() {
+            events.push(TraceEvent::activation(0, Time::from_ms(*arrival)));
+            events.push(TraceEvent::dispatch(0, Time::from_ms(*arrival)));
+            events.push(TraceEvent::deactivation(0, Time::from_ms(*arrival + 1.)));
+        }
+
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(1.0));
+        extractor.push_trace(&Trace::from(events));
+
+        assert_eq!(extractor.status(), MatchStatus::NotPeriodic);
+        assert!(!extractor.is_matching());
+    }
+
+    #[test]
+    pub fn explicit_window_size() {
+        let mut events = vec![];
+        for i in 0..10 {
+            let arrival = Time::from_ms(5. + 10. * i as f64);
+            events.push(TraceEvent::activation(0, arrival));
+            events.push(TraceEvent::dispatch(0, arrival));
+            events.push(TraceEvent::deactivation(0, arrival + Time::from_ms(2.)));
+        }
+
+        // The derived window holds 2 * 1ms / 0.1ms + 1 activations, rounded up to 32
+        let params = PeriodicTaskExtractionParams { window_size: Some(4), ..Default::default() };
+        let mut extractor = PeriodicTaskExtractor::from_params(&params);
+        extractor.push_trace(&Trace::from(events));
+
+        assert_eq!(extractor.activation_history.len(), 4);
+        assert_eq!(extractor.activation_history.front().unwrap().instant, Time::from_ms(65.));
+        assert_eq!(extractor.extract_model().unwrap().period, Time::from_ms(10.));
+    }
+
+    #[test]
+    pub fn frequency_scaling() {
+        let mut events = vec![];
+        for i in 0..10 {
+            let arrival = Time::from_ms(5. + 10. * i as f64);
+            events.push(TraceEvent::activation(0, arrival));
+            events.push(TraceEvent::dispatch(0, arrival));
+            events.push(TraceEvent::deactivation(0, arrival + Time::from_ms(2.)));
+        }
+        let trace = Trace::from(events);
+
+        // Measured at 2GHz, normalized to 1GHz
+        let params = PeriodicTaskExtractionParams { frequency_scaling: Some((2_000_000_000, 1_000_000_000)), ..Default::default() };
+        let mut extractor = PeriodicTaskExtractor::from_params(&params);
+        extractor.push_trace(&trace);
+        let scaled = extractor.extract_model().unwrap();
+
+        let mut extractor = PeriodicTaskExtractor::from_params(&PeriodicTaskExtractionParams::default());
+        extractor.push_trace(&trace);
+        let unscaled = extractor.extract_model().unwrap();
+
+        assert_eq!(scaled.wcet, Time::from_ms(4.));
+        assert_eq!(scaled, unscaled.normalize_wcet(2_000_000_000, 1_000_000_000));
+    }
+
+    #[test]
+    pub fn ewma_follows_drift() {
+        // The period grows by 10us at each activation
+        let mut events = vec![];
+        let mut arrival = Time::zero();
+        let mut gap = Time::from_ms(10.);
+        for _ in 0..200 {
+            events.push(TraceEvent::activation(0, arrival));
+            events.push(TraceEvent::dispatch(0, arrival));
+            events.push(TraceEvent::deactivation(0, arrival + Time::from_ms(1.)));
+            arrival += gap;
+            gap += Time::from_us(10.);
+        }
+        let last_gap = gap - Time::from_us(20.);
+
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(0.1)).with_ewma_alpha(0.5);
+        assert_eq!(extractor.ewma_period(), None);
+        extractor.push_trace(&Trace::from(events));
+
+        let ewma_lag = last_gap - extractor.ewma_period().unwrap();
+        let windowed_lag = last_gap - extractor.average_gap;
+        assert!(ewma_lag <= Time::from_us(10.));
+        assert!(windowed_lag >= Time::from_us(100.));
+    }
+
+    #[test]
+    pub fn lifetime_jitter() {
+        // Period of 10ms, the 6th activation is 0.8ms late
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(0.1)).with_window_size(8);
+        assert_eq!(extractor.lifetime_jitter(), None);
+        let trace = TraceBuilder::new()
+            .periodic(0, Time::zero(), Time::from_ms(10.), Time::from_ms(1.), 5)
+            .job(0, Time::from_ms(50.8), Time::from_ms(1.))
+            .periodic(0, Time::from_ms(60.), Time::from_ms(10.), Time::from_ms(1.), 24)
+            .build();
+        for event in trace.events().filter(|e| e.instant <= Time::from_ms(61.)) {
+            extractor.push_event(*event);
+        }
+        assert_eq!(extractor.windowed_jitter(), Some(Time::from_us(800.)));
+        for event in trace.events().filter(|e| e.instant > Time::from_ms(61.)) {
+            extractor.push_event(*event);
+        }
+
+        // The late activation left the window
+        assert_eq!(extractor.extract_model().unwrap().period, Time::from_ms(10.));
+        assert_eq!(extractor.windowed_jitter(), Some(Time::zero()));
+        assert_eq!(extractor.lifetime_jitter(), Some(Time::from_us(800.)));
+    }
+
+    #[test]
+    pub fn period_range_history() {
+        let trace = TraceBuilder::new().periodic(0, Time::from_ms(5.), Time::from_ms(10.), Time::from_ms(2.), 10).build();
+        let mut extractor = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(0.1)).with_period_range_history(16);
+        let mut bounded = PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(0.1)).with_period_range_history(4);
+        extractor.push_trace(&trace);
+        bounded.push_trace(&trace);
+
+        // One range per activation after the first
+        let history = extractor.period_range_history();
+        assert_eq!(history.len(), 9);
+        for pair in history.windows(2) {
+            assert!(pair[1].get_lower().unwrap() >= pair[0].get_lower().unwrap());
+            assert!(pair[1].get_upper().unwrap() <= pair[0].get_upper().unwrap());
+        }
+        assert!(history.last().unwrap().contains(Time::from_ms(10.)));
+        assert!(history.first().unwrap().get_upper() > history.last().unwrap().get_upper());
+
+        assert_eq!(bounded.period_range_history(), history[5..]);
+        assert!(PeriodicTaskExtractor::new(Time::from_ms(1.0), Time::from_ms(0.1)).period_range_history().is_empty());
+    }
+
+    #[test]
+    pub fn empty_and_single_event() {
+        let mut extractor = PeriodicTaskExtractor::from_params(&PeriodicTaskExtractionParams::default());
+        assert_eq!(extractor.extract_model(), None);
+        assert_eq!(extractor.ewma_period(), None);
+
+        extractor.push_event(TraceEvent::activation(0, Time::from_ms(5.0)));
+        assert_eq!(extractor.extract_model(), None);
+        assert_eq!(extractor.ewma_period(), None);
+    }
+
+    #[test]
+    pub fn same_timestamp_activations() {
+        let mut extractor = PeriodicTaskExtractor::from_params(&PeriodicTaskExtractionParams::default());
+        for _ in 0..10 {
+            extractor.push_event(TraceEvent::activation(0, Time::from_ms(5.0)));
+            extractor.push_event(TraceEvent::dispatch(0, Time::from_ms(5.0)));
+            extractor.push_event(TraceEvent::deactivation(0, Time::from_ms(5.0)));
+        }
+
+        assert!(!extractor.is_matching());
+        assert_eq!(extractor.status(), MatchStatus::NotPeriodic);
+        assert_eq!(extractor.extract_model(), None);
+    }
+}
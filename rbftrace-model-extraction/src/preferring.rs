@@ -0,0 +1,158 @@
+//! This module contains a combinator of two task level extractors, where one model is preferred over the other.
+
+use rbftrace_core::{trace::TraceEvent, model::ModelError, sys_conf::{Pid, Priority}, time::Time};
+
+use crate::TaskModelExtractor;
+
+/// Parameters of both extractors of a `Preferring`
+#[derive(Default)]
+pub struct PreferringParams<PA, PB> {
+    pub preferred: PA,
+    pub fallback: PB,
+}
+
+/// Model extracted by a `Preferring`: the one of the preferred extractor if it matches, the one of the fallback otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preference<MA, MB> {
+    Preferred(MA),
+    Fallback(MB),
+}
+
+impl<MA, MB> Preference<MA, MB> {
+    /// Converts either model to a common type, e.g. with `SystemModel::map`
+    pub fn map_into<U>(self, preferred: impl FnOnce(MA) -> U, fallback: impl FnOnce(MB) -> U) -> U {
+        match self {
+            Preference::Preferred(model) => preferred(model),
+            Preference::Fallback(model) => fallback(model),
+        }
+    }
+
+    pub fn preferred(self) -> Option<MA> {
+        match self {
+            Preference::Preferred(model) => Some(model),
+            Preference::Fallback(_) => None,
+        }
+    }
+
+    pub fn fallback(self) -> Option<MB> {
+        match self {
+            Preference::Preferred(_) => None,
+            Preference::Fallback(model) => Some(model),
+        }
+    }
+}
+
+/// Pushes the events to two extractors, and extracts the model of `A` if it matches, otherwise the one of `B`.
+/// This is the pattern of `CompositeModelExtractor` for any pair of extractors,
+/// e.g. `Preferring<PeriodicTaskExtractor, RBFExtractor>`. Combinators can be nested to prefer more than two models.
+pub struct Preferring<A: TaskModelExtractor, B: TaskModelExtractor> {
+    preferred: A,
+    fallback: B,
+}
+
+impl<A: TaskModelExtractor, B: TaskModelExtractor> Preferring<A, B> {
+    pub fn new(preferred: A, fallback: B) -> Self {
+        Self { preferred, fallback }
+    }
+
+    pub fn preferred(&self) -> &A {
+        &self.preferred
+    }
+
+    pub fn fallback(&self) -> &B {
+        &self.fallback
+    }
+}
+
+impl<A: TaskModelExtractor, B: TaskModelExtractor> TaskModelExtractor for Preferring<A, B> {
+    type Model = Preference<A::Model, B::Model>;
+    type Params = PreferringParams<A::Params, B::Params>;
+
+    fn from_params(params: &Self::Params) -> Self {
+        Self::new(A::from_params(&params.preferred), B::from_params(&params.fallback))
+    }
+
+    fn is_matching(&self) -> bool {
+        self.preferred.is_matching() || self.fallback.is_matching()
+    }
+
+    fn push_event(&mut self, event: TraceEvent) -> bool {
+        let preferred_changed = self.preferred.push_event(event);
+        let fallback_changed = self.fallback.push_event(event);
+
+        preferred_changed || fallback_changed
+    }
+
+    fn extract_model(&mut self) -> Option<Self::Model> {
+        match self.preferred.extract_model() {
+            Some(model) => Some(Preference::Preferred(model)),
+            None => self.fallback.extract_model().map(Preference::Fallback),
+        }
+    }
+
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        match self.preferred.try_extract_model()? {
+            Some(model) => Ok(Some(Preference::Preferred(model))),
+            None => Ok(self.fallback.try_extract_model()?.map(Preference::Fallback)),
+        }
+    }
+
+    fn set_task_attributes(&mut self, pid: Pid, prio: Priority) {
+        self.preferred.set_task_attributes(pid, prio);
+        self.fallback.set_task_attributes(pid, prio);
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        let preferred_changed = self.preferred.finalize_open_jobs(end);
+        let fallback_changed = self.fallback.finalize_open_jobs(end);
+
+        preferred_changed || fallback_changed
+    }
+
+    fn memory_footprint(&self) -> usize {
+        self.preferred.memory_footprint() + self.fallback.memory_footprint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{sys_conf::SysConf, time::Time, trace::{TraceBuilder, TraceEvent}};
+
+    use crate::{
+        SystemModelExtractor,
+        periodic::PeriodicTaskExtractor,
+        rbf::RBFExtractor,
+        preferring::{Preferring, PreferringParams, Preference}};
+
+    #[test]
+    fn periodic_over_rbf() {
+        // Pid 1 is periodic, pid 2 has irregular arrivals
+        let mut builder = TraceBuilder::new().periodic(1, Time::from_ms(1.), Time::from_ms(10.), Time::from_ms(1.), 20);
+        for arrival_ms in [2., 5., 30., 31., 70., 150., 152.] {
+            builder = builder.job(2, Time::from_ms(arrival_ms), Time::from_ms(0.5));
+        }
+        let trace = builder.build();
+
+        let params = PreferringParams::default();
+        let mut extractor = SystemModelExtractor::<Preferring<PeriodicTaskExtractor, RBFExtractor>>::new(params, SysConf::default());
+        for event in trace.events() {
+            extractor.push_event(*event);
+        }
+        extractor.push_event(TraceEvent::activation(2, Time::from_ms(200.)));
+        let model = extractor.extract_model();
+
+        match model.get_model(1).unwrap() {
+            Preference::Preferred(task) => assert_eq!(task.period, Time::from_ms(10.)),
+            other => panic!("Expected a periodic task, got {:?}", other),
+        }
+        match model.get_model(2).unwrap() {
+            Preference::Fallback(rbf) => assert_eq!(rbf.get(Time::from_ms(2.)), Time::from_ms(1.)),
+            other => panic!("Expected an RBF, got {:?}", other),
+        }
+
+        // Back to a single model type, e.g. the periods of the pids that have one
+        let periods = model.map(|m| m.map_into(|task| Some(task.period), |_| None));
+        assert_eq!(periods.pids().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(periods.get_model(1), Some(&Time::from_ms(10.)));
+    }
+}
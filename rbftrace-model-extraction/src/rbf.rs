@@ -1,22 +1,33 @@
 //! This module contains an RBF extractor.
 
-use rbftrace_core::rbf::RbfCurve;
+use std::collections::{BTreeMap, VecDeque};
+
+use rbftrace_core::{rbf::{RbfCurve, Point}, sys_conf::{Pid, Priority}, model::{Job, ModelError}, time::{Time, Cost}};
 
 use crate::{TaskModelExtractor, job::JobExtractor};
 
 pub struct RBFExtractionParams {
-    pub window_size: usize
+    pub window_size: usize,
+    /// Jobs with a smaller execution time are considered noise and are not added to the curve
+    pub min_job_cost: Time,
+    /// Also keep the distribution of the costs, see `RBFExtractor::exceedance_curve`
+    pub probabilistic: bool,
 }
 
 impl Default for RBFExtractionParams {
     fn default() -> Self {
-        Self { window_size: 1000 }
+        Self { window_size: 1000, min_job_cost: Time::zero(), probabilistic: false }
     }
 }
 
 pub struct RBFExtractor {
     job_detector: JobExtractor,
-    rbf: RbfCurve
+    rbf: RbfCurve,
+    min_job_cost: Time,
+    /// First malformed arrival, which was left out of the curve
+    error: Option<ModelError>,
+    /// Only in probabilistic mode
+    histograms: Option<CostHistograms>,
 }
 
 impl TaskModelExtractor for RBFExtractor {
@@ -24,7 +35,7 @@ impl TaskModelExtractor for RBFExtractor {
     type Params = RBFExtractionParams;
 
     fn from_params(params: &Self::Params) -> Self {
-        Self::new(params.window_size)
+        Self::new(params.window_size, params.min_job_cost, params.probabilistic)
     }
 
     fn is_matching(&self) -> bool {
@@ -32,25 +43,383 @@ impl TaskModelExtractor for RBFExtractor {
     }
 
     fn push_event(&mut self, event: rbftrace_core::trace::TraceEvent) -> bool {
-        let maybe_job = self.job_detector.push_event(&event);
+        match self.job_detector.push_event(&event) {
+            Some(job) => self.push_job(job),
+            None => false,
+        }
+    }
 
-        if let Some(job) = &maybe_job {
-            self.rbf.add_arrival(job.arrived_at, job.execution_time);
+    /// Copy of the current curve, see `snapshot`. It lacks the arrivals rejected by `RbfCurve::try_add_arrival`,
+    /// `try_extract_model` reports the first of them as an error instead.
+    fn extract_model(&mut self) -> Option<Self::Model> {
+        Some(self.snapshot())
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        match self.job_detector.finalize_open_jobs(end) {
+            Some(job) => self.push_job(job),
+            None => false,
         }
+    }
 
-        maybe_job.is_some()
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        match &self.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(self.extract_model()),
+        }
     }
 
-    fn extract_model(&mut self) -> Option<Self::Model> {
-        Some(self.rbf.clone())
+    fn set_task_attributes(&mut self, pid: Pid, prio: Priority) {
+        self.set_pid(pid);
+        self.set_priority(prio);
+    }
+
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_size()
     }
 }
 
 impl RBFExtractor {
-    fn new(window_size: usize) -> Self {
+    fn new(window_size: usize, min_job_cost: Time, probabilistic: bool) -> Self {
         Self {
             job_detector: JobExtractor::new(),
-            rbf: RbfCurve::new(0, window_size)
+            rbf: RbfCurve::new(0, window_size),
+            min_job_cost,
+            error: None,
+            histograms: if probabilistic { Some(CostHistograms::new(window_size)) } else { None },
+        }
+    }
+
+    /// Approximate number of bytes allocated on the heap, see `memory_footprint`
+    pub(crate) fn heap_size(&self) -> usize {
+        self.job_detector.heap_size()
+            + self.rbf.heap_size()
+            + self.histograms.as_ref().map_or(0, |histograms| histograms.heap_size())
+    }
+
+    /// Curve that is exceeded with a probability of at most `probability`, e.g. 1e-6.
+    /// For every number n of consecutive jobs, the cost is the `1 - probability` quantile of the
+    /// observed costs of n consecutive jobs, and the delta is the shortest observed span of n arrivals.
+    /// With a probability of 0, the curve upper bounds the deterministic RBF.
+    /// None if the extractor is not in probabilistic mode.
+    pub fn exceedance_curve(&self, probability: f64) -> Option<RbfCurve> {
+        let histograms = self.histograms.as_ref()?;
+        let mut ret = histograms.curve(self.rbf.pid, probability);
+        ret.prio = self.rbf.prio;
+
+        Some(ret)
+    }
+
+    pub fn set_pid(&mut self, pid: Pid) {
+        self.rbf.pid = pid;
+    }
+
+    pub fn set_priority(&mut self, p: Priority) {
+        self.rbf.prio = p;
+    }
+
+    /// Returns false if the arrival of the job was left out because it is malformed
+    fn push_job(&mut self, job: Job) -> bool {
+        if job.execution_time >= self.min_job_cost {
+            if let Err(e) = self.rbf.try_add_arrival(job.arrived_at, job.execution_time) {
+                log::warn!("RBF of pid {}: arrival left out: {:?}", self.rbf.pid, e);
+                self.error.get_or_insert(e);
+                return false;
+            }
+            if let Some(histograms) = &mut self.histograms {
+                histograms.push(job.arrived_at, job.execution_time);
+            }
         }
+
+        true
     }
-}
\ No newline at end of file
+
+    /// The last `n` jobs completed by the task, from the oldest to the most recent
+    pub fn recent_jobs(&self, n: usize) -> Vec<Job> {
+        self.job_detector.recent_jobs(n)
+    }
+
+    /// Long-run utilization implied by the curve, see `RbfCurve::asymptotic_rate`.
+    /// Only meaningful once the window is full.
+    pub fn utilization(&self) -> f64 {
+        self.rbf.asymptotic_rate()
+    }
+
+    /// Copy of the current curve, e.g. to follow its evolution while events are pushed.
+    /// Since the curve keeps the worst case of every window, each snapshot dominates the previous ones.
+    pub fn snapshot(&self) -> RbfCurve {
+        self.rbf.clone()
+    }
+}
+
+/// Distribution of the total cost of n consecutive jobs, for n up to the window size
+struct CostHistograms {
+    window_size: usize,
+    last_arrivals_window: VecDeque<(Time, Cost)>,
+    /// histograms[n-1]: total cost of n consecutive jobs -> number of observations
+    histograms: Vec<BTreeMap<Cost, u64>>,
+    /// min_spans[n-1]: shortest span (as an RBF delta) of n consecutive arrivals
+    min_spans: Vec<Time>,
+}
+
+impl CostHistograms {
+    fn new(window_size: usize) -> Self {
+        Self {
+            window_size,
+            last_arrivals_window: VecDeque::with_capacity(window_size + 1),
+            histograms: Vec::new(),
+            min_spans: Vec::new(),
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        let observations: usize = self.histograms.iter().map(|histogram| histogram.len()).sum();
+
+        self.last_arrivals_window.capacity() * std::mem::size_of::<(Time, Cost)>()
+            + self.histograms.capacity() * std::mem::size_of::<BTreeMap<Cost, u64>>()
+            + observations * std::mem::size_of::<(Cost, u64)>()
+            + self.min_spans.capacity() * std::mem::size_of::<Time>()
+    }
+
+    /// Arrivals must be monotonic, which is checked when adding them to the RBF
+    fn push(&mut self, instant: Time, cost: Cost) {
+        self.last_arrivals_window.push_back((instant, cost));
+
+        let mut total = Time::zero();
+        for (i, (t, c)) in self.last_arrivals_window.iter().rev().enumerate() {
+            total = total.saturating_add(*c);
+            let span = instant - *t + Time::from_ns(1);
+
+            if i == self.histograms.len() {
+                self.histograms.push(BTreeMap::new());
+                self.min_spans.push(span);
+            } else {
+                self.min_spans[i] = self.min_spans[i].min(span);
+            }
+            *self.histograms[i].entry(total).or_insert(0) += 1;
+        }
+
+        // Same window as RbfCurve::add_arrival
+        if self.last_arrivals_window.len() > self.window_size {
+            self.last_arrivals_window.pop_front();
+        }
+    }
+
+    /// Smallest cost exceeded by at most a fraction `probability` of the observations
+    fn quantile(histogram: &BTreeMap<Cost, u64>, probability: f64) -> Cost {
+        let allowed = probability * histogram.values().sum::<u64>() as f64;
+        let mut exceeding = 0;
+
+        for (cost, count) in histogram.iter().rev() {
+            if (exceeding + count) as f64 > allowed {
+                return *cost;
+            }
+            exceeding += count;
+        }
+
+        Time::zero()
+    }
+
+    fn curve(&self, pid: Pid, probability: f64) -> RbfCurve {
+        let mut points: Vec<Point> = Vec::new();
+        let mut last = Point::new(Time::zero(), Time::zero());
+
+        // The spans are non-decreasing with n, the costs are made strictly increasing
+        for (histogram, span) in self.histograms.iter().zip(&self.min_spans) {
+            let cost = Self::quantile(histogram, probability);
+            if cost <= last.cost {
+                continue;
+            }
+            if *span == last.delta {
+                points.pop();
+            }
+            last = Point::new(*span, cost);
+            points.push(last);
+        }
+
+        RbfCurve::from_points(pid, self.window_size, &points).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rbftrace_core::{
+        model::ModelError,
+        sys_conf::{SysConf, ThreadInfo},
+        time::Time,
+        trace::{Trace, TraceEvent}};
+
+    use crate::{SystemModelExtractor, TaskModelExtractor, rbf::{RBFExtractor, RBFExtractionParams}};
+
+    #[test]
+    fn priority_from_sys_conf() {
+        let mut sys_conf = SysConf::default();
+        sys_conf.rt_threads_info.insert(1, ThreadInfo { pid: 1, prio: 42, ..Default::default() });
+
+        let trace = Trace::from([
+            TraceEvent::activation(1, Time::from_ms(5.)),
+            TraceEvent::dispatch(1, Time::from_ms(5.)),
+            TraceEvent::deactivation(1, Time::from_ms(7.)),
+            TraceEvent::activation(2, Time::from_ms(6.)),
+            TraceEvent::dispatch(2, Time::from_ms(7.)),
+            TraceEvent::deactivation(2, Time::from_ms(8.)),
+        ]);
+
+        let model = SystemModelExtractor::<RBFExtractor>::extract_from_trace(RBFExtractionParams::default(), sys_conf, trace);
+
+        let rbf_1 = model.get_model(1).unwrap();
+        assert_eq!(rbf_1.pid, 1);
+        assert_eq!(rbf_1.prio, 42);
+
+        // Not in the system configuration
+        let rbf_2 = model.get_model(2).unwrap();
+        assert_eq!(rbf_2.pid, 2);
+        assert_eq!(rbf_2.prio, 0);
+    }
+
+    #[test]
+    fn noise_jobs_filtered() {
+        let clean = Trace::from([
+            TraceEvent::activation(1, Time::from_ms(5.)),
+            TraceEvent::dispatch(1, Time::from_ms(5.)),
+            TraceEvent::deactivation(1, Time::from_ms(7.)),
+            TraceEvent::activation(1, Time::from_ms(15.)),
+            TraceEvent::dispatch(1, Time::from_ms(15.)),
+            TraceEvent::deactivation(1, Time::from_ms(17.)),
+        ]);
+        let noisy = Trace::from([
+            TraceEvent::activation(1, Time::from_ms(5.)),
+            TraceEvent::dispatch(1, Time::from_ms(5.)),
+            TraceEvent::deactivation(1, Time::from_ms(7.)),
+            // Noise
+            TraceEvent::activation(1, Time::from_ms(10.)),
+            TraceEvent::dispatch(1, Time::from_ms(10.)),
+            TraceEvent::deactivation(1, Time::from_ms(10.01)),
+            TraceEvent::activation(1, Time::from_ms(15.)),
+            TraceEvent::dispatch(1, Time::from_ms(15.)),
+            TraceEvent::deactivation(1, Time::from_ms(17.)),
+        ]);
+        let params = RBFExtractionParams { min_job_cost: Time::from_ms(0.1), ..Default::default() };
+
+        let mut extractor = RBFExtractor::from_params(&params);
+        extractor.push_trace(&clean);
+        let expected = extractor.extract_model().unwrap();
+
+        let mut extractor = RBFExtractor::from_params(&params);
+        extractor.push_trace(&noisy);
+        let filtered = extractor.extract_model().unwrap();
+
+        assert!(filtered.approx_eq(&expected, Time::zero()));
+        assert_eq!(filtered.wcet, Time::from_ms(2.));
+
+        // By default nothing is filtered
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams::default());
+        extractor.push_trace(&noisy);
+        assert!(!extractor.extract_model().unwrap().approx_eq(&expected, Time::zero()));
+    }
+
+    #[test]
+    fn non_monotonic_arrivals() {
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams::default());
+        let events = [
+            TraceEvent::activation(1, Time::from_ms(10.)),
+            TraceEvent::dispatch(1, Time::from_ms(10.)),
+            TraceEvent::deactivation(1, Time::from_ms(12.)),
+            // Arrives before the previous job
+            TraceEvent::activation(1, Time::from_ms(5.)),
+            TraceEvent::dispatch(1, Time::from_ms(5.)),
+            TraceEvent::deactivation(1, Time::from_ms(6.)),
+        ];
+        for event in events.iter() {
+            extractor.push_event(*event);
+        }
+
+        assert_eq!(extractor.try_extract_model(), Err(ModelError::NonMonotonicArrival{
+            prev: Time::from_ms(10.),
+            instant: Time::from_ms(5.),
+        }));
+        // The malformed arrival is left out
+        assert_eq!(extractor.extract_model().unwrap().wcet, Time::from_ms(2.));
+    }
+
+    #[test]
+    fn exceedance_curve() {
+        let params = RBFExtractionParams { window_size: 10, probabilistic: true, ..Default::default() };
+        let mut extractor = RBFExtractor::from_params(&params);
+        for i in 0..20000 {
+            let arrival = Time::from_ms(10. * i as f64);
+            // A single outlier, which appears in at most window_size + 1 windows out of ~20000
+            let cost = if i == 10000 { Time::from_ms(5.) } else { Time::from_ms(1.) };
+            extractor.push_event(TraceEvent::activation(1, arrival));
+            extractor.push_event(TraceEvent::dispatch(1, arrival));
+            extractor.push_event(TraceEvent::deactivation(1, arrival + cost));
+        }
+
+        let deterministic = extractor.extract_model().unwrap();
+        let probabilistic = extractor.exceedance_curve(1e-3).unwrap();
+        let one_job = Time::from_ns(1);
+        let two_jobs = Time::from_ms(10.) + Time::from_ns(1);
+
+        assert_eq!(deterministic.get(one_job), Time::from_ms(5.));
+        assert_eq!(deterministic.get(two_jobs), Time::from_ms(6.));
+        // The outlier is too rare to appear in the exceedance curve
+        assert_eq!(probabilistic.get(one_job), Time::from_ms(1.));
+        assert_eq!(probabilistic.get(two_jobs), Time::from_ms(2.));
+        assert!(probabilistic.as_points().iter().all(|p| p.cost <= deterministic.get(p.delta)));
+
+        // Without exceedances, the curve bounds the deterministic one
+        let worst_case = extractor.exceedance_curve(0.).unwrap();
+        assert!(deterministic.as_points().iter().all(|p| p.cost <= worst_case.get(p.delta)));
+    }
+
+    #[test]
+    fn snapshots_grow() {
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams { window_size: 4, ..Default::default() });
+        let mut snapshots = vec![extractor.snapshot()];
+        for (i, cost_ms) in [1., 3., 1., 2., 1., 4., 1.].iter().enumerate() {
+            let arrival = Time::from_ms(10. * i as f64);
+            extractor.push_event(TraceEvent::activation(1, arrival));
+            extractor.push_event(TraceEvent::dispatch(1, arrival));
+            extractor.push_event(TraceEvent::deactivation(1, arrival + Time::from_ms(*cost_ms)));
+            snapshots.push(extractor.snapshot());
+        }
+
+        for pair in snapshots.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            assert!(prev.as_points().iter().all(|p| p.cost <= next.get(p.delta)));
+        }
+        assert_ne!(snapshots.first(), snapshots.last());
+    }
+
+    #[test]
+    fn utilization() {
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams { window_size: 4, ..Default::default() });
+        for i in 0..8 {
+            let arrival = Time::from_ms(10. * i as f64);
+            extractor.push_event(TraceEvent::activation(1, arrival));
+            extractor.push_event(TraceEvent::dispatch(1, arrival));
+            extractor.push_event(TraceEvent::deactivation(1, arrival + Time::from_ms(2.)));
+        }
+
+        assert!((extractor.utilization() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn deterministic_by_default() {
+        let extractor = RBFExtractor::from_params(&RBFExtractionParams::default());
+
+        assert!(extractor.exceedance_curve(1e-3).is_none());
+    }
+
+    #[test]
+    fn empty_and_single_event() {
+        let mut extractor = RBFExtractor::from_params(&RBFExtractionParams::default());
+        let empty = extractor.extract_model().unwrap();
+        assert_eq!(empty.as_points().len(), 1);
+        assert_eq!(extractor.utilization(), 0.);
+
+        extractor.push_event(TraceEvent::activation(1, Time::from_ms(5.)));
+        assert_eq!(extractor.extract_model().unwrap(), empty);
+        assert_eq!(extractor.utilization(), 0.);
+    }
+}
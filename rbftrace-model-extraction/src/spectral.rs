@@ -5,7 +5,7 @@ use rbftrace_core::{
     time::Time, time::Period, model::PeriodicSelfSuspendingTask
 };
 use crate::{TaskModelExtractor, job::{JobExtractor}};
-use rbftrace_core::model::Job;
+use rbftrace_core::model::{Job, ModelError};
 
 use ringbuffer::{RingBuffer, AllocRingBuffer, RingBufferWrite, RingBufferExt};
 use realfft::RealFftPlanner;
@@ -20,6 +20,14 @@ pub struct SpectralExtractionParams {
     pub max_signal_len: usize,
     pub window_size: usize,
     pub fft_filter_cutoff: f32,
+    /// When the signal would be longer than `max_signal_len`, lower the sampling rate so that the
+    /// signal spans the whole job history, instead of truncating its tail.
+    pub resample_whole_trace: bool,
+    /// Spikes whose normalized power doesn't exceed the one of the adjacent frequency bins by this margin are discarded
+    pub min_spike_prominence: f32,
+    /// Minimum power of the strongest frequency, relative to the power of a pure cosine of the same length (between 0 and 1).
+    /// Below this, the trace is considered not periodic.
+    pub min_periodicity_power: f32,
 }
 
 impl Default for SpectralExtractionParams {
@@ -28,6 +36,72 @@ impl Default for SpectralExtractionParams {
             max_signal_len: 1_000_000,
             window_size: 1000,
             fft_filter_cutoff: 0.5,
+            resample_whole_trace: false,
+            min_spike_prominence: 0.0,
+            min_periodicity_power: 0.0,
+        }
+    }
+}
+
+/// Self-suspension pattern of the observed jobs. Unlike the model, it is populated even when
+/// the number of execution segments varies between jobs, i.e. for a non-segmented model.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SuspensionStats {
+    /// Number of execution segments -> number of jobs with that many segments
+    pub segment_counts: BTreeMap<usize, u64>,
+    /// suspensions[i]: suspensions following the (i+1)-th execution segment, over the jobs that have one
+    pub suspensions: Vec<SegmentStats>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentStats {
+    pub count: u64,
+    pub total: Time,
+    pub max: Time,
+}
+
+impl SegmentStats {
+    pub fn mean(&self) -> Time {
+        if self.count == 0 {
+            return Time::zero();
+        }
+
+        self.total / self.count
+    }
+
+    fn push(&mut self, duration: Time) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+impl SuspensionStats {
+    /// Number of jobs accounted
+    pub fn jobs(&self) -> u64 {
+        self.segment_counts.values().sum()
+    }
+
+    pub fn max_segments(&self) -> usize {
+        self.segment_counts.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn mean_segments(&self) -> f64 {
+        let jobs = self.jobs();
+        if jobs == 0 {
+            return 0.0;
+        }
+
+        self.segment_counts.iter().map(|(segments, n)| (*segments as u64 * n) as f64).sum::<f64>() / jobs as f64
+    }
+
+    fn push(&mut self, job: &SelfSuspendingJob) {
+        *self.segment_counts.entry(job.executions.len()).or_insert(0) += 1;
+        if self.suspensions.len() < job.suspensions.len() {
+            self.suspensions.resize_with(job.suspensions.len(), Default::default);
+        }
+        for (i, susp) in job.suspensions.iter().enumerate() {
+            self.suspensions[i].push(*susp);
         }
     }
 }
@@ -35,15 +109,22 @@ impl Default for SpectralExtractionParams {
 pub struct SpectralExtractor {
     max_signal_len: usize,
     fft_filter_cutoff: f32,
+    resample_whole_trace: bool,
+    min_spike_prominence: f32,
+    min_periodicity_power: f32,
 
     job_history: AllocRingBuffer<Job>,
     still_periodic: bool,
     job_detector: JobExtractor,
 
     current_model: Option<PeriodicSelfSuspendingTask>,
+    suspension_stats: SuspensionStats,
 
     min_gap: Time, // Used for sampling frequency
     wcet: Time,
+
+    /// First malformed job, which was left out of the job history
+    error: Option<ModelError>,
 }
 
 impl SpectralExtractor {
@@ -54,16 +135,61 @@ impl SpectralExtractor {
         Self {
             max_signal_len: if max_signal_len > 0 { max_signal_len.next_power_of_two() } else { 0 },
             fft_filter_cutoff,
+            resample_whole_trace: false,
+            min_spike_prominence: 0.0,
+            min_periodicity_power: 0.0,
             job_history,
             still_periodic: false,
             job_detector: JobExtractor::new(),
             current_model: None,
+            suspension_stats: SuspensionStats::default(),
             min_gap: Time::zero(),
             wcet: Time::zero(),
+            error: None,
         }
     }
 
-    fn extract(&mut self) {
+    /// Approximate number of bytes allocated on the heap, see `memory_footprint`.
+    /// The signal and the spectrum only live during the extraction, and are not counted
+    pub(crate) fn heap_size(&self) -> usize {
+        let model = self.current_model.as_ref().map_or(0, |model| (model.wcet.capacity() + model.ss.capacity()) * std::mem::size_of::<Time>());
+        let stats = self.suspension_stats.segment_counts.len() * std::mem::size_of::<(usize, u64)>()
+                  + self.suspension_stats.suspensions.capacity() * std::mem::size_of::<SegmentStats>();
+
+        self.job_history.capacity() * std::mem::size_of::<Job>()
+            + self.job_detector.heap_size()
+            + model
+            + stats
+    }
+
+    /// See `SpectralExtractionParams::resample_whole_trace`
+    pub fn with_whole_trace_resampling(self, resample_whole_trace: bool) -> Self {
+        Self { resample_whole_trace, ..self }
+    }
+
+    /// See `SpectralExtractionParams::min_spike_prominence`
+    pub fn with_min_spike_prominence(self, min_spike_prominence: f32) -> Self {
+        Self { min_spike_prominence, ..self }
+    }
+
+    /// See `SpectralExtractionParams::min_periodicity_power`
+    pub fn with_min_periodicity_power(self, min_periodicity_power: f32) -> Self {
+        Self { min_periodicity_power, ..self }
+    }
+
+    /// Suspension pattern of the jobs of the last extraction, see `SuspensionStats`.
+    /// Empty if no period was found.
+    pub fn suspension_stats(&self) -> &SuspensionStats {
+        &self.suspension_stats
+    }
+
+    /// The last `n` jobs completed by the task, from the oldest to the most recent
+    pub fn recent_jobs(&self, n: usize) -> Vec<Job> {
+        self.job_detector.recent_jobs(n)
+    }
+
+    /// On error, the task is considered not matching
+    fn extract(&mut self) -> Result<(), ModelError> {
         if self.job_history.len() > 1 {
             // Extract period
             let period = self.fft();
@@ -71,17 +197,31 @@ impl SpectralExtractor {
             if period == Time::zero() {
                 self.still_periodic = false;
                 self.current_model = None;
-                return;
+                self.suspension_stats = SuspensionStats::default();
+                return Ok(());
             }
-            self.still_periodic = true;
 
             // Extract self-suspensions and execution times based on the period
-            self.current_model = Some(self.detect_suspensions(period));
+            match self.detect_suspensions(period) {
+                Ok(model) => {
+                    self.still_periodic = true;
+                    self.current_model = Some(model);
+                },
+                Err(e) => {
+                    self.still_periodic = false;
+                    self.current_model = None;
+                    self.suspension_stats = SuspensionStats::default();
+                    return Err(e);
+                },
+            }
         }
+
+        Ok(())
     }
 
-    fn detect_suspensions(&mut self, period: Period) -> PeriodicSelfSuspendingTask {
+    fn detect_suspensions(&mut self, period: Period) -> Result<PeriodicSelfSuspendingTask, ModelError> {
         let mut model = PeriodicSelfSuspendingTask::default();
+        let mut stats = SuspensionStats::default();
         let mut curr_job_ss = SelfSuspendingJob::default();
         let mut prev_job = &Job::default();
         let mut n_exec_segments = 0;
@@ -90,20 +230,25 @@ impl SpectralExtractor {
         model.segmented = true;
         model.period = period;
 
-        for job in self.job_history.iter() {
-            assert!(job.arrived_at > prev_job.completed_at);
+        for (i, job) in self.job_history.iter().enumerate() {
+            if i > 0 && job.arrived_at <= prev_job.completed_at {
+                return Err(ModelError::OverlappingJobs{completed_at: prev_job.completed_at, arrived_at: job.arrived_at});
+            }
             if job.arrived_at >= next_arrival_ts {
 
                 // Finalize previous self-suspending job
                 if next_arrival_ts > first_arrival_ts {
                     // Check for segmented model (i.e. n_exec_segments is always the same for each job)
-                    assert!(curr_job_ss.suspensions.len() == curr_job_ss.executions.len()-1);
+                    if curr_job_ss.executions.is_empty() || curr_job_ss.suspensions.len() + 1 != curr_job_ss.executions.len() {
+                        return Err(curr_job_ss.inconsistent_segments());
+                    }
                     if n_exec_segments > 0 && curr_job_ss.executions.len() != n_exec_segments {
                         model.segmented = false;
                         model.wcet.clear();
                         model.ss.clear();
                     }
                     n_exec_segments = curr_job_ss.executions.len();
+                    stats.push(&curr_job_ss);
 
                     // Account worst case execution and suspension time for each segment
                     if model.segmented {
@@ -111,7 +256,9 @@ impl SpectralExtractor {
                             model.wcet.resize_with(n_exec_segments, Default::default);
                             model.ss.resize_with(n_exec_segments-1, Default::default);
                         }
-                        assert!(curr_job_ss.executions.len() == model.wcet.len() && curr_job_ss.suspensions.len() == model.ss.len());
+                        if curr_job_ss.executions.len() != model.wcet.len() || curr_job_ss.suspensions.len() != model.ss.len() {
+                            return Err(curr_job_ss.inconsistent_segments());
+                        }
                         for (i, exec) in curr_job_ss.executions.iter().enumerate() {
                             model.wcet[i] = model.wcet[i].max(*exec);
                         }
@@ -144,15 +291,21 @@ impl SpectralExtractor {
 
             prev_job = job;
         }
+        self.suspension_stats = stats;
 
-        model
+        Ok(model)
     }
 
     fn fft(&mut self) -> Period {
+        if self.min_gap == Time::zero() {
+            return Time::zero(); // Coincident arrivals, there is no resolution at which the signal is well defined
+        }
+
         /* Pick the resolution (i.e. sampling frequency) for the signal based
            on the minimum observed interarrival time */
         let mut closest_lower_mag = (self.min_gap.to_ns() as f32).log10().floor() as u32;
-        closest_lower_mag -= 1; // Need enough samples when two arrivals have the MIT
+        // Need enough samples when two arrivals have the MIT (gaps below 10ns use the finest resolution)
+        closest_lower_mag = closest_lower_mag.saturating_sub(1);
         let mut resolution = Time::from(10u64.pow(closest_lower_mag));
         if resolution > Time::from_s(1.0) {
             resolution = Time::from_s(1.0); // Max resolution
@@ -165,7 +318,14 @@ impl SpectralExtractor {
         let trace_delta_ns = self.job_history.back().unwrap().arrived_at - first_arr;
         let mut signal_len = ((trace_delta_ns.to_ns()/resolution.to_ns())+1) as usize;
         if self.max_signal_len > 0 && signal_len > self.max_signal_len {
-            signal_len = self.max_signal_len; // The signal must not be too big to process
+            if self.resample_whole_trace {
+                // Decimate: the coarsest resolution needed to fit the whole span in the signal
+                let max_delta_ns = (self.max_signal_len - 1) as u64;
+                resolution = Time::from_ns(trace_delta_ns.to_ns().div_ceil(max_delta_ns));
+                signal_len = ((trace_delta_ns.to_ns()/resolution.to_ns())+1) as usize;
+            } else {
+                signal_len = self.max_signal_len; // The signal must not be too big to process
+            }
         }
 
         /* Build signal by truncating to desired resolution */
@@ -195,8 +355,8 @@ impl SpectralExtractor {
         // serde_yaml::to_writer(file1, &signal).unwrap();
 
         // FFT
-        let sampling_freq = (1f64/resolution.to_s()).round() as u32;
-        assert!(sampling_freq >= 1);
+        // Resampled signals have an arbitrary resolution, so the frequency is not rounded
+        let sampling_freq = 1f64/resolution.to_s();
         let mut real_planner = RealFftPlanner::<f32>::new();
         let r2c = real_planner.plan_fft_forward(signal_len);
         let mut spectrum = r2c.make_output_vec();
@@ -215,25 +375,33 @@ impl SpectralExtractor {
             fft_result.push((freq_bin, power_norm));
             max_power = max_power.max(power_norm);
         }
+        // A pure cosine concentrates the whole signal in one bin, of magnitude signal_len/2
+        let periodicity_power = max_power / (signal_len as f32 / 2.0).powi(2);
+        if periodicity_power < self.min_periodicity_power {
+            return Time::zero();
+        }
         // Normalize powers between 0 and 1
         for i in 0..fft_result.len() {
             fft_result[i].1 = fft_result[i].1/max_power as f32;
         }
         // Find spikes
-        let mut curr_local_spike: (f32, f32) = (0.0, 0.0); // Frequency => Power
+        let mut curr_local_spike: Option<usize> = None; // Index of the top of the spike
         for i in 0..fft_result.len() {
             if fft_result[i].1 >= self.fft_filter_cutoff {
-                if fft_result[i].1 >= curr_local_spike.1 { // The same spike could be composed by multiple samples. Consider only the top.
-                    curr_local_spike.0 = fft_result[i].0;
-                    curr_local_spike.1 = fft_result[i].1;
+                // The same spike could be composed by multiple samples. Consider only the top.
+                if curr_local_spike.is_none_or(|top| fft_result[i].1 >= fft_result[top].1) {
+                    curr_local_spike = Some(i);
+                }
+            } else if let Some(top) = curr_local_spike.take() {
+                if Self::spike_prominence(&fft_result, top) >= self.min_spike_prominence {
+                    spikes.push(Self::bin_period(resolution, signal_len, top));
                 }
-            } else if curr_local_spike != (0.0, 0.0) {
-                spikes.push(Time::from_s((1f32/curr_local_spike.0) as f64));
-                curr_local_spike = (0.0, 0.0);
             }
         }
-        if curr_local_spike != (0.0, 0.0) { // Edge case
-            spikes.push(Time::from_s((1f32/curr_local_spike.0) as f64));
+        if let Some(top) = curr_local_spike { // Edge case
+            if Self::spike_prominence(&fft_result, top) >= self.min_spike_prominence {
+                spikes.push(Self::bin_period(resolution, signal_len, top));
+            }
         }
 
         // TODO Debug: dump transform
@@ -270,9 +438,28 @@ impl SpectralExtractor {
         // spikes[0]
     }
 
+    /// Period of the frequency of `fft_result[idx]`, i.e. the inverse of `(idx + 1) / (signal_len * resolution)`, without float error
+    fn bin_period(resolution: Time, signal_len: usize, idx: usize) -> Period {
+        resolution.mul_ratio(signal_len as u64, idx as u64 + 1)
+    }
+
+    /// Power of the bin `idx` above the highest of its adjacent bins
+    fn spike_prominence(fft_result: &[(f32, f32)], idx: usize) -> f32 {
+        let left = if idx > 0 { fft_result[idx-1].1 } else { 0.0 };
+        let right = fft_result.get(idx+1).map_or(0.0, |bin| bin.1);
+
+        fft_result[idx].1 - left.max(right)
+    }
+
     fn push_job(&mut self, job: Job) {
         if !self.job_history.is_empty() {
-            let last_gap = job.arrived_at - self.job_history.get(-1).unwrap().arrived_at;
+            let last_arrival = self.job_history.get(-1).unwrap().arrived_at;
+            if job.arrived_at < last_arrival {
+                log::warn!("Spectral extractor: job arriving at {} before the previous one ({}) left out", job.arrived_at, last_arrival);
+                self.error.get_or_insert(ModelError::NonMonotonicArrival{prev: last_arrival, instant: job.arrived_at});
+                return;
+            }
+            let last_gap = job.arrived_at - last_arrival;
             if self.min_gap > Time::zero() {
                 self.min_gap = self.min_gap.min(last_gap);
             } else {
@@ -289,6 +476,9 @@ impl TaskModelExtractor for SpectralExtractor {
 
     fn from_params(params: &Self::Params) -> Self {
         Self::new(params.max_signal_len, params.window_size, params.fft_filter_cutoff)
+            .with_whole_trace_resampling(params.resample_whole_trace)
+            .with_min_spike_prominence(params.min_spike_prominence)
+            .with_min_periodicity_power(params.min_periodicity_power)
     }
 
     fn is_matching(&self) -> bool {
@@ -306,11 +496,36 @@ impl TaskModelExtractor for SpectralExtractor {
         maybe_job.is_some()
     }
 
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        let maybe_job = self.job_detector.finalize_open_jobs(end);
+
+        if let Some(job) = maybe_job {
+            self.push_job(job)
+        }
+
+        maybe_job.is_some()
+    }
+
+    /// The signal and the spectrum only live during the extraction, and are not counted
+    fn memory_footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.heap_size()
+    }
+
     /// Triggers the model extraction and returns the model.
+    /// Jobs arriving before the previous one are left out, and overlapping jobs make the task not matching.
     fn extract_model(&mut self) -> Option<Self::Model> {
-        self.extract();
+        self.extract().ok()?;
         self.current_model.clone()
     }
+
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        self.extract()?;
+
+        Ok(self.current_model.clone())
+    }
 }
 
 /// Only used when extracting self-suspensions
@@ -325,9 +540,15 @@ struct SelfSuspendingJob {
     pub suspensions: Vec<Time> // m-1
 }
 
+impl SelfSuspendingJob {
+    fn inconsistent_segments(&self) -> ModelError {
+        ModelError::InconsistentSegments{arrived_at: self.arrived_at, executions: self.executions.len(), suspensions: self.suspensions.len()}
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use rbftrace_core::{time::Time, trace::{Trace, TraceEvent}, model::PeriodicSelfSuspendingTask};
+    use rbftrace_core::{time::Time, trace::{Trace, TraceBuilder, TraceEvent}, model::{PeriodicSelfSuspendingTask, ModelError}};
     use crate::spectral::{SpectralExtractor, TaskModelExtractor};
 
     const MAX_SIGNAL_LEN: usize = 1_000_000;
@@ -667,8 +888,17 @@ mod test {
             segmented: true,
         };
 
-        assert_eq!(model.unwrap(), expected_model);
+        let model = model.unwrap();
+        assert_eq!(model, expected_model);
         assert!(extractor.is_matching()); 
+
+        assert!(model.is_consistent());
+        assert_eq!(model.computation_segments(), 3);
+        assert_eq!(model.suspension_segments(), 2);
+        assert_eq!(model.segment_wcet(2), Some(Time::from_ms(0.1)));
+        assert_eq!(model.segment_wcet(3), None);
+        assert_eq!(model.segment_ss(1), Some(Time::from_ms(0.9)));
+        assert_eq!(model.segment_ss(2), None);
     }
 
     // Shorter trace causes aliasing
@@ -886,4 +1116,175 @@ mod test {
         assert_eq!(model.unwrap(), expected_model);
         assert!(extractor.is_matching());
     }
+
+    #[test]
+    fn period_change_resampled() {
+        // The period changes from 10ms to 25ms after 1s
+        let mut trace = Trace::new();
+        let mut arrivals: Vec<Time> = (0..100).map(|i| Time::from_ms(10. * i as f64)).collect();
+        arrivals.extend((0..100).map(|i| Time::from_ms(1000. + 25. * i as f64)));
+        for arrival in arrivals {
+            trace.push(TraceEvent::activation(0, arrival)).unwrap();
+            trace.push(TraceEvent::dispatch(0, arrival)).unwrap();
+            trace.push(TraceEvent::deactivation(0, arrival + Time::from_ms(1.0))).unwrap();
+        }
+
+        // Sampled every 1ms, the signal is cropped to the first 1024ms
+        let mut extractor = SpectralExtractor::new(1024, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        extractor.push_trace(&trace);
+        let truncated = extractor.extract_model();
+        assert_eq!(truncated.unwrap().period, Time::from_ms(10.));
+
+        let mut extractor = SpectralExtractor::new(1024, WINDOW_SIZE, FFT_FILTER_CUTOFF).with_whole_trace_resampling(true);
+        extractor.push_trace(&trace);
+        let resampled = extractor.extract_model();
+        assert_ne!(resampled.map(|m| m.period), Some(Time::from_ms(10.)));
+    }
+
+    #[test]
+    fn overlapping_jobs() {
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        for i in 0..10 {
+            let arrival = Time::from_ms(10. * i as f64);
+            // The third job completes after the next arrival
+            let completion = arrival + if i == 2 { Time::from_ms(11.) } else { Time::from_ms(1.) };
+            extractor.push_event(TraceEvent::activation(0, arrival));
+            extractor.push_event(TraceEvent::dispatch(0, arrival));
+            extractor.push_event(TraceEvent::deactivation(0, completion));
+        }
+
+        assert_eq!(extractor.try_extract_model(), Err(ModelError::OverlappingJobs{
+            completed_at: Time::from_ms(31.),
+            arrived_at: Time::from_ms(30.),
+        }));
+        assert_eq!(extractor.extract_model(), None);
+        assert!(!extractor.is_matching());
+    }
+
+    #[test]
+    fn non_monotonic_arrivals() {
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        for arrival in [10., 20., 15.].iter() {
+            extractor.push_event(TraceEvent::activation(0, Time::from_ms(*arrival)));
+            extractor.push_event(TraceEvent::dispatch(0, Time::from_ms(*arrival)));
+            extractor.push_event(TraceEvent::deactivation(0, Time::from_ms(*arrival + 1.)));
+        }
+
+        assert_eq!(extractor.try_extract_model(), Err(ModelError::NonMonotonicArrival{
+            prev: Time::from_ms(20.),
+            instant: Time::from_ms(15.),
+        }));
+    }
+
+    #[test]
+    fn empty_and_single_event() {
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        assert_eq!(extractor.try_extract_model(), Ok(None));
+
+        extractor.push_event(TraceEvent::activation(0, Time::from_ms(5.0)));
+        assert_eq!(extractor.try_extract_model(), Ok(None));
+    }
+
+    #[test]
+    fn nanosecond_gaps() {
+        // Interarrival times below 10ns, i.e. below the finest resolution of the signal
+        let mut trace = Trace::new();
+        for i in 0..3 {
+            let arrival = Time::from_ns(5 * i);
+            trace.push(TraceEvent::activation(0, arrival)).unwrap();
+            trace.push(TraceEvent::dispatch(0, arrival)).unwrap();
+            trace.push(TraceEvent::deactivation(0, arrival + Time::from_ns(1))).unwrap();
+        }
+
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        extractor.push_trace(&trace);
+        assert!(extractor.try_extract_model().is_ok());
+
+        // Zero-length jobs arriving at the same instant
+        let instant = Time::from_ns(20);
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        extractor.push_trace(&trace);
+        for _ in 0..3 {
+            extractor.push_event(TraceEvent::activation(0, instant));
+            extractor.push_event(TraceEvent::dispatch(0, instant));
+            extractor.push_event(TraceEvent::deactivation(0, instant));
+        }
+        assert_eq!(extractor.extract_model(), None);
+    }
+
+    #[test]
+    fn suspension_stats_variable_segments() {
+        // One job every 10s with 1, 2 or 3 execution segments, separated by suspensions of 100ms, then 50ms
+        let mut trace = Trace::new();
+        for i in 0..30 {
+            let mut arrival = Time::from_s(5. + 10. * i as f64);
+            let segments = [1, 2, 1, 3][i % 4];
+            for (segment, suspension) in [Time::from_ms(100.), Time::from_ms(50.), Time::zero()].iter().enumerate().take(segments) {
+                trace.push(TraceEvent::activation(0, arrival)).unwrap();
+                trace.push(TraceEvent::dispatch(0, arrival)).unwrap();
+                let completion = arrival + Time::from_ms(1.);
+                trace.push(TraceEvent::deactivation(0, completion)).unwrap();
+                if segment + 1 < segments {
+                    arrival = completion + *suspension;
+                }
+            }
+        }
+
+        let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF);
+        extractor.push_trace(&trace);
+        let model = extractor.extract_model().unwrap();
+        assert_eq!(model.period, Time::from_s(10.));
+        assert!(!model.segmented);
+
+        // The last job is not complete until the next activation
+        let stats = extractor.suspension_stats();
+        assert_eq!(stats.jobs(), 29);
+        assert_eq!(stats.segment_counts.iter().map(|(s, n)| (*s, *n)).collect::<Vec<_>>(), [(1, 15), (2, 7), (3, 7)]);
+        assert_eq!(stats.max_segments(), 3);
+        assert!((stats.mean_segments() - (15. + 2. * 7. + 3. * 7.) / 29.).abs() < 1e-9);
+        assert_eq!(stats.suspensions.len(), 2);
+        assert_eq!(stats.suspensions[0].count, 14);
+        assert_eq!(stats.suspensions[0].max, Time::from_ms(100.));
+        assert_eq!(stats.suspensions[0].mean(), Time::from_ms(100.));
+        assert_eq!(stats.suspensions[1].count, 7);
+        assert_eq!(stats.suspensions[1].mean(), Time::from_ms(50.));
+    }
+
+    #[test]
+    fn spike_thresholds() {
+        let periodic_trace = |jitter: &dyn Fn(usize) -> f64| {
+            (0..100)
+                .fold(TraceBuilder::new(), |builder, i| builder.job(0, Time::from_ms(5. + 10. * i as f64 + jitter(i)), Time::from_ms(1.)))
+                .build()
+        };
+        let clean = periodic_trace(&|_| 0.);
+        // Up to 2ms of pseudo-random jitter on a 10ms period
+        let noisy = periodic_trace(&|i| ((i * 7919 + 13) % 61) as f64 / 15. - 2.);
+        // The period grows by 5us at each job
+        let drift = periodic_trace(&|i| (i * i) as f64 / 400.);
+
+        let extract = |trace: &Trace, min_spike_prominence: f32, min_periodicity_power: f32| {
+            let mut extractor = SpectralExtractor::new(MAX_SIGNAL_LEN, WINDOW_SIZE, FFT_FILTER_CUTOFF)
+                .with_min_spike_prominence(min_spike_prominence)
+                .with_min_periodicity_power(min_periodicity_power);
+            extractor.push_trace(trace);
+            extractor.extract_model().map(|model| model.period)
+        };
+
+        // Both a jittery trace and a trace whose period drifts from 10ms to 10.5ms match by default
+        let period = Some(Time::from_ms(10.));
+        assert_eq!(extract(&clean, 0.0, 0.0), period);
+        assert_eq!(extract(&noisy, 0.0, 0.0), period);
+        assert_eq!(extract(&drift, 0.0, 0.0), period);
+
+        // The drift spreads the spike over several frequencies
+        assert_eq!(extract(&clean, 0.3, 0.0), period);
+        assert_eq!(extract(&noisy, 0.3, 0.0), period);
+        assert_eq!(extract(&drift, 0.3, 0.0), None);
+
+        // Both spread the power of the signal out of the spike
+        assert_eq!(extract(&clean, 0.0, 0.8), period);
+        assert_eq!(extract(&noisy, 0.0, 0.8), None);
+        assert_eq!(extract(&drift, 0.0, 0.8), None);
+    }
 }
@@ -0,0 +1,135 @@
+//! This module contains an adapter measuring the time spent in a task level extractor.
+
+use std::time::{Duration, Instant};
+
+use rbftrace_core::{trace::TraceEvent, model::ModelError, sys_conf::{Pid, Priority}, time::Time};
+
+use crate::TaskModelExtractor;
+
+/// Cumulative wall-clock time spent in an extractor
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractionTiming {
+    pub pushed_events: u64,
+    pub push_time: Duration,
+    pub extractions: u64,
+    pub extraction_time: Duration,
+}
+
+impl ExtractionTiming {
+    pub fn total_time(&self) -> Duration {
+        self.push_time + self.extraction_time
+    }
+}
+
+/// Wraps a task level extractor and measures the time spent in `push_event` and in the extraction,
+/// e.g. `SystemModelExtractor<Timed<CompositeModelExtractor>>`.
+/// Extractors that are not wrapped pay nothing.
+pub struct Timed<T: TaskModelExtractor> {
+    inner: T,
+    timing: ExtractionTiming,
+}
+
+impl<T: TaskModelExtractor> Timed<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            timing: ExtractionTiming::default(),
+        }
+    }
+
+    pub fn timing(&self) -> &ExtractionTiming {
+        &self.timing
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: TaskModelExtractor> TaskModelExtractor for Timed<T> {
+    type Model = T::Model;
+    type Params = T::Params;
+
+    fn from_params(params: &Self::Params) -> Self {
+        Self::new(T::from_params(params))
+    }
+
+    fn is_matching(&self) -> bool {
+        self.inner.is_matching()
+    }
+
+    fn push_event(&mut self, event: TraceEvent) -> bool {
+        let start = Instant::now();
+        let ret = self.inner.push_event(event);
+        self.timing.push_time += start.elapsed();
+        self.timing.pushed_events += 1;
+
+        ret
+    }
+
+    fn extract_model(&mut self) -> Option<Self::Model> {
+        let start = Instant::now();
+        let ret = self.inner.extract_model();
+        self.timing.extraction_time += start.elapsed();
+        self.timing.extractions += 1;
+
+        ret
+    }
+
+    fn try_extract_model(&mut self) -> Result<Option<Self::Model>, ModelError> {
+        let start = Instant::now();
+        let ret = self.inner.try_extract_model();
+        self.timing.extraction_time += start.elapsed();
+        self.timing.extractions += 1;
+
+        ret
+    }
+
+    fn set_task_attributes(&mut self, pid: Pid, prio: Priority) {
+        self.inner.set_task_attributes(pid, prio);
+    }
+
+    fn finalize_open_jobs(&mut self, end: Time) -> bool {
+        self.inner.finalize_open_jobs(end)
+    }
+
+    fn memory_footprint(&self) -> usize {
+        self.inner.memory_footprint() + std::mem::size_of::<ExtractionTiming>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rbftrace_core::{sys_conf::SysConf, time::Time, trace::TraceBuilder};
+
+    use crate::{
+        SystemModelExtractor,
+        composite::{CompositeModelExtractor, CompositeExtractionParams},
+        timed::Timed};
+
+    #[test]
+    fn timer_accumulates() {
+        let params = CompositeExtractionParams { rbf_enabled: true, periodic_enabled: true, spectral_enabled: true, ..Default::default() };
+        let mut extractor = SystemModelExtractor::<Timed<CompositeModelExtractor>>::new(params, SysConf::default());
+        extractor.push_trace(&TraceBuilder::new().periodic(1, Time::zero(), Time::from_ms(10.), Time::from_ms(2.), 50).build());
+
+        let timing = *extractor.extractor_for(1).unwrap().timing();
+        assert_eq!(timing.pushed_events, 150);
+        assert!(timing.push_time > Duration::ZERO);
+        assert_eq!(timing.extractions, 0);
+
+        let model = extractor.extract_model();
+        assert!(model.get_model(1).unwrap().periodic.is_some());
+
+        let timing = *extractor.extractor_for(1).unwrap().timing();
+        assert_eq!(timing.extractions, 1);
+        assert!(timing.extraction_time > Duration::ZERO);
+        assert_eq!(timing.total_time(), timing.push_time + timing.extraction_time);
+    }
+}
@@ -1,12 +1,19 @@
+#[cfg(feature = "live-tracing")]
 extern crate bindgen;
 
-use std::env;
-use std::process::Command;
-use std::path::PathBuf;
+#[cfg(feature = "live-tracing")]
+use std::{env, process::Command, path::PathBuf};
 
+#[cfg(feature = "live-tracing")]
 static LIB_DIR: &str = "vendor";
+#[cfg(feature = "live-tracing")]
 static LIB_OUTPUT_DIR: &str = "vendor/output/usr/lib64";
 
+// Without live tracing there is nothing to build
+#[cfg(not(feature = "live-tracing"))]
+fn main() {}
+
+#[cfg(feature = "live-tracing")]
 fn main() {
     // Tell Cargo that if the given file changes, to rerun this build script.
     println!("cargo:rerun-if-changed={}/libtraceevent", LIB_DIR);
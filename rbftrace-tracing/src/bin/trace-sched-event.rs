@@ -8,14 +8,18 @@ use std::io::Write;
 
 use rbftrace_core::time::*;
 use rbftrace_core::trace::{
+    Trace,
     TraceEvent,
 };
 use rbftrace_tracing::ftrace::FTraceEVG;
-use rbftrace_config_detection::system::get_pids_with_policy;
+use rbftrace_tracing::events::EventSet;
+use rbftrace_config_detection::system::{get_pids_with_policy, get_cgroup_pids};
 use rbftrace_core::sys_conf::{SchedPolicy, Pid};
+use rbftrace_core::util::log_level;
 
 fn main() {
     let args = Opt::from_args();
+    env_logger::Builder::new().filter_level(log_level(args.verbose, args.quiet)).init();
     let traced_pids: Vec<Pid>;
     let target_pids: Vec<Pid>;
     let mut output: Vec<TraceEvent> = Vec::new();
@@ -23,6 +27,8 @@ fn main() {
     /* Parsing */
     if let Some(pids) = args.pids {
         traced_pids = pids;
+    } else if let Some(ref cgroup) = args.cgroup {
+        traced_pids = get_cgroup_pids(cgroup).expect("Can't read the cgroup.");
     } else {
         traced_pids = get_pids_with_policy(vec!(SchedPolicy::FIFO, SchedPolicy::RR), false);
     }
@@ -47,17 +53,19 @@ fn main() {
     }
 
     /* Tracing */
-    let mut evg = FTraceEVG::new(&target_pids, &traced_pids, Time::from_s(args.ftrace_len).to_ns(), args.ftrace_bufsize);
+    let mut evg = FTraceEVG::new(&target_pids, &traced_pids, Time::from_s(args.ftrace_len).to_ns(), args.ftrace_bufsize, EventSet::default());
     
     evg.setup();
+    let clock = evg.clock();
 
-    println!("Traced pids: {:#?}", traced_pids);
+    log::info!("Traced pids: {:?}", traced_pids);
     if let Some(ref target_pids) = args.target_pids {
-        println!("Target pids: {:#?}", target_pids);
+        log::info!("Target pids: {:?}", target_pids);
     }
     
     while let Some(event) = evg.next_event() {
-        if outputfile.is_none() {
+        // Anonymized events are only written at the end, once every pid has been seen
+        if outputfile.is_none() && !args.anonymize {
             let serialized = serde_yaml::to_string(&event).expect("Can't serialize.");
             print!("{}", serialized);
         } else {
@@ -65,9 +73,23 @@ fn main() {
         }
     }
 
+    let mut trace = Trace::from(output);
+    if args.anonymize {
+        trace.remap_pids();
+    }
+
     if let Some(ref mut file) = outputfile {
-        let serialized = serde_yaml::to_string(&output).expect("Can't serialize.");
+        // Written with its clock, so that traces recorded with different clocks are not mixed up
+        if let Some(clock) = clock {
+            trace = trace.with_clock(clock);
+        }
+        let serialized = serde_yaml::to_string(&trace).expect("Can't serialize.");
         write!(file, "{}", serialized).expect("I/O error.");
+    } else {
+        for event in trace.events() {
+            let serialized = serde_yaml::to_string(event).expect("Can't serialize.");
+            print!("{}", serialized);
+        }
     }
 }
 
@@ -77,6 +99,10 @@ pub struct Opt {
     #[structopt(short = "p", long)]
     pub pids: Option<Vec<Pid>>,
 
+    /// Trace the threads of the cgroup at the specified path (e.g. /sys/fs/cgroup/mygroup), as listed when tracing starts.
+    #[structopt(long, parse(from_os_str), conflicts_with("pids"))]
+    pub cgroup: Option<PathBuf>,
+
     /// Trace until the specified pids are dead. By default, tracing is done until all traced pids are dead, unless -l is specified.
     #[structopt(short = "t", long)]
     pub target_pids: Option<Vec<Pid>>,
@@ -92,4 +118,16 @@ pub struct Opt {
     /// Output file, stdout if not present.
     #[structopt(short = "o", long, parse(from_os_str))]
     pub output: Option<PathBuf>,
+
+    /// Relabel the pids to 1..=n, in order of first appearance, so that the output doesn't contain the real pids.
+    #[structopt(long)]
+    pub anonymize: bool,
+
+    /// Show more diagnostics (repeatable: -v info, -vv debug, -vvv trace). Warnings are shown by default.
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    pub verbose: u64,
+
+    /// Only show errors.
+    #[structopt(short = "q", long, conflicts_with("verbose"))]
+    pub quiet: bool,
 }
@@ -0,0 +1,252 @@
+//! Selection of the ftrace tracepoints enabled while tracing, and parsing of the raw events they produce.
+
+use rbftrace_core::sys_conf::{Pid, Cpu};
+use rbftrace_core::time::Time;
+use rbftrace_core::trace::*;
+
+/* Tracepoints of the "sched" subsystem understood by the event parser */
+pub const SCHED_WAKEUP: &str = "sched_wakeup";
+pub const SCHED_WAKEUP_NEW: &str = "sched_wakeup_new";
+pub const SCHED_SWITCH: &str = "sched_switch";
+pub const SCHED_PROCESS_EXIT: &str = "sched_process_exit";
+pub const SCHED_MIGRATE_TASK: &str = "sched_migrate_task";
+
+/// The set of tracepoints to enable.
+/// By default, every tracepoint needed to reconstruct jobs is enabled (wakeups, switches and exits).
+///
+/// The raw records read during live tracing carry no CPU, so live traces have no per-CPU data.
+/// It only comes from the text output of `trace-cmd report`, see `Trace::from_ftrace_text`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EventSet {
+    pub wakeup: bool,
+    pub wakeup_new: bool,
+    pub switch: bool,
+    pub process_exit: bool,
+    /// Has no effect on live traces, the raw records do not carry the destination CPU
+    pub migrate_task: bool,
+}
+
+impl Default for EventSet {
+    fn default() -> Self {
+        EventSet {
+            wakeup: true,
+            wakeup_new: true,
+            switch: true,
+            process_exit: true,
+            migrate_task: false,
+        }
+    }
+}
+
+impl EventSet {
+    /// Only arrivals (wakeups) are traced
+    pub fn arrivals_only() -> Self {
+        EventSet {
+            wakeup: true,
+            wakeup_new: true,
+            switch: false,
+            process_exit: false,
+            migrate_task: false,
+        }
+    }
+
+    /// Every tracepoint that live tracing can use is traced.
+    /// sched_migrate_task is left out, since the raw records do not carry the destination CPU.
+    pub fn all() -> Self {
+        EventSet {
+            migrate_task: false,
+            ..Default::default()
+        }
+    }
+
+    /// Names of the tracepoints to enable, in the order they should be enabled
+    pub fn tracepoints(&self) -> Vec<&'static str> {
+        let mut ret = Vec::new();
+
+        if self.wakeup {
+            ret.push(SCHED_WAKEUP);
+        }
+        if self.wakeup_new {
+            ret.push(SCHED_WAKEUP_NEW);
+        }
+        if self.switch {
+            ret.push(SCHED_SWITCH);
+        }
+        if self.process_exit {
+            ret.push(SCHED_PROCESS_EXIT);
+        }
+        if self.migrate_task {
+            ret.push(SCHED_MIGRATE_TASK);
+        }
+
+        ret
+    }
+
+    pub fn contains(&self, tracepoint: &str) -> bool {
+        self.tracepoints().contains(&tracepoint)
+    }
+}
+
+/* PARSING */
+
+/// The fields of a raw ftrace event needed to produce a TraceEvent
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RawEvent {
+    pub id: u16,
+    pub ts: u64,
+    /// CPU whose ring buffer recorded the event, if known
+    pub cpu: Option<Cpu>,
+    pub pid: Pid,
+    /// sched_switch only
+    pub next_pid: Pid,
+    /// sched_switch only
+    pub prev_state: i64,
+    /// sched_migrate_task only, if known
+    pub dest_cpu: Option<Cpu>,
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum TraceEventTypeRaw {
+    Switch,
+    Wakeup,
+    Exit,
+    Migrate,
+}
+
+/* These ids are machine-dependent, so they are read from tracefs. */
+/* Only the ids of the enabled tracepoints are read, the others are None. */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EventsId {
+    pub sched_switch_id: Option<u16>,
+    pub sched_wakeup_id: Option<u16>,
+    pub sched_wakeup_new_id: Option<u16>,
+    pub sched_process_exit_id: Option<u16>,
+    pub sched_migrate_task_id: Option<u16>,
+}
+
+/// Turns raw events into TraceEvents, see `SchedEventMapper`
+pub struct EventParser {
+    ids: EventsId,
+    mapper: SchedEventMapper,
+}
+
+impl EventParser {
+    pub fn new(ids: EventsId) -> Self {
+        EventParser {
+            ids,
+            mapper: SchedEventMapper::new(),
+        }
+    }
+
+    /// A context switch produces two events: a Preemption or Deactivation for the
+    /// previous pid, and a Dispatch for the next pid.
+    /// A migration produces no event, it only updates the CPU of the migrated pid.
+    // Note that we are discarding most of the fields
+    pub fn parse(&mut self, raw_event: &RawEvent) -> Option<(TraceEvent, Option<TraceEvent>)> {
+        let raw_type = match Some(raw_event.id) {
+            id if id == self.ids.sched_switch_id => TraceEventTypeRaw::Switch,
+            id if id == self.ids.sched_wakeup_id => TraceEventTypeRaw::Wakeup,
+            id if id == self.ids.sched_wakeup_new_id => TraceEventTypeRaw::Wakeup,
+            id if id == self.ids.sched_process_exit_id => TraceEventTypeRaw::Exit,
+            id if id == self.ids.sched_migrate_task_id => TraceEventTypeRaw::Migrate,
+            _ => { panic!("Bad event id.") }
+        };
+
+        /* https://elixir.bootlin.com/linux/v5.6/source/include/trace/events/sched.h#L167 */
+        let sched_event = match raw_type {
+            TraceEventTypeRaw::Switch => SchedEvent::Switch{
+                prev_pid: raw_event.pid,
+                prev_runnable: is_preemption(raw_event),
+                next_pid: raw_event.next_pid,
+            },
+            TraceEventTypeRaw::Wakeup => SchedEvent::Wakeup{pid: raw_event.pid},
+            TraceEventTypeRaw::Exit => SchedEvent::Exit{pid: raw_event.pid},
+            TraceEventTypeRaw::Migrate => SchedEvent::Migrate{pid: raw_event.pid, dest_cpu: raw_event.dest_cpu},
+        };
+
+        self.mapper.map(sched_event, Time::from_ns(raw_event.ts), raw_event.cpu)
+    }
+}
+
+// If the bitmask for process states is changed, this will break
+/* https://elixir.bootlin.com/linux/v5.6/source/include/linux/sched.h#L76 */
+fn is_preemption(raw_event: &RawEvent) -> bool {
+    // The *current* state of the previous process is "Runnable"
+    raw_event.prev_state == 0 || raw_event.prev_state == 256
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_tracepoints() {
+        assert_eq!(
+            EventSet::default().tracepoints(),
+            [SCHED_WAKEUP, SCHED_WAKEUP_NEW, SCHED_SWITCH, SCHED_PROCESS_EXIT]
+        );
+    }
+
+    #[test]
+    fn arrivals_only() {
+        let events = EventSet::arrivals_only();
+
+        assert_eq!(events.tracepoints(), [SCHED_WAKEUP, SCHED_WAKEUP_NEW]);
+        assert!(!events.contains(SCHED_SWITCH));
+    }
+
+    #[test]
+    fn all_tracepoints() {
+        let events = EventSet::all();
+
+        assert_eq!(events.tracepoints(), [SCHED_WAKEUP, SCHED_WAKEUP_NEW, SCHED_SWITCH, SCHED_PROCESS_EXIT]);
+        assert!(!events.contains(SCHED_MIGRATE_TASK));
+    }
+
+    #[test]
+    fn switch_carries_cpu() {
+        let mut parser = EventParser::new(ids());
+        let raw_event = RawEvent { id: 1, ts: 100, cpu: Some(2), pid: 10, next_pid: 20, prev_state: 1, ..Default::default() };
+
+        let (event, extra_event) = parser.parse(&raw_event).unwrap();
+
+        assert_eq!(event, TraceEvent::deactivation(10, Time::from_ns(100)).with_cpu(2));
+        assert_eq!(extra_event, Some(TraceEvent::dispatch(20, Time::from_ns(100)).with_cpu(2)));
+    }
+
+    #[test]
+    fn migration_updates_cpu() {
+        let mut parser = EventParser::new(ids());
+        let migrate = RawEvent { id: 5, ts: 100, cpu: Some(0), pid: 10, dest_cpu: Some(3), ..Default::default() };
+        let wakeup = RawEvent { id: 2, ts: 200, cpu: Some(0), pid: 10, ..Default::default() };
+
+        assert!(parser.parse(&migrate).is_none());
+        let (event, extra_event) = parser.parse(&wakeup).unwrap();
+
+        assert_eq!(event, TraceEvent::activation(10, Time::from_ns(200)).with_cpu(3));
+        assert_eq!(extra_event, None);
+    }
+
+    #[test]
+    fn unknown_cpu() {
+        let mut parser = EventParser::new(ids());
+        let raw_event = RawEvent { id: 1, ts: 100, pid: 10, next_pid: 20, prev_state: 0, ..Default::default() };
+
+        let (event, extra_event) = parser.parse(&raw_event).unwrap();
+
+        assert_eq!(event, TraceEvent::preemption(10, Time::from_ns(100)));
+        assert_eq!(extra_event, Some(TraceEvent::dispatch(20, Time::from_ns(100))));
+    }
+
+    /* Support */
+
+    fn ids() -> EventsId {
+        EventsId {
+            sched_switch_id: Some(1),
+            sched_wakeup_id: Some(2),
+            sched_wakeup_new_id: Some(3),
+            sched_process_exit_id: Some(4),
+            sched_migrate_task_id: Some(5),
+        }
+    }
+}
@@ -54,12 +54,13 @@ pub fn start_tracing(tracefs: *mut tracefs_instance) {
     }
 }
 
-pub fn set_events(tracefs: *mut tracefs_instance) {
-    unsafe {
-        tracefs_instance_file_write(tracefs, c_str("events/sched/sched_wakeup/enable").as_ptr(), c_str("1").as_ptr());
-        tracefs_instance_file_write(tracefs, c_str("events/sched/sched_wakeup_new/enable").as_ptr(), c_str("1").as_ptr());
-        tracefs_instance_file_write(tracefs, c_str("events/sched/sched_switch/enable").as_ptr(), c_str("1").as_ptr());
-        tracefs_instance_file_write(tracefs, c_str("events/sched/sched_process_exit/enable").as_ptr(), c_str("1").as_ptr());
+pub fn set_events(tracefs: *mut tracefs_instance, events: &[&str]) {
+    for event in events {
+        let path = format!("events/sched/{}/enable", event);
+
+        unsafe {
+            tracefs_instance_file_write(tracefs, c_str(&path).as_ptr(), c_str("1").as_ptr());
+        }
     }
 }
 
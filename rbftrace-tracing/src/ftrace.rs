@@ -4,10 +4,10 @@ use sysinfo::{System, SystemExt};
 use std::convert::TryInto;
 
 use rbftrace_core::trace::*;
-use rbftrace_core::time::*;
 
 /* C wrappers */
 use crate::ffi::trace_cmd;
+use crate::events::*;
 
 pub struct FTraceEVG {
     /// The set of traced pids. Typically every real-time thread in the system
@@ -40,9 +40,11 @@ pub struct FTraceEVG {
     duration: u64, // In seconds
     /// Size of the ftrace ring buffer in kb
     ftrace_bufsize: u32,
+    /// Tracepoints to enable
+    events: EventSet,
 
     /* Needed to parse the events */
-    ids: EventsId,
+    parser: EventParser,
 
     /* Needed by the C functions to read the stream. There is no reason to ever touch these. */
     tracefs: *mut trace_cmd::tracefs_instance,
@@ -111,6 +113,11 @@ impl FTraceEVG {
         None
     }
     
+    /// Clock of the event timestamps, `setup` selects the monotonic clock
+    pub fn clock(&self) -> Option<ClockSource> {
+        ClockSource::from_ftrace_name(&trace_cmd::get_clock(self.tracefs))
+    }
+
     pub fn setup(&mut self) {
         /*** Clean ***/
         trace_cmd::stop_tracing(self.tracefs);
@@ -121,7 +128,7 @@ impl FTraceEVG {
         /* Child processes are traced too + on every pid tracing stops if the process exits */
         trace_cmd::set_event_fork(self.tracefs);
         trace_cmd::set_pids(self.tracefs, &self.rt_pids);
-        trace_cmd::set_events(self.tracefs);
+        trace_cmd::set_events(self.tracefs, &self.events.tracepoints());
         trace_cmd::set_buffer_size(self.tracefs, self.ftrace_bufsize);
 
         /*** Activate tracing ***/
@@ -144,12 +151,12 @@ impl FTraceEVG {
         trace_cmd::clear_event_fork(self.tracefs);
         trace_cmd::destroy_tracefs(self.tracefs);
 
-        println!("TRACING: Done! Processed events: {} Total events: {}", self.processed_events, self.processed_events_all);
+        log::info!("TRACING: Done! Processed events: {} Total events: {}", self.processed_events, self.processed_events_all);
     }
 }
 
 impl FTraceEVG {
-    pub fn new(target_pids: &[Pid], rt_pids: &[Pid], duration: u64, bufsize: u32) -> Self {
+    pub fn new(target_pids: &[Pid], rt_pids: &[Pid], duration: u64, bufsize: u32, events: EventSet) -> Self {
         let s = System::new();
         let cpu_cnt: i32 = s.processors().len().try_into().unwrap();
         let tracefs = trace_cmd::create_tracefs();
@@ -167,8 +174,9 @@ impl FTraceEVG {
             
             duration,
             ftrace_bufsize: bufsize,
+            events,
 
-            ids: EventsId::from_tracefs(tracefs),
+            parser: EventParser::new(EventsId::from_tracefs(tracefs, &events)),
 
             tracefs,
             recorders,
@@ -177,10 +185,13 @@ impl FTraceEVG {
     }
 
     fn read_stream_parse(&mut self) -> Option<TraceEvent> {
-        if let Some(raw_event) = trace_cmd::read_stream_raw(self.recorders, self.cpu_cnt) {
-            let trace_event = Some(self.event_from_raw(&raw_event));
+        /* Some raw events (migrations) do not produce a TraceEvent, in that case read another */
+        while let Some(raw_event) = trace_cmd::read_stream_raw(self.recorders, self.cpu_cnt) {
+            if let Some((trace_event, extra_event)) = self.parser.parse(&RawEvent::from(&raw_event)) {
+                self.extra_event = extra_event;
 
-            return trace_event;
+                return Some(trace_event);
+            }
         }
 
         None
@@ -207,85 +218,46 @@ impl FTraceEVG {
 
         true
     }
-
-    /* Parse raw events */
-    // Not that we are discarding most of the fields
-    fn event_from_raw(&mut self, raw_event: &trace_cmd::rbftrace_event_raw) -> TraceEvent {
-        let raw_type = match raw_event.id {
-            id if id == self.ids.sched_switch_id => TraceEventTypeRaw::Switch,
-            id if id == self.ids.sched_wakeup_id => TraceEventTypeRaw::Wakeup,
-            id if id == self.ids.sched_wakeup_new_id => TraceEventTypeRaw::Wakeup,
-            id if id == self.ids.sched_process_exit_id => TraceEventTypeRaw::Exit,
-            _ => { panic!("Bad event id.") }
-        };
-
-        /* https://elixir.bootlin.com/linux/v5.6/source/include/trace/events/sched.h#L167 */
-        match raw_type {
-            TraceEventTypeRaw::Switch => {
-                let prev_pid = raw_event.pid as u32;
-                let next_pid = raw_event.next_pid as u32;
-                let event_type: TraceEventType;
-
-                // Either Preemption or Deactivation
-                if is_preemption(raw_event) {
-                    event_type = TraceEventType::Preemption;
-                } else {
-                    event_type = TraceEventType::Deactivation;
-                }
-                let event_1 = TraceEvent::new(event_type, prev_pid, Time::from_ns(raw_event.ts));
-
-                // Dispatch
-                let event_2 = TraceEvent::new(TraceEventType::Dispatch, next_pid, Time::from_ns(raw_event.ts));
-
-                self.extra_event = Some(event_2);
-                event_1
-            },
-            TraceEventTypeRaw::Wakeup => {
-                TraceEvent::new(TraceEventType::Activation, raw_event.pid as u32, Time::from_ns(raw_event.ts))
-            },
-            TraceEventTypeRaw::Exit => {
-                TraceEvent::new(TraceEventType::Exit, raw_event.pid as u32, Time::from_ns(raw_event.ts))
-            },
-        }
-    }
 }
 
 /* SUPPORT */
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-pub enum TraceEventTypeRaw {
-    Switch,
-    Wakeup,
-    Exit,
-}
-
-/* These ids are machine-dependent, so we read them from tracefs. */
-#[derive(Debug, Copy, Clone)]
-pub struct EventsId {
-    sched_switch_id: u16,
-    sched_wakeup_id: u16,
-    sched_wakeup_new_id: u16,
-    sched_process_exit_id: u16,
+impl From<&trace_cmd::rbftrace_event_raw> for RawEvent {
+    fn from(raw_event: &trace_cmd::rbftrace_event_raw) -> Self {
+        RawEvent {
+            id: raw_event.id,
+            ts: raw_event.ts,
+            // The raw record of trace-rbftrace.c carries neither the recording CPU nor the destination of a migration,
+            // so live traces have no per-CPU data (see EventSet)
+            cpu: None,
+            pid: raw_event.pid as Pid,
+            next_pid: raw_event.next_pid as Pid,
+            prev_state: raw_event.prev_state as i64,
+            dest_cpu: None,
+        }
+    }
 }
 
 impl EventsId {
-    pub fn from_tracefs(tracefs: *mut trace_cmd::tracefs_instance) -> Self {
+    pub fn from_tracefs(tracefs: *mut trace_cmd::tracefs_instance, events: &EventSet) -> Self {
+        let id_of = |tracepoint: &str| {
+            if events.contains(tracepoint) {
+                Some(trace_cmd::get_event_id(tracefs, tracepoint))
+            } else {
+                None
+            }
+        };
+
         EventsId {
-            sched_switch_id: trace_cmd::get_event_id(tracefs, "sched_switch"),
-            sched_wakeup_id: trace_cmd::get_event_id(tracefs, "sched_wakeup"),
-            sched_wakeup_new_id: trace_cmd::get_event_id(tracefs, "sched_wakeup_new"),
-            sched_process_exit_id: trace_cmd::get_event_id(tracefs, "sched_process_exit"),
+            sched_switch_id: id_of(SCHED_SWITCH),
+            sched_wakeup_id: id_of(SCHED_WAKEUP),
+            sched_wakeup_new_id: id_of(SCHED_WAKEUP_NEW),
+            sched_process_exit_id: id_of(SCHED_PROCESS_EXIT),
+            sched_migrate_task_id: id_of(SCHED_MIGRATE_TASK),
         }
     }
 }
 
-// If the bitmask for process states is changed, this will break
-/* https://elixir.bootlin.com/linux/v5.6/source/include/linux/sched.h#L76 */
-fn is_preemption(raw_event: &trace_cmd::rbftrace_event_raw) -> bool {
-    // The *current* state of the previous process is "Runnable"
-    raw_event.prev_state == 0 || raw_event.prev_state == 256
-}
-
 /* Cleanup on ctrl+C */
 // TODO this is very ugly, but rust won't let us use the EVG instance because of the presence of raw pointers
 fn sigint_handle() {
@@ -1,2 +1,5 @@
+#[cfg(feature = "live-tracing")]
 pub mod ffi;
+#[cfg(feature = "live-tracing")]
 pub mod ftrace;
+pub mod events;